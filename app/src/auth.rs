@@ -0,0 +1,265 @@
+#[cfg(feature = "ssr")]
+mod attempts;
+#[cfg(feature = "ssr")]
+mod sessions;
+#[cfg(feature = "ssr")]
+pub mod users;
+
+cfg_if! { if #[cfg(feature = "ssr")] {
+    use std::{
+        fmt,
+        net::{IpAddr, SocketAddr},
+        path::Path,
+        sync::Arc,
+    };
+
+    use attempts::Attempts;
+    use axum::extract::ConnectInfo;
+    use base64::{Engine as _, engine::general_purpose::STANDARD as BASE64};
+    use http::{HeaderMap, HeaderValue, header, request::Parts};
+    use leptos::logging::warn;
+    use leptos_axum::{ResponseOptions, redirect};
+
+    pub use attempts::LOCKOUT;
+    use sessions::{SESSION_TTL, Sessions};
+    use users::{Users, UsersError};
+}}
+
+use cfg_if::cfg_if;
+use leptos::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{error_template::AppError, permissions::Role};
+
+pub const SESSION_COOKIE: &str = "file_share_session";
+
+/// A logged-in user.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct User {
+    pub name: String,
+    /// Role from the users file, users without one fall back to the global
+    /// upload setting
+    pub role: Option<Role>,
+}
+
+/// The user of the current request, if authentication is enabled and the
+/// request carries a valid session.
+#[cfg(feature = "ssr")]
+pub fn current_user() -> Option<User> {
+    use_context::<Parts>().and_then(|parts| parts.extensions.get::<User>().cloned())
+}
+
+/// The user of the current request, if authentication is enabled and the
+/// request carries a valid session.
+#[cfg(not(feature = "ssr"))]
+pub fn current_user() -> Option<User> {
+    None
+}
+
+/// User accounts and their sessions.
+#[cfg(feature = "ssr")]
+#[derive(Clone)]
+pub struct Auth {
+    users: Arc<Users>,
+    sessions: Arc<Sessions>,
+    attempts: Arc<Attempts>,
+    /// Whether the server is only reachable over HTTPS, so that the session
+    /// cookie is never sent without it
+    secure_cookies: bool,
+}
+
+#[cfg(feature = "ssr")]
+impl fmt::Debug for Auth {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Auth")
+            .field("users", &self.users.len())
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(feature = "ssr")]
+impl Auth {
+    /// Load the accounts from a users file. With `secure_cookies`, the
+    /// session cookie is only sent over HTTPS.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file can't be read or contains an invalid line.
+    pub fn load(path: &Path, secure_cookies: bool) -> Result<Self, UsersError> {
+        Ok(Self {
+            users: Arc::new(Users::load(path)?),
+            sessions: Arc::default(),
+            attempts: Arc::default(),
+            secure_cookies,
+        })
+    }
+
+    pub fn user_count(&self) -> usize {
+        self.users.len()
+    }
+
+    /// Whether `client` failed to log in too often and has to wait for
+    /// [`LOCKOUT`] before its credentials are checked again.
+    pub async fn throttled(&self, client: Option<IpAddr>) -> bool {
+        match client {
+            Some(client) => self.attempts.throttled(client).await,
+            None => false,
+        }
+    }
+
+    /// Check the credentials of `client`, unless it's throttled.
+    async fn verify(&self, username: &str, password: &str, client: Option<IpAddr>) -> Option<User> {
+        if self.throttled(client).await {
+            return None;
+        }
+
+        let user = self.users.verify(username, password).await;
+
+        if let Some(client) = client {
+            match user {
+                Some(_) => self.attempts.succeeded(client).await,
+                None => self.attempts.failed(client).await,
+            }
+        }
+
+        user
+    }
+
+    /// Check the credentials and start a new session.
+    ///
+    /// Returns the session token on success.
+    pub async fn login(
+        &self,
+        username: &str,
+        password: &str,
+        client: Option<IpAddr>,
+    ) -> Option<String> {
+        let user = self.verify(username, password, client).await?;
+
+        Some(self.sessions.create(user).await)
+    }
+
+    pub async fn logout(&self, token: &str) {
+        self.sessions.remove(token).await;
+    }
+
+    /// Find the user of a request from `client`, either from the session
+    /// cookie or from HTTP Basic credentials (for `curl` and other API
+    /// clients).
+    pub async fn authenticate(&self, headers: &HeaderMap, client: Option<IpAddr>) -> Option<User> {
+        if let Some(token) = session_token(headers) {
+            if let Some(user) = self.sessions.get(token).await {
+                return Some(user);
+            }
+        }
+
+        let (username, password) = basic_credentials(headers)?;
+
+        self.verify(&username, &password, client).await
+    }
+}
+
+/// Extract the session token from the `Cookie` headers.
+#[cfg(feature = "ssr")]
+pub fn session_token(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get_all(header::COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(';'))
+        .filter_map(|cookie| cookie.trim().split_once('='))
+        .find_map(|(name, value)| (name == SESSION_COOKIE).then_some(value))
+}
+
+#[cfg(feature = "ssr")]
+fn basic_credentials(headers: &HeaderMap) -> Option<(String, String)> {
+    let value = headers.get(header::AUTHORIZATION)?.to_str().ok()?;
+    let encoded = value.strip_prefix("Basic ")?;
+    let decoded = String::from_utf8(BASE64.decode(encoded.trim()).ok()?).ok()?;
+    let (username, password) = decoded.split_once(':')?;
+
+    Some((username.to_owned(), password.to_owned()))
+}
+
+#[cfg(feature = "ssr")]
+fn set_session_cookie(token: &str, max_age: u64, secure: bool) {
+    let mut cookie =
+        format!("{SESSION_COOKIE}={token}; Path=/; HttpOnly; SameSite=Lax; Max-Age={max_age}");
+
+    if secure {
+        cookie.push_str("; Secure");
+    }
+
+    if let Some(response) = use_context::<ResponseOptions>() {
+        response.insert_header(
+            header::SET_COOKIE,
+            HeaderValue::from_str(&cookie).expect("Session cookie is a valid header value"),
+        );
+    }
+}
+
+#[server(name = Login, prefix = "/api", endpoint = "login")]
+pub async fn login(
+    username: String,
+    password: String,
+    next: Option<String>,
+) -> Result<(), AppError> {
+    let Some(auth) = use_context::<Auth>() else {
+        return Err(AppError::Disabled("Authentication is disabled".into()));
+    };
+
+    // only allow local redirects
+    let next = next.filter(|next| next.starts_with('/') && !next.starts_with("//"));
+
+    // the next attempt still goes where the user wanted
+    let retry = |failed: &str| match &next {
+        Some(next) => redirect(&format!(
+            "/login?failed={failed}&next={}",
+            urlencoding::encode(next)
+        )),
+        None => redirect(&format!("/login?failed={failed}")),
+    };
+
+    let client = use_context::<Parts>().and_then(|parts| {
+        parts
+            .extensions
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|ConnectInfo(addr)| addr.ip().to_canonical())
+    });
+
+    if auth.throttled(client).await {
+        warn!("Refused login attempt for user '{username}', too many failed ones");
+        retry("throttled");
+        return Ok(());
+    }
+
+    let Some(token) = auth.login(&username, &password, client).await else {
+        warn!("Failed login attempt for user '{username}'");
+        retry("true");
+        return Ok(());
+    };
+
+    set_session_cookie(&token, SESSION_TTL.as_secs(), auth.secure_cookies);
+
+    redirect(next.as_deref().unwrap_or("/index"));
+
+    Ok(())
+}
+
+#[server(name = Logout, prefix = "/api", endpoint = "logout")]
+pub async fn logout() -> Result<(), AppError> {
+    let Some(auth) = use_context::<Auth>() else {
+        return Err(AppError::Disabled("Authentication is disabled".into()));
+    };
+
+    if let Some(parts) = use_context::<Parts>() {
+        if let Some(token) = session_token(&parts.headers) {
+            auth.logout(token).await;
+        }
+    }
+
+    set_session_cookie("", 0, auth.secure_cookies);
+    redirect("/login");
+
+    Ok(())
+}
@@ -0,0 +1,47 @@
+use std::{collections::HashMap, net::IpAddr, time::Duration};
+
+use tokio::{sync::Mutex, time::Instant};
+
+/// Failed logins a client may have in a row before it has to wait.
+const MAX_FAILURES: u32 = 5;
+/// How long a client has to wait after too many failed logins, and after
+/// which its failures are forgotten.
+pub const LOCKOUT: Duration = Duration::from_secs(60);
+
+struct Failures {
+    count: u32,
+    last: Instant,
+}
+
+/// Failed logins by client, so that passwords can't be guessed quickly and
+/// guessing doesn't keep the server busy hashing.
+#[derive(Default)]
+pub struct Attempts(Mutex<HashMap<IpAddr, Failures>>);
+
+impl Attempts {
+    /// Whether `client` failed too often recently and has to wait.
+    pub async fn throttled(&self, client: IpAddr) -> bool {
+        self.0.lock().await.get(&client).is_some_and(|failures| {
+            failures.count >= MAX_FAILURES && failures.last.elapsed() < LOCKOUT
+        })
+    }
+
+    pub async fn failed(&self, client: IpAddr) {
+        let now = Instant::now();
+        let mut attempts = self.0.lock().await;
+
+        // piggyback the cleanup of old failures on new ones
+        attempts.retain(|_, failures| now.duration_since(failures.last) < LOCKOUT);
+
+        let failures = attempts.entry(client).or_insert(Failures {
+            count: 0,
+            last: now,
+        });
+        failures.count += 1;
+        failures.last = now;
+    }
+
+    pub async fn succeeded(&self, client: IpAddr) {
+        self.0.lock().await.remove(&client);
+    }
+}
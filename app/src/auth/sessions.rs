@@ -0,0 +1,61 @@
+use std::{collections::HashMap, fmt::Write as _, time::Duration};
+
+use rand_core::{OsRng, RngCore as _};
+use tokio::{sync::Mutex, time::Instant};
+
+use super::User;
+
+pub const SESSION_TTL: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+struct Session {
+    user: User,
+    expires: Instant,
+}
+
+#[derive(Default)]
+pub struct Sessions(Mutex<HashMap<String, Session>>);
+
+impl Sessions {
+    /// Start a new session and return its token.
+    pub async fn create(&self, user: User) -> String {
+        let mut bytes = [0u8; 32];
+        OsRng.fill_bytes(&mut bytes);
+
+        let token = bytes.iter().fold(String::new(), |mut token, byte| {
+            let _ = write!(token, "{byte:02x}");
+            token
+        });
+
+        let now = Instant::now();
+        let mut sessions = self.0.lock().await;
+
+        // piggyback the cleanup of expired sessions on logins
+        sessions.retain(|_, session| session.expires > now);
+        sessions.insert(
+            token.clone(),
+            Session {
+                user,
+                expires: now + SESSION_TTL,
+            },
+        );
+
+        token
+    }
+
+    pub async fn get(&self, token: &str) -> Option<User> {
+        let mut sessions = self.0.lock().await;
+
+        match sessions.get(token) {
+            Some(session) if session.expires > Instant::now() => Some(session.user.clone()),
+            Some(_) => {
+                sessions.remove(token);
+                None
+            },
+            None => None,
+        }
+    }
+
+    pub async fn remove(&self, token: &str) {
+        self.0.lock().await.remove(token);
+    }
+}
@@ -0,0 +1,186 @@
+use std::{collections::HashMap, io, path::Path};
+
+use argon2::{
+    Argon2, PasswordHash, PasswordHasher as _, PasswordVerifier as _,
+    password_hash::{self, SaltString},
+};
+use rand_core::OsRng;
+use thiserror::Error;
+
+use super::User;
+use crate::permissions::Role;
+
+#[derive(Debug, Error)]
+pub enum UsersError {
+    #[error("Failed to read the users file\ncaused by: {0}")]
+    Io(#[from] io::Error),
+
+    #[error("Invalid line {0} in the users file, expected `username[:role]:password-hash`")]
+    InvalidLine(usize),
+
+    #[error("Invalid role for user '{1}' on line {0}\ncaused by: {2}")]
+    InvalidRole(usize, String, String),
+
+    #[error("Invalid password hash for user '{1}' on line {0}\ncaused by: {2}")]
+    InvalidHash(usize, String, password_hash::Error),
+}
+
+struct Account {
+    role: Option<Role>,
+    hash: String,
+}
+
+/// Accounts loaded from a users file.
+///
+/// Each line has the form `username:password-hash` or
+/// `username:role:password-hash`, where the hash is an Argon2 PHC string as
+/// printed by `file-share --hash-password` and the role is one of
+/// `read-only`, `uploader` or `admin`. Empty lines and lines starting with `#`
+/// are ignored.
+pub struct Users(HashMap<String, Account>);
+
+impl Users {
+    /// Read and parse a users file.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file can't be read or contains an invalid line.
+    pub fn load(path: &Path) -> Result<Self, UsersError> {
+        Self::parse(&std::fs::read_to_string(path)?)
+    }
+
+    /// Parse the content of a users file.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if any line is malformed or has an invalid hash.
+    pub fn parse(content: &str) -> Result<Self, UsersError> {
+        let mut users = HashMap::new();
+
+        for (index, line) in content.lines().enumerate() {
+            let line = line.trim();
+
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let (username, role, hash) = match line.splitn(3, ':').collect::<Vec<_>>()[..] {
+                [username, hash] => (username, None, hash),
+                [username, role, hash] => match role.parse() {
+                    Ok(role) => (username, Some(role), hash),
+                    Err(e) => {
+                        return Err(UsersError::InvalidRole(index + 1, username.to_owned(), e));
+                    },
+                },
+                _ => return Err(UsersError::InvalidLine(index + 1)),
+            };
+
+            if let Err(e) = PasswordHash::new(hash) {
+                return Err(UsersError::InvalidHash(index + 1, username.to_owned(), e));
+            }
+
+            users.insert(
+                username.to_owned(),
+                Account {
+                    role,
+                    hash: hash.to_owned(),
+                },
+            );
+        }
+
+        Ok(Self(users))
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Check a password against the stored hash and return the matching
+    /// user.
+    pub async fn verify(&self, username: &str, password: &str) -> Option<User> {
+        let account = self.0.get(username)?;
+
+        verify_password(&account.hash, password).await.then(|| User {
+            name: username.to_owned(),
+            role: account.role,
+        })
+    }
+}
+
+/// Check `password` against a hash made by [`hash_password`].
+///
+/// Hashing is deliberately slow, so it runs on the blocking thread pool.
+pub async fn verify_password(hash: &str, password: &str) -> bool {
+    let hash = hash.to_owned();
+    let password = password.to_owned();
+
+    tokio::task::spawn_blocking(move || {
+        PasswordHash::new(&hash).is_ok_and(|hash| {
+            Argon2::default()
+                .verify_password(password.as_bytes(), &hash)
+                .is_ok()
+        })
+    })
+    .await
+    .unwrap_or(false)
+}
+
+/// Hash a password for use in the users file.
+///
+/// # Errors
+///
+/// Returns an error if the password can't be hashed (e.g. it's too long).
+pub fn hash_password(password: &str) -> Result<String, password_hash::Error> {
+    let salt = SaltString::generate(&mut OsRng);
+
+    Ok(Argon2::default()
+        .hash_password(password.as_bytes(), &salt)?
+        .to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    pub async fn test_users() {
+        let hash = hash_password("hunter2").unwrap();
+        let users =
+            Users::parse(&format!("# comment\n\nalice:{hash}\nbob:admin:{hash}\n")).unwrap();
+
+        assert_eq!(users.len(), 2);
+        assert_eq!(
+            users.verify("alice", "hunter2").await,
+            Some(User {
+                name: "alice".into(),
+                role: None
+            })
+        );
+        assert_eq!(
+            users
+                .verify("bob", "hunter2")
+                .await
+                .and_then(|user| user.role),
+            Some(Role::Admin)
+        );
+        assert!(users.verify("alice", "hunter3").await.is_none());
+        assert!(users.verify("carol", "hunter2").await.is_none());
+
+        assert!(matches!(
+            Users::parse("alice"),
+            Err(UsersError::InvalidLine(1))
+        ));
+        assert!(matches!(
+            Users::parse(&format!("alice:owner:{hash}")),
+            Err(UsersError::InvalidRole(1, ..))
+        ));
+        assert!(matches!(
+            Users::parse("alice:plaintext"),
+            Err(UsersError::InvalidHash(1, ..))
+        ));
+    }
+}
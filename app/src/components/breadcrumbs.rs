@@ -4,14 +4,18 @@ use leptos::prelude::*;
 
 use crate::utils::{display_os_string, encode_path};
 
+/// Links to the current folder and all of its parents, `query` is appended
+/// to each of them.
 #[component]
-pub fn Breadcrumbs(path: Signal<PathBuf>) -> impl IntoView {
+pub fn Breadcrumbs(path: Signal<PathBuf>, #[prop(optional)] query: String) -> impl IntoView {
+    let home = format!("/index{query}");
+
     let breadcrumbs = move || {
         path.with(|path| {
             path.iter()
                 .scan(PathBuf::new(), |path, part| {
                     path.push(part);
-                    let path = format!("/index/{}", encode_path(&path));
+                    let path = format!("/index/{}{query}", encode_path(&path));
 
                     Some(view! {
                       <li>
@@ -24,7 +28,7 @@ pub fn Breadcrumbs(path: Signal<PathBuf>) -> impl IntoView {
     };
 
     let home_icon = view! {
-      <a href="/index">
+      <a href=home>
         <svg class="w-6 h-6 fill-current" viewBox="0 0 24 24">
           <path d="M12,3L20,9V21H15V14H9V21H4V9L12,3Z" />
         </svg>
@@ -0,0 +1,256 @@
+use std::path::PathBuf;
+
+use leptos::{ev::MouseEvent, prelude::*, task::spawn_local};
+
+use crate::{
+    components::shares::create_share,
+    server::{copy_entry, delete_entry, move_entry, rename_entry},
+    utils::display_os_string,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Operation {
+    Rename,
+    Move,
+    Copy,
+    Share,
+    Delete,
+}
+
+impl Operation {
+    fn title(self) -> &'static str {
+        match self {
+            Operation::Rename => "Rename",
+            Operation::Move => "Move",
+            Operation::Copy => "Copy",
+            Operation::Share => "Share",
+            Operation::Delete => "Delete",
+        }
+    }
+}
+
+/// Menu with the operations on a single entry of a listing.
+#[island]
+pub fn EntryActions(path: PathBuf, name: String) -> impl IntoView {
+    let path = StoredValue::new(path);
+    let name = StoredValue::new(name);
+
+    let operation = RwSignal::new(None::<Operation>);
+    // new name for renames, destination folder for moves and copies
+    let input = RwSignal::new(String::new());
+    let error = RwSignal::new(None::<String>);
+    let running = RwSignal::new(false);
+
+    // limits of a new share link, empty when there's none
+    let expires_hours = RwSignal::new(String::new());
+    let max_downloads = RwSignal::new(String::new());
+    let password = RwSignal::new(String::new());
+    // full URL of the created link
+    let shared_url = RwSignal::new(None::<String>);
+
+    let open = move |op: Operation| {
+        input.set(match op {
+            Operation::Rename => name.get_value(),
+            Operation::Move | Operation::Copy => path.with_value(display_os_string),
+            Operation::Share | Operation::Delete => String::new(),
+        });
+        expires_hours.set(String::new());
+        max_downloads.set(String::new());
+        password.set(String::new());
+        shared_url.set(None);
+        error.set(None);
+        operation.set(Some(op));
+    };
+
+    let share = move |path: PathBuf, name: String| {
+        let limit = |value: RwSignal<String>, what: &str| {
+            let value = value.get_untracked();
+            let value = value.trim();
+
+            if value.is_empty() {
+                Ok(None)
+            } else {
+                value
+                    .parse::<u64>()
+                    .map(Some)
+                    .map_err(|_| format!("Invalid {what}: '{value}'"))
+            }
+        };
+
+        let limits = limit(expires_hours, "expiry time")
+            .and_then(|hours| Ok((hours, limit(max_downloads, "download count")?)));
+
+        let (expires_hours, max_downloads) = match limits {
+            Ok(limits) => limits,
+            Err(e) => {
+                error.set(Some(e));
+                return;
+            },
+        };
+
+        let password = Some(password.get_untracked()).filter(|password| !password.is_empty());
+
+        running.set(true);
+
+        spawn_local(async move {
+            let result = create_share(path, name, expires_hours, max_downloads, password).await;
+
+            running.set(false);
+
+            match result {
+                Ok(link) => {
+                    let origin = window().location().origin().unwrap_or_default();
+                    error.set(None);
+                    shared_url.set(Some(format!("{origin}{}", link.href())));
+                },
+                Err(e) => error.set(Some(e.to_string())),
+            }
+        });
+    };
+
+    let confirm = move |_: MouseEvent| {
+        let Some(op) = operation.get_untracked() else {
+            return;
+        };
+
+        let path = path.get_value();
+        let name = name.get_value();
+        let input = input.get_untracked();
+
+        if op == Operation::Share {
+            share(path, name);
+            return;
+        }
+
+        running.set(true);
+
+        spawn_local(async move {
+            let result = match op {
+                Operation::Rename => rename_entry(path, name, input).await,
+                Operation::Move => move_entry(path, name, input.into()).await,
+                Operation::Copy => copy_entry(path, name, input.into()).await,
+                Operation::Delete => delete_entry(path, name).await,
+                Operation::Share => unreachable!("shares are created by share()"),
+            };
+
+            running.set(false);
+
+            match result {
+                Ok(()) => _ = window().location().reload(),
+                Err(e) => error.set(Some(e.to_string())),
+            }
+        });
+    };
+
+    let dialog = move || {
+        operation.get().map(|op| {
+            let field = match op {
+                Operation::Rename => Some("New name"),
+                Operation::Move | Operation::Copy => Some("Destination folder"),
+                Operation::Share | Operation::Delete => None,
+            }
+            .map(|label| {
+                view! {
+                  <label class="w-full floating-label">
+                    <span>{label}</span>
+                    <input class="w-full input" type="text" bind:value=input autofocus />
+                  </label>
+                }
+            });
+
+            let question = (op == Operation::Delete).then(|| {
+                view! {
+                  <p>
+                    "Move " <span class="font-bold break-all">{name.get_value()}</span>
+                    " to the trash?"
+                  </p>
+                }
+            });
+
+            let share_fields = (op == Operation::Share).then(|| {
+                view! {
+                  <div class="flex flex-col gap-4">
+                    <label class="w-full floating-label">
+                      <span>Expires after (hours, optional)</span>
+                      <input class="w-full input" type="number" min="1" bind:value=expires_hours />
+                    </label>
+                    <label class="w-full floating-label">
+                      <span>Maximum downloads (optional)</span>
+                      <input class="w-full input" type="number" min="1" bind:value=max_downloads />
+                    </label>
+                    <label class="w-full floating-label">
+                      <span>Password (optional)</span>
+                      <input class="w-full input" type="password" bind:value=password />
+                    </label>
+                    {move || {
+                      shared_url
+                        .get()
+                        .map(|url| {
+                          view! {
+                            <label class="w-full floating-label">
+                              <span>Link</span>
+                              <input class="w-full input" type="text" readonly value=url />
+                            </label>
+                          }
+                        })
+                    }}
+                  </div>
+                }
+            });
+
+            view! {
+              <div class="modal modal-open">
+                <div class="modal-box">
+                  <h3 class="mb-4 text-lg font-bold">{op.title()}</h3>
+                  {field}
+                  {share_fields}
+                  {question}
+                  <p class="text-error">{move || error.get()}</p>
+                  <div class="modal-action">
+                    <button class="btn" on:click=move |_| operation.set(None)>
+                      {move || if shared_url.with(Option::is_some) { "Close" } else { "Cancel" }}
+                    </button>
+                    <button
+                      class="btn"
+                      class:btn-primary=op != Operation::Delete
+                      class:btn-error=op == Operation::Delete
+                      disabled=move || running.get() || shared_url.with(Option::is_some)
+                      on:click=confirm
+                    >
+                      {op.title()}
+                    </button>
+                  </div>
+                </div>
+              </div>
+            }
+        })
+    };
+
+    view! {
+      <div class="dropdown dropdown-end">
+        <div tabindex="0" role="button" class="btn btn-ghost btn-sm" title="Actions">
+          "⋮"
+        </div>
+        <ul tabindex="0" class="p-2 w-32 shadow-sm dropdown-content menu bg-base-100 rounded-box z-1">
+          <li>
+            <button on:click=move |_| open(Operation::Rename)>Rename</button>
+          </li>
+          <li>
+            <button on:click=move |_| open(Operation::Move)>Move</button>
+          </li>
+          <li>
+            <button on:click=move |_| open(Operation::Copy)>Copy</button>
+          </li>
+          <li>
+            <button on:click=move |_| open(Operation::Share)>Share</button>
+          </li>
+          <li>
+            <button class="text-error" on:click=move |_| open(Operation::Delete)>
+              Delete
+            </button>
+          </li>
+        </ul>
+      </div>
+      {dialog}
+    }
+}
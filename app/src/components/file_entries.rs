@@ -1,14 +1,26 @@
+mod gallery;
 mod icon;
+mod sorting;
 
-use std::path::PathBuf;
+use std::{ffi::OsString, path::PathBuf};
 
+use gallery::GalleryEntries;
 use icon::Icon;
-use leptos::{either::Either, prelude::*};
+use leptos::{
+    either::{Either, EitherOf3},
+    prelude::*,
+};
 use leptos_router::components::A;
+pub use sorting::{SortKey, SortOrder, Sorting, ViewMode};
 
 use crate::{
+    components::{EntryActions, SELECTION_FORM},
+    permissions::current_access,
+    preview::preview_kind,
     server::{Entries, ServerEntry},
-    utils::{format_bytes, format_file_href, format_folder_href},
+    utils::{
+        display_os_string, format_bytes, format_file_href, format_folder_href, format_preview_href,
+    },
 };
 
 #[derive(Debug, Clone, Copy, PartialEq, PartialOrd, Ord, Eq)]
@@ -21,68 +33,194 @@ pub enum EntryType {
 fn EntryComponent(
     type_: EntryType,
     href: String,
-    name: String,
+    path: PathBuf,
+    name: OsString,
     size: Option<String>,
     relative_time: String,
+    /// Whether the link downloads the file instead of opening it
+    #[prop(optional)]
+    download: bool,
 ) -> impl IntoView {
+    // selecting and changing entries goes through forms and server functions
+    // that only take UTF-8, other names can only be opened and downloaded
+    let exact_name = name
+        .to_str()
+        .filter(|_| path.to_str().is_some())
+        .map(ToOwned::to_owned);
+    let name = display_os_string(&name);
+
     let inner = view! {
       <div class="grid gap-2 w-full entry grid-cols-(--entry-cols-mobile) md:grid-cols-(--entry-cols)">
         <Icon type_=type_ name=name.clone() />
-        <span class="flex overflow-x-hidden items-center">{name}</span>
+        <span class="flex overflow-x-hidden items-center">{name.clone()}</span>
         <span class="flex justify-end items-center">{size}</span>
         <span class="hidden items-center md:flex">{relative_time}</span>
       </div>
     };
 
-    if type_ == EntryType::Folder {
+    let link = if type_ == EntryType::Folder {
         Either::Left(view! {
-          <A href=href exact=true>
+          <A href=href exact=true attr:class="grow">
             {inner}
           </A>
         })
     } else {
         Either::Right(view! {
-          <A href=href attr:download>
+          <A href=href attr:download=download attr:class="grow">
             {inner}
           </A>
         })
+    };
+
+    let checkbox = exact_name.clone().map(|name| {
+        view! {
+          <input
+            type="checkbox"
+            class="checkbox checkbox-sm"
+            name="name"
+            value=name
+            form=SELECTION_FORM
+            aria-label="Select"
+          />
+        }
+    });
+
+    let actions = exact_name
+        .filter(|name| current_access(&path.join(name)).can_write())
+        .map(|name| view! { <EntryActions path=path name=name /> });
+
+    view! {
+      <div class="flex items-center">
+        <div class="flex justify-center w-8 shrink-0">{checkbox}</div>
+        {link}
+        <div class="flex justify-center w-10 shrink-0">{actions}</div>
+      </div>
     }
 }
 
+/// Column headers linking to the listing sorted by them, plus a toggle for
+/// keeping folders first.
 #[component]
-pub fn FileEntries(path: Signal<PathBuf>, mut entries: Entries) -> impl IntoView {
+pub fn EntriesHeader(sorting: Sorting) -> impl IntoView {
+    // an empty href would keep the current query
+    let href = |sorting: Sorting| match sorting.to_query() {
+        query if query.is_empty() => "?".to_string(),
+        query => query,
+    };
+
+    let column = move |key: SortKey, label: &'static str| {
+        let arrow = (sorting.key == key).then_some(match sorting.order {
+            SortOrder::Ascending => " ▲",
+            SortOrder::Descending => " ▼",
+        });
+
+        view! {
+          <a class="link link-hover" href=href(sorting.toggled(key))>
+            {label}
+            {arrow}
+          </a>
+        }
+    };
+
+    let folders_first = sorting.folders_first;
+    let folders_first_href = href(sorting.with_folders_first(!folders_first));
+
+    let view_mode = move |view: ViewMode, label: &'static str| {
+        let class = if sorting.view == view {
+            "join-item btn btn-xs btn-active"
+        } else {
+            "join-item btn btn-xs"
+        };
+
+        view! {
+          <a class=class href=href(sorting.with_view(view))>
+            {label}
+          </a>
+        }
+    };
+
+    // the checkbox only shows the state, clicks go to the link around it
+    view! {
+      <div class="flex gap-2 justify-end">
+        <div class="join">
+          {view_mode(ViewMode::List, "List")}
+          {view_mode(ViewMode::Gallery, "Gallery")}
+        </div>
+        <a class="btn btn-ghost btn-xs" href=folders_first_href>
+          <input
+            type="checkbox"
+            class="pointer-events-none checkbox checkbox-xs"
+            tabindex="-1"
+            checked=folders_first
+          />
+          "Folders first"
+        </a>
+      </div>
+      <div class="flex mb-1 border-b border-base-content">
+        <span class="w-8 shrink-0"></span>
+        <div class="grid gap-2 grow grid-cols-(--entry-cols-mobile) md:grid-cols-(--entry-cols)">
+          <span></span>
+          {column(SortKey::Name, "Name")}
+          <span class="flex justify-end">{column(SortKey::Size, "Size")}</span>
+          <span class="hidden md:inline">{column(SortKey::Modified, "Last Modified")}</span>
+        </div>
+        <span class="w-10 shrink-0"></span>
+      </div>
+    }
+}
+
+#[component]
+pub fn FileEntries(path: Signal<PathBuf>, mut entries: Entries, sorting: Sorting) -> impl IntoView {
     if entries.is_empty() {
-        return Either::Left(view! { <div class="file-view">"The folder is empty"</div> });
+        return EitherOf3::A(view! { <div class="file-view">"The folder is empty"</div> });
     }
-    entries.sort_unstable();
+    sorting.sort(&mut entries);
 
     let path = path.get_untracked();
+    // folder links keep the sorting
+    let query = sorting.to_query();
 
-    Either::Right(view! {
+    if sorting.view == ViewMode::Gallery {
+        return EitherOf3::B(view! { <GalleryEntries path=path entries=entries query=query /> });
+    }
+
+    EitherOf3::C(view! {
       <div class="file-view">
         {entries
           .into_iter()
-          .map(|entry| match entry {
-            ServerEntry::File { name, size, last_modified } => {
-              view! {
-                <EntryComponent
-                  type_=EntryType::File
-                  href=format_file_href(&path, &name)
-                  name=name
-                  size=Some(format_bytes(size))
-                  relative_time=last_modified.humanize()
-                />
+          .map(|entry| {
+            let name = entry.os_name();
+            match entry {
+              ServerEntry::File { size, last_modified, .. } => {
+                let previewable = preview_kind(&name).is_some();
+                let href = if previewable {
+                  format_preview_href(&path, &name)
+                } else {
+                  format_file_href(&path, &name)
+                };
+                view! {
+                  <EntryComponent
+                    type_=EntryType::File
+                    href=href
+                    path=path.clone()
+                    name=name
+                    size=Some(format_bytes(size))
+                    relative_time=last_modified.humanize()
+                    download=!previewable
+                  />
+                }
               }
-            }
-            ServerEntry::Folder { name, last_modified } => {
-              view! {
-                <EntryComponent
-                  type_=EntryType::Folder
-                  href=format_folder_href(&path, &name)
-                  name=name
-                  size=None
-                  relative_time=last_modified.humanize()
-                />
+              ServerEntry::Folder { last_modified, .. } => {
+                view! {
+                  <EntryComponent
+                    type_=EntryType::Folder
+                    href=format!("{}{query}", format_folder_href(&path, &name))
+                    path=path.clone()
+                    name=name
+                    size=None
+                    relative_time=last_modified.humanize()
+                  />
+                }
               }
             }
           })
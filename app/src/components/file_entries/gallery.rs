@@ -0,0 +1,227 @@
+use std::{
+    ffi::OsStr,
+    path::{Path, PathBuf},
+};
+
+use leptos::{
+    either::EitherOf3,
+    ev::{self, MouseEvent},
+    prelude::*,
+};
+use serde::{Deserialize, Serialize};
+use web_sys::Element;
+
+use super::{EntryType, icon::Icon};
+use crate::{
+    preview::preview_kind,
+    server::{Entries, ServerEntry},
+    utils::{
+        display_os_string, format_file_href, format_folder_href, format_preview_href,
+        format_thumbnail_href,
+    },
+};
+
+/// Extensions of the images the server makes thumbnails of.
+const THUMBNAIL_EXTENSIONS: [&str; 6] = ["gif", "jfif", "jpeg", "jpg", "png", "webp"];
+
+fn has_thumbnail(name: &OsStr) -> bool {
+    Path::new(name)
+        .extension()
+        .and_then(OsStr::to_str)
+        .is_some_and(|extension| {
+            THUMBNAIL_EXTENSIONS.contains(&extension.to_ascii_lowercase().as_str())
+        })
+}
+
+/// An image shown by the lightbox of a [`Gallery`].
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct GalleryImage {
+    pub name: String,
+    pub href: String,
+}
+
+/// Opens `images` full-screen in a lightbox when one of the links to them in
+/// `children` is clicked, those are marked with `data-gallery-index`.
+///
+/// Without JS, the links just open the images.
+#[island]
+fn Gallery(images: Vec<GalleryImage>, children: Children) -> impl IntoView {
+    let images = StoredValue::new(images);
+    let count = images.with_value(Vec::len);
+    let open = RwSignal::new(None::<usize>);
+
+    let step = move |forward: bool| {
+        open.update(|open| {
+            if let Some(index) = open {
+                *index = if forward {
+                    (*index + 1) % count
+                } else {
+                    (*index + count - 1) % count
+                };
+            }
+        });
+    };
+
+    let on_click = move |ev: MouseEvent| {
+        // opening in a new tab and the like is left to the browser
+        if ev.ctrl_key() || ev.meta_key() || ev.shift_key() || ev.alt_key() {
+            return;
+        }
+
+        let index = event_target::<Element>(&ev)
+            .closest("[data-gallery-index]")
+            .ok()
+            .flatten()
+            .and_then(|link| link.get_attribute("data-gallery-index"))
+            .and_then(|index| index.parse::<usize>().ok());
+
+        if let Some(index) = index {
+            ev.prevent_default();
+            open.set(Some(index));
+        }
+    };
+
+    let keys = window_event_listener(ev::keydown, move |ev| {
+        if open.get_untracked().is_none() {
+            return;
+        }
+
+        match ev.key().as_str() {
+            "Escape" => open.set(None),
+            "ArrowLeft" => step(false),
+            "ArrowRight" => step(true),
+            _ => {},
+        }
+    });
+    on_cleanup(move || keys.remove());
+
+    let lightbox = move || {
+        let image = open
+            .get()
+            .and_then(|index| images.with_value(|images| images.get(index).cloned()))?;
+
+        let navigation = (count > 1).then(|| {
+            view! {
+              <button
+                class="absolute left-2 btn btn-circle"
+                aria-label="Previous"
+                on:click=move |_| step(false)
+              >
+                "❮"
+              </button>
+              <button
+                class="absolute right-2 btn btn-circle"
+                aria-label="Next"
+                on:click=move |_| step(true)
+              >
+                "❯"
+              </button>
+            }
+        });
+
+        Some(view! {
+          <div class="flex fixed inset-0 z-50 flex-col bg-black/90" role="dialog">
+            <div class="flex gap-2 justify-between items-center p-2 text-white">
+              <span class="break-all">{image.name.clone()}</span>
+              <div class="flex gap-2 shrink-0">
+                <a class="btn btn-sm" href=image.href.clone() download>
+                  Download
+                </a>
+                <button class="btn btn-sm" aria-label="Close" on:click=move |_| open.set(None)>
+                  "✕"
+                </button>
+              </div>
+            </div>
+            <div class="flex relative justify-center items-center p-2 min-h-0 grow">
+              <img class="object-contain max-w-full max-h-full" src=image.href alt=image.name />
+              {navigation}
+            </div>
+          </div>
+        })
+    };
+
+    view! {
+      <div on:click=on_click>{children()}</div>
+      {lightbox}
+    }
+}
+
+/// The entries of a folder as a grid, images with their thumbnails.
+#[component]
+pub fn GalleryEntries(path: PathBuf, entries: Entries, query: String) -> impl IntoView {
+    let mut images = Vec::new();
+
+    let cards = entries
+        .into_iter()
+        .map(|entry| {
+            let os_name = entry.os_name();
+            let name = display_os_string(&os_name);
+
+            match entry {
+                ServerEntry::Folder { .. } => {
+                    let href = format!("{}{query}", format_folder_href(&path, &os_name));
+                    EitherOf3::A(view! {
+                      <a class="flex flex-col gap-1 items-center p-2 rounded-box hover:bg-base-200" href=href>
+                        <div class="flex justify-center items-center w-full aspect-square">
+                          <Icon type_=EntryType::Folder name=name.clone() />
+                        </div>
+                        <span class="w-full text-center break-all line-clamp-2">{name}</span>
+                      </a>
+                    })
+                },
+                ServerEntry::File { .. } if has_thumbnail(&os_name) => {
+                    let href = format_file_href(&path, &os_name);
+                    let index = images.len();
+                    images.push(GalleryImage {
+                        name: name.clone(),
+                        href: href.clone(),
+                    });
+
+                    EitherOf3::B(view! {
+                      <a
+                        class="flex flex-col gap-1 items-center p-2 rounded-box hover:bg-base-200"
+                        href=href
+                        data-gallery-index=index.to_string()
+                      >
+                        <img
+                          class="object-cover w-full rounded aspect-square"
+                          src=format_thumbnail_href(&path, &os_name)
+                          alt=name.clone()
+                          loading="lazy"
+                        />
+                        <span class="w-full text-center break-all line-clamp-2">{name}</span>
+                      </a>
+                    })
+                },
+                ServerEntry::File { .. } => {
+                    let previewable = preview_kind(&os_name).is_some();
+                    let href = if previewable {
+                        format_preview_href(&path, &os_name)
+                    } else {
+                        format_file_href(&path, &os_name)
+                    };
+                    EitherOf3::C(view! {
+                      <a
+                        class="flex flex-col gap-1 items-center p-2 rounded-box hover:bg-base-200"
+                        href=href
+                        download=!previewable
+                      >
+                        <div class="flex justify-center items-center w-full aspect-square">
+                          <Icon type_=EntryType::File name=name.clone() />
+                        </div>
+                        <span class="w-full text-center break-all line-clamp-2">{name}</span>
+                      </a>
+                    })
+                },
+            }
+        })
+        .collect_view();
+
+    view! {
+      <Gallery images=images>
+        <div class="grid grid-cols-2 gap-2 sm:grid-cols-3 md:grid-cols-4 lg:grid-cols-6 file-view">
+          {cards}
+        </div>
+      </Gallery>
+    }
+}
@@ -0,0 +1,259 @@
+use std::{cmp::Ordering, iter::Peekable, str::Chars};
+
+use leptos_router::params::ParamsMap;
+
+use crate::server::ServerEntry;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum SortKey {
+    #[default]
+    Name,
+    Size,
+    Modified,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum SortOrder {
+    #[default]
+    Ascending,
+    Descending,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ViewMode {
+    #[default]
+    List,
+    /// A grid with thumbnails of images
+    Gallery,
+}
+
+/// How a listing is sorted and shown, kept in the query of the URL as
+/// `?sort=size&order=desc&folders_first=false&view=gallery`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Sorting {
+    pub key: SortKey,
+    pub order: SortOrder,
+    pub folders_first: bool,
+    pub view: ViewMode,
+}
+
+impl Default for Sorting {
+    fn default() -> Self {
+        Self {
+            key: SortKey::default(),
+            order: SortOrder::default(),
+            folders_first: true,
+            view: ViewMode::default(),
+        }
+    }
+}
+
+impl Sorting {
+    /// Read the sorting from a query, invalid values fall back to the
+    /// defaults.
+    pub fn from_query(query: &ParamsMap) -> Self {
+        let default = Self::default();
+
+        let key = match query.get("sort").as_deref() {
+            Some("size") => SortKey::Size,
+            Some("modified") => SortKey::Modified,
+            _ => SortKey::Name,
+        };
+
+        let order = match query.get("order").as_deref() {
+            Some("desc") => SortOrder::Descending,
+            _ => SortOrder::Ascending,
+        };
+
+        let folders_first = match query.get("folders_first").as_deref() {
+            Some("false") => false,
+            Some("true") => true,
+            _ => default.folders_first,
+        };
+
+        let view = match query.get("view").as_deref() {
+            Some("gallery") => ViewMode::Gallery,
+            _ => ViewMode::List,
+        };
+
+        Self {
+            key,
+            order,
+            folders_first,
+            view,
+        }
+    }
+
+    /// The query string (including the `?`) for this sorting, empty for the
+    /// default one.
+    pub fn to_query(self) -> String {
+        let default = Self::default();
+        let mut params = Vec::new();
+
+        match self.key {
+            SortKey::Name => {},
+            SortKey::Size => params.push("sort=size"),
+            SortKey::Modified => params.push("sort=modified"),
+        }
+
+        if self.order == SortOrder::Descending {
+            params.push("order=desc");
+        }
+
+        if self.folders_first != default.folders_first {
+            params.push(if self.folders_first {
+                "folders_first=true"
+            } else {
+                "folders_first=false"
+            });
+        }
+
+        if self.view == ViewMode::Gallery {
+            params.push("view=gallery");
+        }
+
+        if params.is_empty() {
+            String::new()
+        } else {
+            format!("?{}", params.join("&"))
+        }
+    }
+
+    /// Sorting by `key`, reversing the order when already sorted by it.
+    #[must_use]
+    pub fn toggled(self, key: SortKey) -> Self {
+        let order = match (self.key == key, self.order) {
+            (true, SortOrder::Ascending) => SortOrder::Descending,
+            _ => SortOrder::Ascending,
+        };
+
+        Self { key, order, ..self }
+    }
+
+    #[must_use]
+    pub fn with_folders_first(self, folders_first: bool) -> Self {
+        Self {
+            folders_first,
+            ..self
+        }
+    }
+
+    #[must_use]
+    pub fn with_view(self, view: ViewMode) -> Self {
+        Self { view, ..self }
+    }
+
+    pub fn sort(self, entries: &mut [ServerEntry]) {
+        entries.sort_by(|a, b| self.compare(a, b));
+    }
+
+    fn compare(self, a: &ServerEntry, b: &ServerEntry) -> Ordering {
+        let is_file = |entry: &ServerEntry| matches!(entry, ServerEntry::File { .. });
+
+        if self.folders_first {
+            let folders = is_file(a).cmp(&is_file(b));
+            if folders.is_ne() {
+                return folders;
+            }
+        }
+
+        let by_name = || natural_cmp(name(a), name(b));
+
+        let ordering = match self.key {
+            SortKey::Name => by_name(),
+            SortKey::Size => size(a).cmp(&size(b)).then_with(by_name),
+            SortKey::Modified => last_modified(a).cmp(&last_modified(b)).then_with(by_name),
+        };
+
+        match self.order {
+            SortOrder::Ascending => ordering,
+            SortOrder::Descending => ordering.reverse(),
+        }
+    }
+}
+
+fn name(entry: &ServerEntry) -> &str {
+    match entry {
+        ServerEntry::Folder { name, .. } | ServerEntry::File { name, .. } => name,
+    }
+}
+
+/// Folders have no size and go before all files.
+fn size(entry: &ServerEntry) -> Option<u64> {
+    match entry {
+        ServerEntry::Folder { .. } => None,
+        ServerEntry::File { size, .. } => Some(*size),
+    }
+}
+
+fn last_modified(entry: &ServerEntry) -> crate::utils::SystemTime {
+    match entry {
+        ServerEntry::Folder { last_modified, .. } | ServerEntry::File { last_modified, .. } => {
+            *last_modified
+        },
+    }
+}
+
+/// Take a run of ASCII digits from `chars`.
+fn take_number(chars: &mut Peekable<Chars<'_>>) -> String {
+    let mut number = String::new();
+
+    while let Some(digit) = chars.next_if(char::is_ascii_digit) {
+        number.push(digit);
+    }
+
+    number
+}
+
+/// Compare names ignoring case, with runs of digits compared by their value,
+/// so that `file2` goes before `file10`.
+pub fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let (mut a_chars, mut b_chars) = (a.chars().peekable(), b.chars().peekable());
+
+    loop {
+        let ordering = match (a_chars.peek().copied(), b_chars.peek().copied()) {
+            (None, None) => break,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) if x.is_ascii_digit() && y.is_ascii_digit() => {
+                let x = take_number(&mut a_chars);
+                let y = take_number(&mut b_chars);
+
+                let (x, y) = (x.trim_start_matches('0'), y.trim_start_matches('0'));
+
+                // without leading zeros, a longer number is a larger one
+                x.len().cmp(&y.len()).then_with(|| x.cmp(y))
+            },
+            (Some(x), Some(y)) => {
+                a_chars.next();
+                b_chars.next();
+
+                x.to_lowercase().cmp(y.to_lowercase())
+            },
+        };
+
+        if ordering.is_ne() {
+            return ordering;
+        }
+    }
+
+    // names equal up to case and leading zeros still get a stable order
+    a.cmp(b)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn test_natural_cmp() {
+        assert_eq!(natural_cmp("file2", "file10"), Ordering::Less);
+        assert_eq!(natural_cmp("file10", "file2"), Ordering::Greater);
+        assert_eq!(natural_cmp("File1", "file2"), Ordering::Less);
+        assert_eq!(natural_cmp("a", "B"), Ordering::Less);
+        assert_eq!(natural_cmp("img007", "img7b"), Ordering::Less);
+        assert_eq!(natural_cmp("v1.10", "v1.9"), Ordering::Greater);
+        assert_eq!(natural_cmp("abc", "abc"), Ordering::Equal);
+        assert_eq!(natural_cmp("ab", "abc"), Ordering::Less);
+    }
+}
@@ -4,6 +4,9 @@ use leptos::prelude::*;
 
 use crate::utils::display_os_string;
 
+/// Id of the form the selection checkboxes of the listing belong to.
+pub const SELECTION_FORM: &str = "selection";
+
 #[component]
 pub fn FolderDownloads(path: Signal<PathBuf>) -> impl IntoView {
     let method_list = move || {
@@ -30,3 +33,34 @@ pub fn FolderDownloads(path: Signal<PathBuf>) -> impl IntoView {
       </div>
     }
 }
+
+/// Archive downloads of the entries ticked in the listing.
+///
+/// The checkboxes of the entries belong to the form by its id, so the
+/// selection is sent as repeated `name` parameters without any scripting.
+#[component]
+pub fn SelectionDownloads(path: Signal<PathBuf>) -> impl IntoView {
+    let action = move || path.with(|path| format!("/archive/{}", display_os_string(path)));
+
+    let method_list = ["zip", "tar", "tar.gz", "tar.zst"].map(|method| {
+        view! {
+          <li>
+            <button type="submit" name="method" value=method class="px-3 min-w-20">
+              {method}
+            </button>
+          </li>
+        }
+    });
+
+    view! {
+      <form id=SELECTION_FORM method="get" action=action class="dropdown dropdown-hover grow">
+        <input type="hidden" name="selection" value="true" />
+        <label tabindex="0" class="w-full btn">
+          Download Selected
+        </label>
+        <ul tabindex="0" class="p-2 shadow dropdown-content menu bg-base-100 rounded-box">
+          {method_list}
+        </ul>
+      </form>
+    }
+}
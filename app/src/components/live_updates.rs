@@ -0,0 +1,84 @@
+use std::{path::PathBuf, time::Duration};
+
+use leptos::{logging, prelude::*, task::spawn_local};
+use server_fn::codec::{StreamingText, TextStream};
+
+use crate::{components::SELECTION_FORM, error_template::AppError};
+
+/// Changes are usually several events in quick succession, e.g. a file being
+/// written, so the listing reloads only after they settle.
+const SETTLE_DELAY: Duration = Duration::from_millis(500);
+
+/// How often to check whether the user is done with the page.
+const IDLE_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// While anything matches this, reloading would lose what the user is doing:
+/// selected entries, an open dialog, text being typed, search results or an
+/// upload in progress. Other checkboxes like "Folders first" are settings
+/// that survive a reload.
+fn busy_selector() -> String {
+    format!(
+        "input[form={SELECTION_FORM}]:checked, input:focus, dialog[open], .modal-open, [data-busy]"
+    )
+}
+
+/// Stream a line every time the entries of the folder `path` change.
+#[server(
+    output = StreamingText,
+    name = WatchDir,
+    prefix = "/api",
+    endpoint = "watch"
+)]
+pub async fn watch_dir(path: PathBuf) -> Result<TextStream<AppError>, AppError> {
+    use crate::{AppConfig, permissions::current_access, watcher};
+
+    let folder = expect_context::<AppConfig>().sandbox().join(&path)?;
+
+    if !current_access(folder.relative()).can_read() {
+        return Err(AppError::NotFound);
+    }
+
+    Ok(TextStream::new(watcher::changes(
+        folder.relative().to_owned(),
+    )))
+}
+
+fn is_busy() -> bool {
+    document()
+        .query_selector(&busy_selector())
+        .ok()
+        .flatten()
+        .is_some()
+}
+
+fn reload_when_idle() {
+    if is_busy() {
+        set_timeout(reload_when_idle, IDLE_CHECK_INTERVAL);
+    } else {
+        _ = window().location().reload();
+    }
+}
+
+async fn watch(path: PathBuf) {
+    use futures::StreamExt;
+
+    let mut changes = match watch_dir(path).await {
+        Ok(changes) => changes.into_inner(),
+        Err(e) => {
+            logging::warn!("Live updates are unavailable: {e}");
+            return;
+        },
+    };
+
+    // the page is reloaded as a whole, so one change is enough
+    if let Some(Ok(_)) = changes.next().await {
+        set_timeout(reload_when_idle, SETTLE_DELAY);
+    }
+}
+
+/// Reloads the listing of `path` when its entries change on the server, as
+/// soon as the user isn't in the middle of something.
+#[island]
+pub fn LiveUpdates(path: PathBuf) -> impl IntoView {
+    Effect::new(move |_| spawn_local(watch(path.clone())));
+}
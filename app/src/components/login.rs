@@ -0,0 +1,77 @@
+use leptos::prelude::*;
+use leptos_router::hooks::use_query_map;
+
+use crate::auth::{Login, Logout, User};
+
+#[component]
+pub fn LoginPage() -> impl IntoView {
+    let login_action = ServerAction::<Login>::new();
+    let query = use_query_map();
+
+    let failed = move || query.read().get("failed");
+    let next = move || query.read().get("next").unwrap_or_default();
+
+    view! {
+      <div class="flex justify-center items-center p-3 min-h-screen">
+        <ActionForm action=login_action>
+          <div class="w-80 shadow-xl card bg-base-100">
+            <div class="gap-3 card-body">
+              <h2 class="card-title">Log In</h2>
+              {move || {
+                failed()
+                  .map(|failed| {
+                    let message = if failed == "throttled" {
+                      "Too many failed attempts, try again in a minute"
+                    } else {
+                      "Invalid username or password"
+                    };
+                    view! { <p class="text-error">{message}</p> }
+                  })
+              }}
+              <input
+                class="w-full input"
+                type="text"
+                name="username"
+                placeholder="Username"
+                autocomplete="username"
+                required
+                autofocus
+              />
+              <input
+                class="w-full input"
+                type="password"
+                name="password"
+                placeholder="Password"
+                autocomplete="current-password"
+                required
+              />
+              <input type="hidden" name="next" value=next />
+              <div class="justify-end card-actions">
+                <button class="btn btn-primary" type="submit">
+                  Log In
+                </button>
+              </div>
+            </div>
+          </div>
+        </ActionForm>
+      </div>
+    }
+}
+
+#[component]
+pub fn LogoutButton(user: User) -> impl IntoView {
+    let logout_action = ServerAction::<Logout>::new();
+
+    view! {
+      <div class="flex justify-end">
+        <ActionForm action=logout_action>
+          <div class="flex gap-2 items-center">
+            <span>{user.name}</span>
+            <button class="btn btn-sm" type="submit">
+              Log Out
+            </button>
+          </div>
+        </ActionForm>
+      </div>
+    }
+}
@@ -1,15 +1,31 @@
 mod breadcrumbs;
+mod entry_actions;
 mod file_entries;
 mod folder_download;
+mod live_updates;
 mod loading;
+mod login;
 mod new_folder;
+mod preview;
+mod search;
+mod shares;
+mod trash;
 mod upload;
 mod upload_bar;
 
 pub use breadcrumbs::Breadcrumbs;
-pub use file_entries::FileEntries;
-pub use folder_download::FolderDownloads;
+pub use entry_actions::EntryActions;
+pub use file_entries::{EntriesHeader, FileEntries, Sorting};
+pub use folder_download::{FolderDownloads, SELECTION_FORM, SelectionDownloads};
+pub use live_updates::LiveUpdates;
 pub use loading::Loading;
+pub use login::{LoginPage, LogoutButton};
 pub use new_folder::NewFolderButton;
+pub use preview::{PreviewPage, Readme, preview_file};
+pub use search::SearchBox;
+pub use shares::{SharePage, SharesPage};
+pub use trash::TrashPage;
 pub use upload::FileUpload;
+#[cfg(feature = "ssr")]
+pub use upload::progress;
 pub use upload_bar::UploadBar;
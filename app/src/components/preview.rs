@@ -0,0 +1,148 @@
+use std::path::{Path, PathBuf};
+
+use leptos::{either::Either, prelude::*};
+use leptos_router::hooks::use_params_map;
+
+use crate::{
+    components::{Breadcrumbs, Loading},
+    error_template::AppError,
+    preview::{FilePreview, PREVIEW_LIMIT, PreviewKind},
+    utils::{decode_path, display_os_string, format_bytes, format_file_href},
+};
+
+/// The start of the text file `path` rendered as HTML, see
+/// [`crate::preview::preview_kind`] for the supported files.
+#[server(name = PreviewFile, prefix = "/api", endpoint = "preview")]
+pub async fn preview_file(path: PathBuf) -> Result<FilePreview, AppError> {
+    use tokio::{fs, io::AsyncReadExt as _};
+
+    use crate::{
+        AppConfig,
+        auth::current_user,
+        preview::{decode_text, highlight, preview_kind, render_markdown},
+    };
+
+    let not_found = || AppError::NotFound;
+
+    let app_config = expect_context::<AppConfig>();
+    let file = app_config.sandbox().join(&path)?;
+
+    let name = path.file_name().ok_or_else(not_found)?;
+    let kind = preview_kind(name).ok_or_else(|| {
+        AppError::UnsupportedMediaType(format!("'{}' can't be previewed", display_os_string(name)))
+    })?;
+
+    let resolved = app_config
+        .resolve_readable(current_user().as_ref(), &file)
+        .await?;
+    let metadata = fs::metadata(&resolved).await?;
+
+    if !metadata.is_file() {
+        return Err(not_found());
+    }
+
+    let mut bytes = Vec::new();
+    fs::File::open(&resolved)
+        .await?
+        .take(PREVIEW_LIMIT)
+        .read_to_end(&mut bytes)
+        .await?;
+
+    if bytes.contains(&0) {
+        return Err(AppError::UnsupportedMediaType(format!(
+            "'{}' is not a text file",
+            display_os_string(name)
+        )));
+    }
+
+    let size = metadata.len();
+    let truncated = size > bytes.len() as u64;
+    let text = decode_text(&bytes, truncated);
+
+    let name = display_os_string(name);
+    let base = path.parent().map(Path::to_owned).unwrap_or_default();
+
+    // highlighting and rendering can take a while for large files
+    let html = tokio::task::spawn_blocking(move || match kind {
+        PreviewKind::Markdown => render_markdown(&text, &base),
+        PreviewKind::Text => highlight(&text, &name),
+    })
+    .await
+    .map_err(|e| AppError::Io(e.to_string()))?;
+
+    Ok(FilePreview {
+        kind,
+        html,
+        size,
+        truncated,
+    })
+}
+
+fn content_class(kind: PreviewKind) -> &'static str {
+    match kind {
+        PreviewKind::Markdown => "markdown",
+        PreviewKind::Text => "overflow-x-auto text-sm",
+    }
+}
+
+/// The README of a folder, shown below its listing.
+#[component]
+pub fn Readme(name: String, preview: FilePreview) -> impl IntoView {
+    view! {
+      <div class="mt-4 border card border-base-300">
+        <div class="card-body">
+          <h2 class="card-title">{name}</h2>
+          <div class=content_class(preview.kind) inner_html=preview.html></div>
+        </div>
+      </div>
+    }
+}
+
+/// A text file shown in the browser instead of being downloaded.
+#[component]
+pub fn PreviewPage() -> impl IntoView {
+    let path = use_params_map()
+        .with_untracked(|params| decode_path(&params.get("path").unwrap_or_default()));
+
+    let name = path.file_name().map(display_os_string).unwrap_or_default();
+    let parent = path.parent().map(Path::to_owned).unwrap_or_default();
+    let download_href = format_file_href(&parent, path.file_name().unwrap_or_default());
+
+    let preview = Resource::new(|| (), move |()| preview_file(path.clone()));
+
+    view! {
+      <div class="p-3 App">
+        <div class="flex flex-wrap gap-2 justify-between items-center">
+          <Breadcrumbs path=Signal::stored(parent) />
+          <a class="btn btn-primary" href=download_href download>
+            Download
+          </a>
+        </div>
+        <h1 class="py-2 text-xl font-bold break-all">{name}</h1>
+        <Transition fallback=Loading>
+          {move || Suspend::new(async move {
+            match preview.await {
+              Ok(preview) => {
+                let truncated = preview
+                  .truncated
+                  .then(|| {
+                    format!(
+                      "The file has {}, only the first {} are shown.",
+                      format_bytes(preview.size),
+                      format_bytes(PREVIEW_LIMIT),
+                    )
+                  });
+                Either::Left(
+                  view! {
+                    <p class="pb-2 text-warning">{truncated}</p>
+                    <div class=content_class(preview.kind) inner_html=preview.html></div>
+                  },
+                )
+              }
+              Err(e) => Either::Right(view! { <p class="text-lg">{format!("{e}")}</p> }),
+            }
+          })}
+        </Transition>
+      </div>
+    }
+}
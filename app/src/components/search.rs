@@ -0,0 +1,291 @@
+use std::{ffi::OsStr, path::PathBuf};
+
+use futures::StreamExt as _;
+use leptos::{ev::SubmitEvent, logging, prelude::*, task::spawn_local};
+use server_fn::codec::{StreamingText, TextStream};
+
+use crate::{
+    error_template::AppError,
+    preview::preview_kind,
+    search::{MAX_RESULTS, SearchHit},
+    utils::{
+        display_os_string, encode_path, format_bytes, format_file_href, format_folder_href,
+        format_preview_href,
+    },
+};
+
+/// Start of the day `date` (`YYYY-MM-DD` in local time) plus `days_later`.
+#[cfg(feature = "ssr")]
+fn start_of_day(date: &str, days_later: u64) -> Result<crate::utils::SystemTime, AppError> {
+    use chrono::{Days, Local, NaiveDate};
+
+    let invalid = || AppError::BadRequest(format!("Invalid date '{date}'"));
+
+    let start = NaiveDate::parse_from_str(date, "%Y-%m-%d")
+        .map_err(|_| invalid())?
+        .checked_add_days(Days::new(days_later))
+        .and_then(|date| date.and_hms_opt(0, 0, 0))
+        .and_then(|start| start.and_local_timezone(Local).earliest())
+        .ok_or_else(invalid)?;
+
+    Ok(crate::utils::SystemTime(start.timestamp(), 0))
+}
+
+/// Search `path` recursively for names matching `query`, a glob when it
+/// contains `*` or `?` and a substring otherwise, ignoring case.
+///
+/// Sizes are in bytes and dates in the `YYYY-MM-DD` format, both ends
+/// inclusive. The hits are streamed as JSON, one per line.
+#[server(
+    output = StreamingText,
+    name = Search,
+    prefix = "/api",
+    endpoint = "search"
+)]
+pub async fn search(
+    path: PathBuf,
+    query: String,
+    min_size: Option<u64>,
+    max_size: Option<u64>,
+    modified_after: Option<String>,
+    modified_before: Option<String>,
+) -> Result<TextStream<AppError>, AppError> {
+    use crate::{
+        AppConfig,
+        auth::current_user,
+        search::{Pattern, SearchFilter},
+    };
+
+    if query.is_empty() {
+        return Err(AppError::BadRequest("Nothing to search for".into()));
+    }
+
+    let app_config = expect_context::<AppConfig>();
+    let dir = app_config.sandbox().join(path)?;
+
+    let resolved = app_config
+        .resolve_readable(current_user().as_ref(), &dir)
+        .await?;
+    let root = app_config.sandbox().resolve().await?;
+
+    let date = |date: Option<String>, days_later| {
+        date.filter(|date| !date.is_empty())
+            .map(|date| start_of_day(&date, days_later))
+            .transpose()
+    };
+
+    let filter = SearchFilter {
+        pattern: Pattern::new(&query),
+        min_size,
+        max_size,
+        modified_after: date(modified_after, 0)?,
+        // the whole last day is included
+        modified_before: date(modified_before, 1)?,
+    };
+
+    let hits =
+        crate::search::search(app_config, current_user(), root, &resolved, filter).map(|hit| {
+            serde_json::to_string(&hit)
+                .map(|json| json + "\n")
+                .map_err(|e| AppError::Io(e.to_string()))
+        });
+
+    Ok(TextStream::new(hits))
+}
+
+#[allow(clippy::cast_possible_truncation)]
+#[allow(clippy::cast_sign_loss)]
+fn mebibytes_to_bytes(value: &str) -> Option<u64> {
+    let mebibytes = value.trim().parse::<f64>().ok()?;
+    Some((mebibytes * 1024.0 * 1024.0) as u64)
+}
+
+fn none_if_empty(value: String) -> Option<String> {
+    (!value.is_empty()).then_some(value)
+}
+
+#[component]
+fn SearchResult(hit: SearchHit) -> impl IntoView {
+    let SearchHit {
+        folder,
+        name,
+        size,
+        last_modified,
+    } = hit;
+
+    let previewable = size.is_some() && preview_kind(OsStr::new(&name)).is_some();
+    let href = match size {
+        Some(_) if previewable => format_preview_href(&folder, &name),
+        Some(_) => format_file_href(&folder, &name),
+        None => format_folder_href(&folder, &name),
+    };
+    let folder_href = format!("/index/{}", encode_path(&folder));
+    let folder = format!("/{}", display_os_string(&folder));
+
+    view! {
+      <li>
+        <div class="flex flex-wrap gap-x-2 justify-between">
+          <div class="flex overflow-x-hidden flex-col">
+            <a class="font-bold break-all link link-hover" href=href download=size.is_some() && !previewable>
+              {name}
+            </a>
+            <a class="text-sm break-all opacity-70 link link-hover" href=folder_href>
+              {folder}
+            </a>
+          </div>
+          <div class="flex gap-2 items-center text-sm">
+            <span>{size.map(format_bytes)}</span>
+            <span class="hidden md:inline">{last_modified.humanize()}</span>
+          </div>
+        </div>
+      </li>
+    }
+}
+
+/// Recursive search in the current folder with the hits shown as they
+/// arrive.
+#[island]
+pub fn SearchBox(path: PathBuf) -> impl IntoView {
+    let path = StoredValue::new(path);
+
+    let query = RwSignal::new(String::new());
+    let min_size = RwSignal::new(String::new());
+    let max_size = RwSignal::new(String::new());
+    let modified_after = RwSignal::new(String::new());
+    let modified_before = RwSignal::new(String::new());
+
+    let hits = RwSignal::new(Vec::<SearchHit>::new());
+    let status = RwSignal::new(None::<String>);
+    // a newer search makes the streams of older ones stop
+    let generation = StoredValue::new(0_u64);
+
+    let clear = move || {
+        generation.update_value(|generation| *generation += 1);
+        hits.set(Vec::new());
+        status.set(None);
+    };
+
+    let on_submit = move |ev: SubmitEvent| {
+        ev.prevent_default();
+
+        clear();
+
+        if query.with_untracked(String::is_empty) {
+            return;
+        }
+
+        let current = generation.get_value();
+        status.set(Some("Searching…".into()));
+
+        spawn_local(async move {
+            let stream = search(
+                path.get_value(),
+                query.get_untracked(),
+                mebibytes_to_bytes(&min_size.get_untracked()),
+                mebibytes_to_bytes(&max_size.get_untracked()),
+                none_if_empty(modified_after.get_untracked()),
+                none_if_empty(modified_before.get_untracked()),
+            )
+            .await;
+
+            let mut stream = match stream {
+                Ok(stream) => stream.into_inner(),
+                Err(e) => {
+                    status.set(Some(e.to_string()));
+                    return;
+                },
+            };
+
+            let mut buffer = String::new();
+
+            while let Some(chunk) = stream.next().await {
+                if generation.get_value() != current {
+                    return;
+                }
+
+                match chunk {
+                    Ok(chunk) => buffer.push_str(&chunk),
+                    Err(e) => {
+                        status.set(Some(e.to_string()));
+                        return;
+                    },
+                }
+
+                // a hit can be split between chunks, only complete lines are
+                // parsed
+                while let Some(end) = buffer.find('\n') {
+                    let line = buffer.drain(..=end).collect::<String>();
+
+                    match serde_json::from_str::<SearchHit>(&line) {
+                        Ok(hit) => hits.update(|hits| hits.push(hit)),
+                        Err(e) => logging::warn!("Invalid search hit '{line}': {e}"),
+                    }
+                }
+            }
+
+            let count = hits.with_untracked(Vec::len);
+            status.set(Some(if count >= MAX_RESULTS {
+                format!("Showing the first {count} results")
+            } else {
+                format!("{count} result(s)")
+            }));
+        });
+    };
+
+    view! {
+      <div class="py-2">
+        <form class="flex flex-col gap-2" on:submit=on_submit>
+          <div class="flex gap-2">
+            <input
+              class="input grow"
+              type="search"
+              placeholder="Search in this folder, e.g. report or *.pdf"
+              bind:value=query
+            />
+            <button class="btn btn-primary" type="submit">
+              Search
+            </button>
+            <button
+              class="btn"
+              type="button"
+              on:click=move |_| {
+                query.set(String::new());
+                clear();
+              }
+            >
+              Clear
+            </button>
+          </div>
+          <details>
+            <summary class="cursor-pointer">Filters</summary>
+            <div class="flex flex-wrap gap-2 pt-2">
+              <label class="input">
+                "Min MiB"
+                <input type="number" min="0" step="any" bind:value=min_size />
+              </label>
+              <label class="input">
+                "Max MiB"
+                <input type="number" min="0" step="any" bind:value=max_size />
+              </label>
+              <label class="input">
+                "Modified from"
+                <input type="date" bind:value=modified_after />
+              </label>
+              <label class="input">
+                "Modified until"
+                <input type="date" bind:value=modified_before />
+              </label>
+            </div>
+          </details>
+        </form>
+        <p class="py-1 text-sm">{move || status.get()}</p>
+        <ul class="p-0 w-full menu" data-busy=move || hits.with(|hits| !hits.is_empty())>
+          <For
+            each=move || hits.get()
+            key=|hit| (hit.folder.clone(), hit.name.clone())
+            children=|hit| view! { <SearchResult hit=hit /> }
+          />
+        </ul>
+      </div>
+    }
+}
@@ -0,0 +1,291 @@
+use std::path::PathBuf;
+
+use leptos::{
+    either::{Either, EitherOf3},
+    ev::MouseEvent,
+    prelude::*,
+    task::spawn_local,
+};
+use leptos_router::hooks::use_params_map;
+
+use crate::{
+    components::Loading, error_template::AppError, shares::ShareLink, utils::display_os_string,
+};
+
+/// Share `name` in `path` with anyone who has the link, for `expires_hours`
+/// and `max_downloads` downloads if given, behind `password` if there is
+/// one.
+#[server(name = CreateShare, prefix = "/api", endpoint = "create_share")]
+pub async fn create_share(
+    path: PathBuf,
+    name: String,
+    expires_hours: Option<u64>,
+    max_downloads: Option<u64>,
+    password: Option<String>,
+) -> Result<ShareLink, AppError> {
+    use std::time::Duration;
+
+    use crate::{AppConfig, permissions::current_access, server::visible_entry, shares};
+
+    let app_config = expect_context::<AppConfig>();
+    let entry = visible_entry(&path, &name)?;
+
+    // links are used without an account, so they can't reveal hidden entries
+    if !current_access(entry.relative()).can_write()
+        || !app_config.access(None, entry.relative()).can_read()
+    {
+        return Err(AppError::Forbidden(format!(
+            "Sharing '{}' is not allowed",
+            display_os_string(entry.relative())
+        )));
+    }
+
+    if max_downloads == Some(0) {
+        return Err(AppError::BadRequest(
+            "A link needs to allow at least one download".into(),
+        ));
+    }
+
+    let link = shares::create(
+        &app_config,
+        &entry,
+        expires_hours.map(|hours| Duration::from_secs(hours.saturating_mul(60 * 60))),
+        max_downloads,
+        password.as_deref().filter(|password| !password.is_empty()),
+    )
+    .await?;
+
+    Ok(link)
+}
+
+/// Share links of entries the current user can change.
+#[server(name = ListShares, prefix = "/api", endpoint = "list_shares")]
+pub async fn list_shares() -> Result<Vec<ShareLink>, AppError> {
+    use crate::{AppConfig, permissions::current_access, shares};
+
+    let links = shares::list(&expect_context::<AppConfig>())
+        .await?
+        .into_iter()
+        .filter(|link| current_access(&link.path).can_write())
+        .collect();
+
+    Ok(links)
+}
+
+/// Delete a share link, it stops working immediately.
+#[server(name = RevokeShare, prefix = "/api", endpoint = "revoke_share")]
+pub async fn revoke_share(id: String) -> Result<(), AppError> {
+    use crate::{
+        AppConfig,
+        permissions::{current_access, write_denied},
+        shares,
+    };
+
+    let app_config = expect_context::<AppConfig>();
+    let link = shares::list(&app_config)
+        .await?
+        .into_iter()
+        .find(|link| link.id == id)
+        .ok_or(AppError::NotFound)?;
+
+    if !current_access(&link.path).can_write() {
+        return Err(write_denied(format!(
+            "Revoking the link to '{}' is not allowed",
+            display_os_string(&link.path)
+        )));
+    }
+
+    shares::revoke(&app_config, &id).await?;
+
+    Ok(())
+}
+
+/// A share link that can still be used, for the page it leads to.
+#[server(name = ShareInfo, prefix = "/api", endpoint = "share_info")]
+pub async fn share_info(id: String) -> Result<ShareLink, AppError> {
+    use crate::{AppConfig, shares};
+
+    Ok(shares::get(&expect_context::<AppConfig>(), &id).await?)
+}
+
+/// Revoke button of a share link.
+#[island]
+fn ShareActions(id: String) -> impl IntoView {
+    let id = StoredValue::new(id);
+    let error = RwSignal::new(None::<String>);
+    let running = RwSignal::new(false);
+
+    let revoke = move |_: MouseEvent| {
+        let id = id.get_value();
+        running.set(true);
+
+        spawn_local(async move {
+            let result = revoke_share(id).await;
+
+            running.set(false);
+
+            match result {
+                Ok(()) => _ = window().location().reload(),
+                Err(e) => error.set(Some(e.to_string())),
+            }
+        });
+    };
+
+    view! {
+      <div class="flex flex-wrap gap-2 justify-end items-center">
+        <span class="text-error">{move || error.get()}</span>
+        <button class="btn btn-sm btn-error" disabled=running on:click=revoke>
+          Revoke
+        </button>
+      </div>
+    }
+}
+
+/// What a link still allows, e.g. "expires in 2 days, 3 downloads left".
+fn limits(link: &ShareLink) -> String {
+    let mut limits = vec![format!("Created {}", link.created.humanize())];
+
+    if let Some(expires) = link.expires {
+        limits.push(format!("expires {}", expires.humanize()));
+    }
+
+    if let Some(left) = link.downloads_left() {
+        limits.push(format!("{left} download(s) left"));
+    } else {
+        limits.push(format!("downloaded {} time(s)", link.downloads));
+    }
+
+    if link.has_password {
+        limits.push("password protected".into());
+    }
+
+    limits.join(", ")
+}
+
+#[component]
+pub fn SharesPage() -> impl IntoView {
+    let listing = Resource::new(|| (), |()| list_shares());
+
+    view! {
+      <div class="p-3 App">
+        <div class="flex gap-2 justify-between items-center py-2">
+          <h1 class="text-xl font-bold">Share Links</h1>
+          <a class="btn" href="/index">
+            Back to Files
+          </a>
+        </div>
+        <p class="mb-2">"Expired and used up links are removed automatically."</p>
+        <Transition fallback=Loading>
+          {move || Suspend::new(async move {
+            match listing.await {
+              Ok(links) if links.is_empty() => {
+                EitherOf3::A(view! { <div class="file-view">"There are no share links"</div> })
+              }
+              Ok(links) => {
+                EitherOf3::B(
+                  view! {
+                    <div class="file-view">
+                      {links
+                        .into_iter()
+                        .map(|link| {
+                          view! {
+                            <div class="flex flex-wrap gap-2 justify-between items-center py-1 border-b border-base-300">
+                              <div class="flex overflow-x-hidden flex-col">
+                                <a class="break-all link" href=link.href()>
+                                  {display_os_string(&link.path)}
+                                  {link.is_folder.then_some("/")}
+                                </a>
+                                <span class="text-sm opacity-70">{limits(&link)}</span>
+                              </div>
+                              <ShareActions id=link.id />
+                            </div>
+                          }
+                        })
+                        .collect_view()}
+                    </div>
+                  },
+                )
+              }
+              Err(e) => EitherOf3::C(view! { <p class="text-lg">{format!("{e}")}</p> }),
+            }
+          })}
+        </Transition>
+      </div>
+    }
+}
+
+/// Download form of a shared file or folder, reachable without an account.
+#[component]
+pub fn SharePage() -> impl IntoView {
+    let params = use_params_map();
+    let id = params.with_untracked(|params| params.get("id").unwrap_or_default());
+
+    let link = Resource::new(move || id.clone(), share_info);
+
+    view! {
+      <div class="p-3 App">
+        <Transition fallback=Loading>
+          {move || Suspend::new(async move {
+            match link.await {
+              Ok(link) => {
+                let name = link
+                  .path
+                  .file_name()
+                  .map(display_os_string)
+                  .unwrap_or_default();
+                let password = link
+                  .has_password
+                  .then(|| {
+                    view! {
+                      <label class="w-full floating-label">
+                        <span>Password</span>
+                        <input class="w-full input" type="password" name="password" required />
+                      </label>
+                    }
+                  });
+                let download = if link.is_folder {
+                  Either::Left(
+                    view! {
+                      <select class="select" name="method">
+                        {["zip", "tar", "tar.gz", "tar.zst"]
+                          .map(|method| view! { <option value=method>{method}</option> })}
+                      </select>
+                      <button class="btn btn-primary" type="submit">
+                        Download Folder
+                      </button>
+                    },
+                  )
+                } else {
+                  Either::Right(
+                    view! {
+                      <button class="btn btn-primary" type="submit">
+                        Download
+                      </button>
+                    },
+                  )
+                };
+                Either::Left(
+                  view! {
+                    <form
+                      class="flex flex-col gap-4 p-6 mx-auto max-w-md shadow-sm card bg-base-100"
+                      method="post"
+                      action=format!("{}/download", link.href())
+                    >
+                      <h1 class="text-xl font-bold break-all">
+                        {name}
+                        {link.is_folder.then_some("/")}
+                      </h1>
+                      <p class="text-sm opacity-70">{limits(&link)}</p>
+                      {password}
+                      <div class="flex gap-2 justify-end">{download}</div>
+                    </form>
+                  },
+                )
+              }
+              Err(e) => Either::Right(view! { <p class="text-lg">{format!("{e}")}</p> }),
+            }
+          })}
+        </Transition>
+      </div>
+    }
+}
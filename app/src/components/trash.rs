@@ -0,0 +1,169 @@
+use leptos::{either::EitherOf3, ev::MouseEvent, prelude::*, task::spawn_local};
+
+use crate::{
+    components::Loading, error_template::AppError, trash::TrashEntry, utils::display_os_string,
+};
+
+/// Entries in the trash the current user could restore.
+#[server(name = ListTrash, prefix = "/api", endpoint = "list_trash")]
+pub async fn list_trash() -> Result<Vec<TrashEntry>, AppError> {
+    use crate::{AppConfig, server::can_write_tree, trash};
+
+    let app_config = expect_context::<AppConfig>();
+
+    let entries = trash::list(&app_config)
+        .await?
+        .into_iter()
+        .filter(|entry| {
+            app_config
+                .sandbox()
+                .join(&entry.original)
+                .is_ok_and(|original| can_write_tree(&original))
+        })
+        .collect();
+
+    Ok(entries)
+}
+
+/// Move a trashed entry back to where it was deleted from.
+#[server(name = RestoreTrash, prefix = "/api", endpoint = "restore_trash")]
+pub async fn restore_trash(id: String) -> Result<(), AppError> {
+    use crate::{AppConfig, permissions::write_denied, server::can_write_tree, trash};
+
+    let app_config = expect_context::<AppConfig>();
+    let entry = trash::get(&app_config, &id).await?;
+
+    if !can_write_tree(&app_config.sandbox().join(&entry.original)?) {
+        return Err(write_denied(format!(
+            "Restoring '{}' is not allowed",
+            display_os_string(&entry.original)
+        )));
+    }
+
+    trash::restore(&app_config, &id).await?;
+
+    Ok(())
+}
+
+/// Delete a trashed entry for good.
+#[server(name = PurgeTrash, prefix = "/api", endpoint = "purge_trash")]
+pub async fn purge_trash(id: String) -> Result<(), AppError> {
+    use crate::{AppConfig, permissions::write_denied, server::can_write_tree, trash};
+
+    let app_config = expect_context::<AppConfig>();
+    let entry = trash::get(&app_config, &id).await?;
+
+    if !can_write_tree(&app_config.sandbox().join(&entry.original)?) {
+        return Err(write_denied(format!(
+            "Deleting '{}' is not allowed",
+            display_os_string(&entry.original)
+        )));
+    }
+
+    trash::purge(&app_config, &id).await?;
+
+    Ok(())
+}
+
+/// Restore and purge buttons of a trashed entry.
+#[island]
+fn TrashActions(id: String) -> impl IntoView {
+    let id = StoredValue::new(id);
+    let error = RwSignal::new(None::<String>);
+    let running = RwSignal::new(false);
+
+    let run = move |purge: bool| {
+        move |_: MouseEvent| {
+            let id = id.get_value();
+            running.set(true);
+
+            spawn_local(async move {
+                let result = if purge {
+                    purge_trash(id).await
+                } else {
+                    restore_trash(id).await
+                };
+
+                running.set(false);
+
+                match result {
+                    Ok(()) => _ = window().location().reload(),
+                    Err(e) => error.set(Some(e.to_string())),
+                }
+            });
+        }
+    };
+
+    view! {
+      <div class="flex flex-wrap gap-2 justify-end items-center">
+        <span class="text-error">{move || error.get()}</span>
+        <button class="btn btn-sm" disabled=running on:click=run(false)>
+          Restore
+        </button>
+        <button class="btn btn-sm btn-error" disabled=running on:click=run(true)>
+          Delete Permanently
+        </button>
+      </div>
+    }
+}
+
+#[component]
+pub fn TrashPage() -> impl IntoView {
+    let listing = Resource::new(|| (), |()| list_trash());
+
+    let retention_days = expect_context::<crate::AppConfig>()
+        .trash_retention
+        .as_secs()
+        / (24 * 60 * 60);
+
+    view! {
+      <div class="p-3 App">
+        <div class="flex gap-2 justify-between items-center py-2">
+          <h1 class="text-xl font-bold">Trash</h1>
+          <a class="btn" href="/index">
+            Back to Files
+          </a>
+        </div>
+        <p class="mb-2">
+          {format!("Deleted entries are removed permanently after {retention_days} day(s).")}
+        </p>
+        <Transition fallback=Loading>
+          {move || Suspend::new(async move {
+            match listing.await {
+              Ok(entries) if entries.is_empty() => {
+                EitherOf3::A(view! { <div class="file-view">"The trash is empty"</div> })
+              }
+              Ok(entries) => {
+                EitherOf3::B(
+                  view! {
+                    <div class="file-view">
+                      {entries
+                        .into_iter()
+                        .map(|entry| {
+                          view! {
+                            <div class="flex flex-wrap gap-2 justify-between items-center py-1 border-b border-base-300">
+                              <div class="flex overflow-x-hidden flex-col">
+                                <span class="break-all">
+                                  {display_os_string(&entry.original)}
+                                  {entry.is_folder.then_some("/")}
+                                </span>
+                                <span class="text-sm opacity-70">
+                                  {format!("Deleted {}", entry.deleted.humanize())}
+                                </span>
+                              </div>
+                              <TrashActions id=entry.id />
+                            </div>
+                          }
+                        })
+                        .collect_view()}
+                    </div>
+                  },
+                )
+              }
+              Err(e) => EitherOf3::C(view! { <p class="text-lg">{format!("{e}")}</p> }),
+            }
+          })}
+        </Transition>
+      </div>
+    }
+}
@@ -1,46 +1,114 @@
 use std::{
     hash::{DefaultHasher, Hash, Hasher},
-    path::PathBuf,
+    path::{Path, PathBuf},
+    time::Duration,
 };
 
-use leptos::{
-    ev::SubmitEvent,
-    html::{Form, Input},
-    logging,
-    prelude::*,
-    task::spawn_local,
-};
+use futures::channel::oneshot;
+use leptos::{ev::SubmitEvent, html::Input, logging, prelude::*, task::spawn_local};
+use serde::{Deserialize, Serialize};
 use server_fn::codec::{MultipartData, MultipartFormData, StreamingText, TextStream};
+use web_sys::{File, FormData, js_sys::Math, wasm_bindgen::JsValue};
 use web_time::Instant;
 
+use crate::{conflict::ConflictPolicy, error_template::AppError};
+
+mod conflict_dialog;
 mod form;
 #[cfg(feature = "ssr")]
 pub mod progress;
 pub mod progress_bar;
 pub mod use_upload_progress;
 
+use conflict_dialog::{ConflictDialog, PendingConflict};
 use form::UploadForm;
 use progress_bar::{Progress, ProgressBar};
 use use_upload_progress::update_progress;
 
-#[server(input = MultipartFormData)]
-pub async fn upload_file(data: MultipartData) -> Result<(), ServerFnError> {
-    use server_fn::ServerFnError::ServerError;
-    use tokio::{fs::OpenOptions, io::AsyncWriteExt};
+/// Progress of a resumable upload.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UploadStatus {
+    pub id: String,
+    /// Number of bytes already stored on the server
+    pub offset: u64,
+}
+
+/// Start uploading a file of `size` bytes named `name` into `path`.
+///
+/// `key` identifies the file for the client, e.g. a random string it keeps.
+/// Calling it again with the same file and key returns the same id with the
+/// offset to continue from. Returns `None` when the file already exists and
+/// the `conflict` policy (or the configured one) is to reject it.
+#[server(name = BeginUpload, prefix = "/api", endpoint = "begin_upload")]
+pub async fn begin_upload(
+    path: PathBuf,
+    name: String,
+    size: u64,
+    key: String,
+    conflict: Option<ConflictPolicy>,
+) -> Result<Option<UploadStatus>, AppError> {
+    use crate::{
+        AppConfig,
+        auth::current_user,
+        permissions::write_denied,
+        uploads::{self, UploadError},
+    };
+
+    let app_config = expect_context::<AppConfig>();
+    let target = app_config.sandbox().join(path)?.join_name(&name)?;
+    let resolved = target.resolve_new().await?;
+
+    // checks where symlinks lead as well
+    if !app_config
+        .real_access(current_user().as_ref(), &target, &resolved)
+        .await
+        .can_write()
+    {
+        return Err(write_denied(format!("Uploading '{name}' is not allowed")));
+    }
+
+    let policy = conflict.unwrap_or(app_config.on_conflict);
+
+    let (id, offset) = match uploads::begin(&app_config, target, size, &key, policy).await {
+        Ok(started) => started,
+        Err(UploadError::Exists) => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+
+    logging::log!("[{id}]\tupload of '{name}' at {offset}/{size}");
 
-    use crate::AppConfig;
+    Ok(Some(UploadStatus { id, offset }))
+}
+
+/// Append a chunk to an upload started by [`begin_upload`].
+///
+/// The multipart form has the fields `id`, `offset` and `progress` (id of the
+/// progress stream) followed by the chunk itself. Returns the new offset.
+#[server(
+    input = MultipartFormData,
+    name = UploadChunk,
+    prefix = "/api",
+    endpoint = "upload_chunk"
+)]
+pub async fn upload_chunk(data: MultipartData) -> Result<u64, AppError> {
+    use std::pin::pin;
+
+    use crate::{
+        permissions::{current_access, write_denied},
+        uploads,
+    };
 
     async fn collect_field_with_name(
         data: &mut multer::Multipart<'static>,
         name: &str,
-    ) -> Result<String, ServerFnError> {
+    ) -> Result<String, AppError> {
         let Ok(Some(mut field)) = data.next_field().await else {
             logging::error!("no field");
-            return Err(ServerError("No field.".into()));
+            return Err(AppError::BadRequest("No field.".into()));
         };
 
         if field.name().is_none_or(|n| n != name) {
-            return Err(ServerError(format!("Missing field '{name}'.")));
+            return Err(AppError::BadRequest(format!("Missing field '{name}'.")));
         }
 
         let mut buffer = String::new();
@@ -51,53 +119,62 @@ pub async fn upload_file(data: MultipartData) -> Result<(), ServerFnError> {
         Ok(buffer)
     }
 
-    let app_config = expect_context::<AppConfig>();
-
-    if !app_config.allow_upload {
-        return Err(ServerError("Uploads are disabled".into()));
-    }
-
     let Some(mut data) = data.into_inner() else {
         unreachable!("should always return Some on the server side");
     };
 
-    let base_req_path = {
-        let req_path = collect_field_with_name(&mut data, "path").await?;
-        app_config.target_dir.join(req_path.trim())
-    };
-
     let id = collect_field_with_name(&mut data, "id").await?;
+    let offset = collect_field_with_name(&mut data, "offset")
+        .await?
+        .parse::<u64>()
+        .map_err(|e| AppError::BadRequest(format!("Invalid offset: {e}")))?;
+    let progress_id = collect_field_with_name(&mut data, "progress").await?;
 
-    logging::log!("[{id}]\tbase path: {base_req_path:?}");
+    let target = uploads::target(&id).await?;
 
-    while let Ok(Some(mut field)) = data.next_field().await {
-        let Some(name) = field.file_name().map(ToOwned::to_owned) else {
-            logging::error!("no file name");
-            return Err(ServerError("Missing file name in multipart".into()));
-        };
+    if !current_access(target.relative()).can_write() {
+        return Err(write_denied("Uploading here is not allowed".into()));
+    }
 
-        let path = base_req_path.join(&name);
-        logging::log!("[{name}]\tpath: {path:?}");
+    let Ok(Some(field)) = data.next_field().await else {
+        return Err(AppError::BadRequest("Missing chunk in multipart".into()));
+    };
 
-        let mut file = OpenOptions::new()
-            .create(true)
-            .write(true)
-            .truncate(true)
-            .open(&path)
-            .await?;
+    let offset = uploads::append(&id, offset, pin!(field), async |len| {
+        progress::add_chunk(&progress_id, len).await;
+    })
+    .await?;
 
-        logging::log!("[{name}]\topen");
+    Ok(offset)
+}
 
-        while let Ok(Some(chunk)) = field.chunk().await {
-            let len = chunk.len();
+/// The offset to continue the upload `id` from after a chunk failed.
+///
+/// Uploads that were completed in the meantime report their whole size, so
+/// a retry never starts a file over or stores it under a second name.
+#[server(name = ResumeUpload, prefix = "/api", endpoint = "resume_upload")]
+pub async fn resume_upload(id: String) -> Result<u64, AppError> {
+    use crate::{
+        permissions::{current_access, write_denied},
+        uploads,
+    };
 
-            progress::add_chunk(&id, len).await;
-            file.write_all(&chunk).await?;
-        }
+    // completed uploads are no longer running, only their size is reported
+    let allowed = uploads::target(&id)
+        .await
+        .ok()
+        .is_none_or(|target| current_access(target.relative()).can_write());
 
-        logging::log!("[{name}]\tfinished");
+    if !allowed {
+        return Err(write_denied("Uploading here is not allowed".into()));
     }
 
+    Ok(uploads::resume(&id).await?)
+}
+
+/// Close the progress stream of a finished batch of uploads.
+#[server(name = FinishUploads, prefix = "/api", endpoint = "finish_uploads")]
+pub async fn finish_uploads(id: String) -> Result<(), AppError> {
     logging::log!("[{id}]\tfinished");
     progress::finish(&id).await;
 
@@ -105,24 +182,197 @@ pub async fn upload_file(data: MultipartData) -> Result<(), ServerFnError> {
 }
 
 #[allow(clippy::unused_async)]
-#[server(output = StreamingText)]
-pub async fn file_progress(id: String) -> Result<TextStream, ServerFnError> {
+#[server(
+    output = StreamingText,
+    name = FileProgress,
+    prefix = "/api",
+    endpoint = "upload_progress"
+)]
+pub async fn file_progress(id: String) -> Result<TextStream<AppError>, AppError> {
     Ok(TextStream::new(progress::progress_stream(id.clone()).await))
 }
 
+/// Size of the pieces files are sent in, a dropped connection loses at most
+/// one chunk.
+const CHUNK_SIZE: u64 = 8 * 1024 * 1024;
+/// How many times in a row a chunk is retried before giving up on the file.
+const MAX_RETRIES: u32 = 5;
+const RETRY_DELAY: Duration = Duration::from_secs(2);
+
+#[allow(clippy::cast_possible_truncation)]
+#[allow(clippy::cast_sign_loss)]
+fn file_size(file: &File) -> u64 {
+    file.size() as u64
+}
+
+/// The key identifying `file` in `path` for [`begin_upload`], random and
+/// kept in the browser's storage until the upload is done, so that only
+/// this browser resumes it.
+#[allow(clippy::cast_possible_truncation)]
+#[allow(clippy::cast_sign_loss)]
+fn upload_key(path: &Path, file: &File) -> String {
+    let storage = window().local_storage().ok().flatten();
+    let item = storage_item(path, file);
+
+    if let Some(key) = storage
+        .as_ref()
+        .and_then(|storage| storage.get_item(&item).ok().flatten())
+    {
+        return key;
+    }
+
+    let random = || (Math::random() * 2_f64.powi(53)) as u64;
+    let key = format!("{:014x}{:014x}", random(), random());
+
+    if let Some(storage) = storage {
+        _ = storage.set_item(&item, &key);
+    }
+
+    key
+}
+
+/// Forget the key of an upload that's done.
+fn forget_upload_key(path: &Path, file: &File) {
+    if let Ok(Some(storage)) = window().local_storage() {
+        _ = storage.remove_item(&storage_item(path, file));
+    }
+}
+
+fn storage_item(path: &Path, file: &File) -> String {
+    format!(
+        "upload:{}:{}:{}:{}",
+        path.display(),
+        file.name(),
+        file_size(file),
+        file.last_modified()
+    )
+}
+
+async fn sleep(duration: Duration) {
+    let (tx, rx) = oneshot::channel();
+    set_timeout(move || _ = tx.send(()), duration);
+    _ = rx.await;
+}
+
+#[allow(clippy::cast_precision_loss)]
+fn chunk_form(
+    file: &File,
+    status: &UploadStatus,
+    end: u64,
+    progress_id: &str,
+) -> Result<FormData, JsValue> {
+    let form = FormData::new()?;
+    form.append_with_str("id", &status.id)?;
+    form.append_with_str("offset", &status.offset.to_string())?;
+    form.append_with_str("progress", progress_id)?;
+
+    let chunk = file.slice_with_f64_and_f64(status.offset as f64, end as f64)?;
+    form.append_with_blob_and_filename("chunk", &chunk, &file.name())?;
+
+    Ok(form)
+}
+
+/// Start uploading a file, asking the user what to do when its name is
+/// taken.
+///
+/// `remembered` holds the answer the user chose to apply to all files.
+/// Returns `None` when the file is skipped.
+async fn begin_with_prompt(
+    file: &File,
+    path: &Path,
+    pending: RwSignal<Option<PendingConflict>>,
+    apply_to_all: RwSignal<bool>,
+    remembered: &mut Option<Option<ConflictPolicy>>,
+) -> Result<Option<UploadStatus>, AppError> {
+    let size = file_size(file);
+    let key = upload_key(path, file);
+
+    if let Some(status) =
+        begin_upload(path.to_owned(), file.name(), size, key.clone(), None).await?
+    {
+        return Ok(Some(status));
+    }
+
+    let choice = if let Some(choice) = *remembered {
+        choice
+    } else {
+        let (tx, rx) = oneshot::channel();
+        pending.set(Some(PendingConflict {
+            name: file.name(),
+            answer: tx,
+        }));
+
+        let choice = rx.await.unwrap_or(None);
+
+        if apply_to_all.get_untracked() {
+            *remembered = Some(choice);
+        }
+
+        choice
+    };
+
+    let Some(policy) = choice else {
+        logging::log!("Skipping '{}'", file.name());
+        return Ok(None);
+    };
+
+    begin_upload(path.to_owned(), file.name(), size, key, Some(policy)).await
+}
+
+/// Send the rest of a file chunk by chunk, resuming from the offset committed
+/// by the server when a chunk fails.
+async fn upload_rest(
+    file: &File,
+    mut status: UploadStatus,
+    progress_id: &str,
+) -> Result<(), AppError> {
+    let size = file_size(file);
+    let mut retries = 0;
+
+    while status.offset < size {
+        let end = (status.offset + CHUNK_SIZE).min(size);
+        let form = chunk_form(file, &status, end, progress_id)
+            .map_err(|e| AppError::ServerFn(format!("{e:?}")))?;
+
+        match upload_chunk(form.into()).await {
+            Ok(offset) => {
+                status.offset = offset;
+                retries = 0;
+            },
+            Err(e) if retries < MAX_RETRIES => {
+                retries += 1;
+                logging::warn!(
+                    "[{}]\tchunk at {} failed ({e}), retrying",
+                    status.id,
+                    status.offset
+                );
+
+                sleep(RETRY_DELAY).await;
+
+                // the server may have committed the chunk, or even completed
+                // the upload when it was the last one
+                if let Ok(offset) = resume_upload(status.id.clone()).await {
+                    status.offset = offset;
+                }
+            },
+            Err(e) => return Err(e),
+        }
+    }
+
+    Ok(())
+}
+
 #[island]
 pub fn FileUpload(path: PathBuf) -> impl IntoView {
     let current_upload = RwSignal::new(None::<(String, Progress)>);
+    let pending_conflict = RwSignal::new(None::<PendingConflict>);
+    let apply_to_all = RwSignal::new(false);
 
     let file_ref: NodeRef<Input> = NodeRef::new();
-    let form_ref: NodeRef<Form> = NodeRef::new();
 
     let on_submit = move |ev: SubmitEvent| {
         ev.prevent_default();
 
-        let form = form_ref.get().unwrap();
-        let form_data = web_sys::FormData::new_with_form(&form).unwrap();
-
         let file_list = file_ref.get().unwrap().files().unwrap();
 
         let files = (0..file_list.length())
@@ -134,9 +384,7 @@ pub fn FileUpload(path: PathBuf) -> impl IntoView {
             return;
         }
 
-        #[allow(clippy::cast_possible_truncation)]
-        #[allow(clippy::cast_sign_loss)]
-        let total = files.iter().map(|f| f.size() as u64).sum::<u64>();
+        let total = files.iter().map(file_size).sum::<u64>();
 
         let id = {
             let mut hasher = DefaultHasher::default();
@@ -153,8 +401,6 @@ pub fn FileUpload(path: PathBuf) -> impl IntoView {
             return;
         }
 
-        _ = form_data.set_with_str("id", &id);
-
         let _ = current_upload.write().insert((
             id.clone(),
             Progress {
@@ -164,12 +410,57 @@ pub fn FileUpload(path: PathBuf) -> impl IntoView {
             },
         ));
 
-        spawn_local(update_progress(id.clone(), current_upload));
+        let path = path.clone();
+
+        apply_to_all.set(false);
 
         spawn_local(async move {
-            upload_file(form_data.into())
-                .await
-                .expect("couldn't upload file");
+            let mut uploads = Vec::new();
+            let mut remembered = None;
+
+            for file in files {
+                let started = begin_with_prompt(
+                    &file,
+                    &path,
+                    pending_conflict,
+                    apply_to_all,
+                    &mut remembered,
+                )
+                .await;
+
+                match started {
+                    Ok(Some(status)) => uploads.push((file, status)),
+                    Ok(None) => {},
+                    Err(e) => logging::error!("Couldn't upload '{}': {e}", file.name()),
+                }
+            }
+
+            // the bar only tracks what's left to send
+            let remaining = uploads
+                .iter()
+                .map(|(file, status)| file_size(file) - status.offset)
+                .sum::<u64>();
+
+            current_upload.update(|upload| {
+                if let Some((_, progress)) = upload {
+                    progress.size = remaining.max(1);
+                }
+            });
+
+            spawn_local(update_progress(id.clone(), current_upload));
+
+            for (file, status) in uploads {
+                match upload_rest(&file, status, &id).await {
+                    Ok(()) => forget_upload_key(&path, &file),
+                    Err(e) => logging::error!("Couldn't upload '{}': {e}", file.name()),
+                }
+            }
+
+            if let Err(e) = finish_uploads(id.clone()).await {
+                logging::error!("[{id}]\tcouldn't close the progress stream: {e}");
+            }
+
+            current_upload.write().take();
 
             logging::log!("[{id}]\tfinished (upload)");
         });
@@ -177,7 +468,8 @@ pub fn FileUpload(path: PathBuf) -> impl IntoView {
 
     view! {
       <div class="flex flex-col gap-2 grow">
-        <UploadForm path=path file_ref=file_ref form_ref=form_ref on_submit=on_submit />
+        <UploadForm file_ref=file_ref on_submit=on_submit />
+        <ConflictDialog pending=pending_conflict apply_to_all=apply_to_all />
 
         {move || {
           current_upload
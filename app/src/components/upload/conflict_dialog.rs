@@ -0,0 +1,65 @@
+use futures::channel::oneshot;
+use leptos::prelude::*;
+
+use crate::conflict::ConflictPolicy;
+
+/// A question about an upload whose name is already taken.
+pub struct PendingConflict {
+    pub name: String,
+    /// Receives the chosen policy, `None` to skip the file
+    pub answer: oneshot::Sender<Option<ConflictPolicy>>,
+}
+
+#[component]
+pub fn ConflictDialog(
+    pending: RwSignal<Option<PendingConflict>>,
+    apply_to_all: RwSignal<bool>,
+) -> impl IntoView {
+    let answer = move |policy: Option<ConflictPolicy>| {
+        if let Some(pending) = pending.write().take() {
+            _ = pending.answer.send(policy);
+        }
+    };
+
+    move || {
+        pending
+            .with(|pending| pending.as_ref().map(|pending| pending.name.clone()))
+            .map(|name| {
+                view! {
+                  <div class="modal modal-open">
+                    <div class="modal-box">
+                      <h3 class="text-lg font-bold">File already exists</h3>
+                      <p class="py-4">
+                        "A file named " <span class="font-bold break-all">{name}</span>
+                        " already exists in this folder."
+                      </p>
+                      <label class="flex gap-2 items-center cursor-pointer">
+                        <input type="checkbox" class="checkbox" bind:checked=apply_to_all />
+                        "Do the same for the other files"
+                      </label>
+                      <div class="flex-wrap modal-action">
+                        <button class="btn" on:click=move |_| answer(None)>
+                          Skip
+                        </button>
+                        <button class="btn" on:click=move |_| answer(Some(ConflictPolicy::Rename))>
+                          Rename
+                        </button>
+                        <button
+                          class="btn"
+                          on:click=move |_| answer(Some(ConflictPolicy::Timestamp))
+                        >
+                          Add Timestamp
+                        </button>
+                        <button
+                          class="btn btn-warning"
+                          on:click=move |_| answer(Some(ConflictPolicy::Overwrite))
+                        >
+                          Overwrite
+                        </button>
+                      </div>
+                    </div>
+                  </div>
+                }
+            })
+    }
+}
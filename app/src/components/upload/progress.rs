@@ -2,10 +2,12 @@ use std::{collections::HashMap, sync::LazyLock};
 
 use async_broadcast::{Receiver, Sender, broadcast};
 use futures::StreamExt;
-use leptos::{logging, prelude::*};
+use leptos::logging;
 use tokio::sync::Mutex;
 use tokio_stream::Stream;
 
+use crate::error_template::AppError;
+
 struct FileHandle {
     total: usize,
     tx: Sender<usize>,
@@ -37,12 +39,16 @@ pub async fn add_chunk(id: &str, len: usize) {
     let tx = entry.tx.clone();
     drop(lock);
 
-    tx.broadcast(new_total)
-        .await
-        .expect("couldn't send a message over channel");
+    // the channel is closed when another request finished the same id
+    _ = tx.broadcast(new_total).await;
+}
+
+/// Number of uploads whose progress is being reported.
+pub async fn active() -> usize {
+    FILES.lock().await.len()
 }
 
-pub async fn progress_stream(id: String) -> impl Stream<Item = Result<String, ServerFnError>> {
+pub async fn progress_stream(id: String) -> impl Stream<Item = Result<String, AppError>> {
     let mut lock = FILES.lock().await;
     let entry = lock.entry(id.clone()).or_insert_with(|| {
         logging::log!("[{id}]\tinserting channel (progress)");
@@ -36,7 +36,7 @@ pub fn ProgressBar(
     let formatted_speed = move || format_bytes(average_speed() as u64);
 
     view! {
-      <div class="flex flex-row gap-5 justify-between items-baseline m-2 w-full">
+      <div class="flex flex-row gap-5 justify-between items-baseline m-2 w-full" data-busy>
         <span>Uploading {move || format!("{: >3}", percent())}%</span>
         <div class="h-3 rounded-full bg-neutral grow">
           <div
@@ -2,19 +2,36 @@ use std::path::PathBuf;
 
 use leptos::prelude::*;
 
-use crate::components::{FileUpload, FolderDownloads, NewFolderButton};
+use crate::components::{FileUpload, FolderDownloads, NewFolderButton, SelectionDownloads};
 
 #[component]
 pub fn UploadBar(
     #[prop(into)] path: Signal<PathBuf>,
     create_folder_action: ServerAction<crate::server::NewFolder>,
+    can_write: bool,
 ) -> impl IntoView {
+    let file_upload = can_write.then(|| view! { <FileUpload path=path() /> });
+    let new_folder_button =
+        can_write.then(|| view! { <NewFolderButton path=path action=create_folder_action /> });
+    let trash_link = can_write.then(|| {
+        view! {
+          <a class="btn" href="/trash">
+            Trash
+          </a>
+          <a class="btn" href="/shares">
+            Share Links
+          </a>
+        }
+    });
+
     view! {
       <div class="flex flex-wrap gap-2 justify-center items-start py-2 w-full">
-        <FileUpload path=path() />
+        {file_upload}
         <div class="flex gap-2 grow">
-          <NewFolderButton path=path action=create_folder_action />
+          {new_folder_button}
           <FolderDownloads path=path />
+          <SelectionDownloads path=path />
+          {trash_link}
         </div>
       </div>
     }
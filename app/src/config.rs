@@ -1,7 +1,24 @@
-use std::path::PathBuf;
+use std::{path::PathBuf, time::Duration};
+
+use crate::{conflict::ConflictPolicy, permissions::PathRule};
+
+/// Directory inside the shared directory where the server keeps its own
+/// data (e.g. unfinished uploads, the trash). It's hidden from everyone.
+pub const STATE_DIR: &str = ".file-share";
 
 #[derive(Debug, Clone)]
 pub struct AppConfig {
     pub target_dir: PathBuf,
     pub allow_upload: bool,
+    pub rules: Vec<PathRule>,
+    /// Default for uploads that don't choose a policy themselves
+    pub on_conflict: ConflictPolicy,
+    /// How long deleted entries are kept in the trash
+    pub trash_retention: Duration,
+}
+
+impl AppConfig {
+    pub fn state_dir(&self) -> PathBuf {
+        self.target_dir.join(STATE_DIR)
+    }
 }
@@ -0,0 +1,147 @@
+use std::{fmt, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+
+/// What to do when an uploaded file has the name of an existing one.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ConflictPolicy {
+    /// Refuse the upload
+    #[default]
+    Reject,
+    /// Replace the existing file
+    Overwrite,
+    /// Store the upload as `name (1).ext`, `name (2).ext`, ...
+    Rename,
+    /// Store the upload as `name (YYYY-MM-DD HH.MM.SS).ext`
+    Timestamp,
+}
+
+impl FromStr for ConflictPolicy {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "reject" => Ok(ConflictPolicy::Reject),
+            "overwrite" => Ok(ConflictPolicy::Overwrite),
+            "rename" => Ok(ConflictPolicy::Rename),
+            "timestamp" => Ok(ConflictPolicy::Timestamp),
+            _ => Err(format!(
+                "Invalid conflict policy '{value}', expected one of reject, overwrite, rename, \
+                 timestamp"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for ConflictPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            ConflictPolicy::Reject => "reject",
+            ConflictPolicy::Overwrite => "overwrite",
+            ConflictPolicy::Rename => "rename",
+            ConflictPolicy::Timestamp => "timestamp",
+        };
+        write!(f, "{name}")
+    }
+}
+
+#[cfg(feature = "ssr")]
+mod resolve {
+    use std::{
+        io,
+        path::{Path, PathBuf},
+    };
+
+    use tokio::fs;
+
+    use super::ConflictPolicy;
+
+    /// `name (suffix).ext` next to `path`.
+    fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+        let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+
+        let name = match path.extension() {
+            Some(extension) => format!("{stem} ({suffix}).{}", extension.to_string_lossy()),
+            None => format!("{stem} ({suffix})"),
+        };
+
+        path.with_file_name(name)
+    }
+
+    async fn exists(path: &Path) -> io::Result<bool> {
+        // symlinks count even when dangling
+        match fs::symlink_metadata(path).await {
+            Ok(_) => Ok(true),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn first_free(path: &Path) -> io::Result<PathBuf> {
+        for n in 1.. {
+            let candidate = with_suffix(path, &n.to_string());
+            if !exists(&candidate).await? {
+                return Ok(candidate);
+            }
+        }
+
+        unreachable!("ran out of numbers")
+    }
+
+    impl ConflictPolicy {
+        /// The path an upload to `path` should be written to, `None` if it
+        /// should be rejected.
+        ///
+        /// The new name is a sibling of `path`, so it stays in the same
+        /// (already sandboxed) directory.
+        ///
+        /// # Errors
+        ///
+        /// Returns an error if the existence of a file can't be checked.
+        pub async fn resolve(self, path: &Path) -> io::Result<Option<PathBuf>> {
+            if !exists(path).await? {
+                return Ok(Some(path.to_owned()));
+            }
+
+            let path = match self {
+                ConflictPolicy::Reject => return Ok(None),
+                ConflictPolicy::Overwrite => path.to_owned(),
+                ConflictPolicy::Rename => first_free(path).await?,
+                ConflictPolicy::Timestamp => {
+                    let now = chrono::Local::now().format("%Y-%m-%d %H.%M.%S").to_string();
+                    let stamped = with_suffix(path, &now);
+
+                    if exists(&stamped).await? {
+                        first_free(&stamped).await?
+                    } else {
+                        stamped
+                    }
+                },
+            };
+
+            Ok(Some(path))
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        pub fn test_with_suffix() {
+            assert_eq!(
+                with_suffix(Path::new("/a/photo.jpg"), "1"),
+                Path::new("/a/photo (1).jpg")
+            );
+            assert_eq!(
+                with_suffix(Path::new("/a/archive.tar.gz"), "2"),
+                Path::new("/a/archive.tar (2).gz")
+            );
+            assert_eq!(
+                with_suffix(Path::new("/a/.bashrc"), "1"),
+                Path::new("/a/.bashrc (1)")
+            );
+        }
+    }
+}
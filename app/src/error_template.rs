@@ -3,22 +3,191 @@ use http::status::StatusCode;
 use leptos::prelude::*;
 #[cfg(feature = "ssr")]
 use leptos_axum::ResponseOptions;
+use serde::{Deserialize, Serialize};
+use server_fn::{
+    codec::JsonEncoding,
+    error::{FromServerFnError, ServerFnErrorErr},
+};
 use thiserror::Error;
 
-#[derive(Clone, Debug, Error)]
+cfg_if! { if #[cfg(feature = "ssr")] {
+    use std::{io, path::Path};
+
+    use axum::response::{IntoResponse, Response};
+    use leptos::logging;
+
+    use crate::{
+        sandbox::SandboxError,
+        shares::ShareError,
+        trash::TrashError,
+        uploads::UploadError,
+        utils::display_os_string,
+    };
+}}
+
+/// Everything that can go wrong handling a request.
+///
+/// Browsers get it as a page through [`ErrorTemplate`], API clients and
+/// server functions as JSON like
+/// `{"error":"conflict","message":"'notes.txt' already exists"}`.
+#[derive(Clone, Debug, PartialEq, Eq, Error, Serialize, Deserialize)]
+#[serde(tag = "error", content = "message", rename_all = "kebab-case")]
 pub enum AppError {
     #[error("Not Found")]
     NotFound,
+    /// The client isn't allowed to do this
+    #[error("{0}")]
+    Forbidden(String),
+    /// A path that can't be inside the shared directory
+    #[error("{0}")]
+    InvalidPath(String),
+    /// Parameters that don't make sense
+    #[error("{0}")]
+    BadRequest(String),
+    /// The target already exists
+    #[error("{0}")]
+    Conflict(String),
+    #[error("{0}")]
+    PayloadTooLarge(String),
+    /// A share link that was used up or expired
+    #[error("{0}")]
+    Gone(String),
+    #[error("{0}")]
+    UnsupportedMediaType(String),
+    /// A feature that's turned off in the config of the server
+    #[error("{0}")]
+    Disabled(String),
+    #[error("{0}")]
+    Io(String),
+    /// Calling a server function failed, e.g. because the server can't be
+    /// reached
+    #[error("{0}")]
+    ServerFn(String),
 }
 
 impl AppError {
     pub fn status_code(&self) -> StatusCode {
         match self {
             AppError::NotFound => StatusCode::NOT_FOUND,
+            AppError::Forbidden(_) | AppError::Disabled(_) => StatusCode::FORBIDDEN,
+            AppError::InvalidPath(_) | AppError::BadRequest(_) => StatusCode::BAD_REQUEST,
+            AppError::Conflict(_) => StatusCode::CONFLICT,
+            AppError::PayloadTooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+            AppError::Gone(_) => StatusCode::GONE,
+            AppError::UnsupportedMediaType(_) => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            AppError::Io(_) | AppError::ServerFn(_) => StatusCode::INTERNAL_SERVER_ERROR,
         }
     }
+
+    /// The error as JSON, the way server functions return it.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap_or_default()
+    }
 }
 
+impl FromServerFnError for AppError {
+    type Encoder = JsonEncoding;
+
+    fn from_server_fn_error(value: ServerFnErrorErr) -> Self {
+        match value {
+            ServerFnErrorErr::Args(_) | ServerFnErrorErr::MissingArg(_) => {
+                AppError::BadRequest(value.to_string())
+            },
+            _ => AppError::ServerFn(value.to_string()),
+        }
+    }
+}
+
+cfg_if! { if #[cfg(feature = "ssr")] {
+    impl AppError {
+        /// `path` can't be used because of `err`, which is logged.
+        pub fn invalid_path(path: &Path, err: SandboxError) -> Self {
+            logging::warn!("Attempt to access invalid path {path:?}: {err}");
+
+            match err {
+                SandboxError::Traversal | SandboxError::Absolute | SandboxError::InvalidName(_) => {
+                    AppError::InvalidPath(format!(
+                        "Invalid path '{}': {err}",
+                        display_os_string(path)
+                    ))
+                },
+                err => err.into(),
+            }
+        }
+    }
+
+    impl From<SandboxError> for AppError {
+        fn from(err: SandboxError) -> Self {
+            match err {
+                SandboxError::Traversal | SandboxError::Absolute | SandboxError::InvalidName(_) => {
+                    AppError::InvalidPath(err.to_string())
+                },
+                SandboxError::Escape => AppError::Forbidden(err.to_string()),
+                SandboxError::NotFound => AppError::NotFound,
+                SandboxError::Io(e) => e.into(),
+            }
+        }
+    }
+
+    impl From<io::Error> for AppError {
+        fn from(err: io::Error) -> Self {
+            match err.kind() {
+                io::ErrorKind::NotFound => AppError::NotFound,
+                io::ErrorKind::AlreadyExists => AppError::Conflict(err.to_string()),
+                _ => AppError::Io(err.to_string()),
+            }
+        }
+    }
+
+    impl From<ShareError> for AppError {
+        fn from(err: ShareError) -> Self {
+            match err {
+                ShareError::Unknown => AppError::NotFound,
+                ShareError::Expired | ShareError::Exhausted => AppError::Gone(err.to_string()),
+                ShareError::WrongPassword => AppError::Forbidden(err.to_string()),
+                ShareError::InvalidPassword(_) => AppError::BadRequest(err.to_string()),
+                ShareError::Sandbox(e) => e.into(),
+                ShareError::Io(e) => e.into(),
+            }
+        }
+    }
+
+    impl From<TrashError> for AppError {
+        fn from(err: TrashError) -> Self {
+            match err {
+                TrashError::Unknown(_) => AppError::NotFound,
+                TrashError::Exists(_) => AppError::Conflict(err.to_string()),
+                TrashError::Sandbox(e) => e.into(),
+                TrashError::Io(e) => e.into(),
+            }
+        }
+    }
+
+    impl From<UploadError> for AppError {
+        fn from(err: UploadError) -> Self {
+            match err {
+                UploadError::Unknown(_) => AppError::NotFound,
+                UploadError::OffsetMismatch { .. } => AppError::Conflict(err.to_string()),
+                UploadError::InvalidKey => AppError::BadRequest(err.to_string()),
+                UploadError::TooLarge => AppError::PayloadTooLarge(err.to_string()),
+                UploadError::Exists => AppError::Conflict(err.to_string()),
+                UploadError::Sandbox(e) => e.into(),
+                UploadError::Io(e) => e.into(),
+            }
+        }
+    }
+
+    /// A plain text response, turned into a page or JSON for the client by
+    /// the server, which finds the error in the response extensions.
+    impl IntoResponse for AppError {
+        fn into_response(self) -> Response {
+            let mut response = (self.status_code(), self.to_string()).into_response();
+            response.extensions_mut().insert(self);
+            response
+        }
+    }
+}}
+
 #[component]
 pub fn ErrorTemplate(
     #[prop(optional)] outside_errors: Option<Errors>,
@@ -54,3 +223,27 @@ pub fn ErrorTemplate(
       </For>
     }
 }
+
+/// A standalone page showing `error`, for responses that don't go through
+/// the router.
+#[cfg(feature = "ssr")]
+pub fn error_page(error: AppError) -> String {
+    Owner::new().with(|| {
+        let mut outside_errors = Errors::default();
+        outside_errors.insert_with_default_key(error);
+
+        let body = view! { <ErrorTemplate outside_errors /> }.to_html();
+
+        format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+  <head>
+    <meta charset="utf-8" />
+    <title>File Share</title>
+    <link rel="stylesheet" href="/pkg/file-share.css" />
+  </head>
+  <body>{body}</body>
+</html>"#
+        )
+    })
+}
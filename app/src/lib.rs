@@ -3,27 +3,51 @@
 
 use std::path::PathBuf;
 
+pub mod auth;
 mod components;
 mod config;
-mod error_template;
+pub mod conflict;
+pub mod error_template;
+#[cfg(feature = "ssr")]
+pub mod metrics;
+pub mod permissions;
+pub mod preview;
+#[cfg(feature = "ssr")]
+pub mod sandbox;
+pub mod search;
 mod server;
+pub mod shares;
 #[cfg(feature = "ssr")]
 mod state;
+pub mod trash;
+#[cfg(feature = "ssr")]
+pub mod uploads;
 pub mod utils;
+#[cfg(feature = "ssr")]
+pub mod watcher;
 
 use leptos::{either::Either, prelude::*};
 use leptos_meta::*;
-use leptos_router::{components::*, hooks::use_params, params::*};
+use leptos_router::{
+    components::*,
+    hooks::{use_params, use_query_map},
+    params::*,
+};
 use leptos_router_macro::path;
-use urlencoding::decode;
 
+#[cfg(feature = "ssr")]
+pub use crate::components::progress;
 pub use crate::config::AppConfig;
 #[cfg(feature = "ssr")]
 pub use crate::state::AppState;
 use crate::{
+    auth::current_user,
     components::*,
     error_template::{AppError, ErrorTemplate},
+    permissions::current_access,
+    preview::find_readme,
     server::*,
+    utils::decode_path,
 };
 
 #[derive(PartialEq, Eq, Params, Debug)]
@@ -35,13 +59,10 @@ struct PathQuery {
 pub fn FilesPage() -> impl IntoView {
     let path_query = use_params::<PathQuery>();
 
-    let path =
-        Memo::new(
-            move |_| match path_query.read().as_ref().map(|query| decode(&query.path)) {
-                Ok(Ok(path)) => PathBuf::from(path.as_ref()),
-                _ => PathBuf::new(),
-            },
-        );
+    let path = Memo::new(move |_| match path_query.read().as_ref() {
+        Ok(query) => decode_path(&query.path),
+        Err(_) => PathBuf::new(),
+    });
 
     let create_folder_action = ServerAction::<NewFolder>::new();
 
@@ -52,27 +73,55 @@ pub fn FilesPage() -> impl IntoView {
 
     let path_signal = Signal::from(path);
 
-    let app_config = expect_context::<AppConfig>();
+    let sorting = use_query_map().with_untracked(Sorting::from_query);
 
-    let upload_bar = app_config.allow_upload.then(|| {
-        view! { <UploadBar path=path_signal create_folder_action=create_folder_action /> }
-    });
+    // islands get the path as JSON, which can't hold names that aren't valid
+    // UTF-8, such folders are only listed
+    let utf8_path = path.with_untracked(|path| path.to_str().is_some());
+
+    let can_write = utf8_path && path.with_untracked(|path| current_access(path).can_write());
+
+    let logout_button = current_user().map(|user| view! { <LogoutButton user=user /> });
 
     view! {
       <div class="p-3 App">
-        {upload_bar}
-        <Breadcrumbs path=path_signal />
-        <div class="grid gap-2 mb-1 border-b grid-cols-(--entry-cols-mobile) border-base-content md:grid-cols-(--entry-cols)">
-          <span></span>
-          <span>Name</span>
-          <span>Size</span>
-          <span class="hidden md:inline">Last Modified</span>
-        </div>
+        {logout_button}
+        <UploadBar
+          path=path_signal
+          create_folder_action=create_folder_action
+          can_write=can_write
+        />
+        {utf8_path
+          .then(|| {
+            view! {
+              <SearchBox path=path.get_untracked() />
+              <LiveUpdates path=path.get_untracked() />
+            }
+          })}
+        <Breadcrumbs path=path_signal query=sorting.to_query() />
+        <EntriesHeader sorting=sorting />
         <Transition fallback=Loading>
           {move || Suspend::new(async move {
             match listing.await {
               Ok(entries) => {
-                Either::Left(view! { <FileEntries path=path_signal entries=entries /> })
+                let readme = match find_readme(&entries) {
+                  Some(name) => {
+                    match preview_file(path.get_untracked().join(&name)).await {
+                      Ok(preview) => Some(view! { <Readme name=name preview=preview /> }),
+                      Err(e) => {
+                        leptos::logging::warn!("Failed to render {name}: {e}");
+                        None
+                      }
+                    }
+                  }
+                  None => None,
+                };
+                Either::Left(
+                  view! {
+                    <FileEntries path=path_signal entries=entries sorting=sorting />
+                    {readme}
+                  },
+                )
               }
               Err(e) => Either::Right(view! { <p class="text-lg">{format!("{e}")}</p> }),
             }
@@ -115,6 +164,11 @@ pub fn App() -> impl IntoView {
           view! { <ErrorTemplate outside_errors /> }.into_view()
         }>
           <Route path=path!("/index/*path") view=FilesPage />
+          <Route path=path!("/preview/*path") view=PreviewPage />
+          <Route path=path!("/login") view=LoginPage />
+          <Route path=path!("/trash") view=TrashPage />
+          <Route path=path!("/shares") view=SharesPage />
+          <Route path=path!("/share/:id") view=SharePage />
         </Routes>
       </Router>
     }
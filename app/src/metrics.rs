@@ -0,0 +1,202 @@
+use std::{
+    collections::BTreeMap,
+    fmt::Write as _,
+    sync::{
+        LazyLock, Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+};
+
+use crate::progress;
+
+/// Bytes of the files served by `/files`
+pub static FILE_BYTES: AtomicU64 = AtomicU64::new(0);
+/// Finished uploads, through the web interface or `/upload`
+pub static UPLOADS: AtomicU64 = AtomicU64::new(0);
+/// Bytes written by uploads, including ones that didn't finish
+pub static UPLOAD_BYTES: AtomicU64 = AtomicU64::new(0);
+/// Calls of `/api/list_dir`, including the ones rendering listings
+pub static LIST_DIR_CALLS: AtomicU64 = AtomicU64::new(0);
+/// Responses that are still being sent
+pub static IN_FLIGHT: AtomicU64 = AtomicU64::new(0);
+
+/// Archives generated by their method
+static ARCHIVES: LazyLock<Mutex<BTreeMap<String, u64>>> = LazyLock::new(Default::default);
+/// Responses with a 4xx or 5xx status by the status
+static ERRORS: LazyLock<Mutex<BTreeMap<u16, u64>>> = LazyLock::new(Default::default);
+
+pub fn add(counter: &AtomicU64, value: u64) {
+    counter.fetch_add(value, Ordering::Relaxed);
+}
+
+pub fn count_archive(method: &str) {
+    if let Ok(mut archives) = ARCHIVES.lock() {
+        *archives.entry(method.to_owned()).or_default() += 1;
+    }
+}
+
+pub fn count_error(status: u16) {
+    if let Ok(mut errors) = ERRORS.lock() {
+        *errors.entry(status).or_default() += 1;
+    }
+}
+
+/// Decrements [`IN_FLIGHT`] when dropped.
+pub struct InFlight(());
+
+impl InFlight {
+    pub fn start() -> Self {
+        add(&IN_FLIGHT, 1);
+        Self(())
+    }
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        IN_FLIGHT.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Append a metric with its help and type lines, and a sample for every
+/// label value, or a single sample without labels when `label` is `None`.
+fn write_metric(
+    out: &mut String,
+    name: &str,
+    kind: &str,
+    help: &str,
+    label: Option<&str>,
+    samples: &[(String, u64)],
+) {
+    _ = writeln!(out, "# HELP {name} {help}");
+    _ = writeln!(out, "# TYPE {name} {kind}");
+
+    for (value, sample) in samples {
+        match label {
+            Some(label) => _ = writeln!(out, "{name}{{{label}=\"{value}\"}} {sample}"),
+            None => _ = writeln!(out, "{name} {sample}"),
+        }
+    }
+}
+
+/// The current values of all metrics in the Prometheus text format.
+pub async fn render() -> String {
+    let single = |counter: &AtomicU64| vec![(String::new(), counter.load(Ordering::Relaxed))];
+
+    let archives = ARCHIVES
+        .lock()
+        .map(|archives| archives.clone().into_iter().collect::<Vec<_>>())
+        .unwrap_or_default();
+    let errors = ERRORS
+        .lock()
+        .map(|errors| {
+            errors
+                .iter()
+                .map(|(status, count)| (status.to_string(), *count))
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+    let progress_entries = progress::active().await as u64;
+
+    let mut out = String::new();
+
+    write_metric(
+        &mut out,
+        "file_share_file_bytes_total",
+        "counter",
+        "Bytes of files served by /files",
+        None,
+        &single(&FILE_BYTES),
+    );
+    write_metric(
+        &mut out,
+        "file_share_archives_total",
+        "counter",
+        "Archives generated, by method",
+        Some("method"),
+        &archives,
+    );
+    write_metric(
+        &mut out,
+        "file_share_uploads_total",
+        "counter",
+        "Finished uploads",
+        None,
+        &single(&UPLOADS),
+    );
+    write_metric(
+        &mut out,
+        "file_share_upload_bytes_total",
+        "counter",
+        "Bytes written by uploads",
+        None,
+        &single(&UPLOAD_BYTES),
+    );
+    write_metric(
+        &mut out,
+        "file_share_list_dir_total",
+        "counter",
+        "Folder listings",
+        None,
+        &single(&LIST_DIR_CALLS),
+    );
+    write_metric(
+        &mut out,
+        "file_share_error_responses_total",
+        "counter",
+        "Responses with a 4xx or 5xx status, by status",
+        Some("status"),
+        &errors,
+    );
+    write_metric(
+        &mut out,
+        "file_share_transfers_in_flight",
+        "gauge",
+        "Responses that are still being sent",
+        None,
+        &single(&IN_FLIGHT),
+    );
+    write_metric(
+        &mut out,
+        "file_share_upload_progress_entries",
+        "gauge",
+        "Uploads with progress being reported",
+        None,
+        &[(String::new(), progress_entries)],
+    );
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn test_write_metric() {
+        let mut out = String::new();
+
+        write_metric(
+            &mut out,
+            "requests_total",
+            "counter",
+            "Requests",
+            Some("status"),
+            &[("404".into(), 3), ("500".into(), 1)],
+        );
+        write_metric(
+            &mut out,
+            "in_flight",
+            "gauge",
+            "Running",
+            None,
+            &[(String::new(), 2)],
+        );
+
+        assert_eq!(
+            out,
+            "# HELP requests_total Requests\n# TYPE requests_total \
+             counter\nrequests_total{status=\"404\"} 3\nrequests_total{status=\"500\"} 1\n# HELP \
+             in_flight Running\n# TYPE in_flight gauge\nin_flight 2\n"
+        );
+    }
+}
@@ -0,0 +1,264 @@
+use std::{
+    fmt,
+    path::{Component, Path, PathBuf},
+    str::FromStr,
+};
+
+use leptos::prelude::expect_context;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    AppConfig,
+    auth::{User, current_user},
+    config::STATE_DIR,
+    error_template::AppError,
+};
+
+/// What a user may do with a path.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum Access {
+    /// The path isn't listed and can't be accessed
+    Hidden,
+    /// Listing and downloading
+    Read,
+    /// Uploading and creating folders on top of reading
+    Write,
+}
+
+impl Access {
+    pub fn can_read(self) -> bool {
+        self >= Access::Read
+    }
+
+    pub fn can_write(self) -> bool {
+        self >= Access::Write
+    }
+}
+
+/// Role of an account from the users file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Role {
+    /// Can only browse and download
+    ReadOnly,
+    /// Can also upload and create folders
+    Uploader,
+    /// Can do anything anywhere, ignoring path rules
+    Admin,
+}
+
+impl FromStr for Role {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "read-only" => Ok(Role::ReadOnly),
+            "uploader" => Ok(Role::Uploader),
+            "admin" => Ok(Role::Admin),
+            _ => Err(format!(
+                "Invalid role '{value}', expected one of read-only, uploader, admin"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Role::ReadOnly => "read-only",
+            Role::Uploader => "uploader",
+            Role::Admin => "admin",
+        };
+        write!(f, "{name}")
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RuleMode {
+    /// Hidden from everyone except admins
+    Hidden,
+    /// Read-only for everyone except admins
+    ReadOnly,
+    /// Writable by everyone
+    Writable,
+}
+
+/// Access rule for a directory and everything below it, written as
+/// `PATH=MODE`, e.g. `/incoming=writable`.
+///
+/// When several rules match a path, the most specific one wins.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PathRule {
+    pub path: PathBuf,
+    pub mode: RuleMode,
+}
+
+impl FromStr for PathRule {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let Some((path, mode)) = value.rsplit_once('=') else {
+            return Err(format!("Invalid rule '{value}', expected PATH=MODE"));
+        };
+
+        let mode = match mode {
+            "hidden" => RuleMode::Hidden,
+            "read-only" => RuleMode::ReadOnly,
+            "writable" => RuleMode::Writable,
+            _ => {
+                return Err(format!(
+                    "Invalid mode '{mode}', expected one of hidden, read-only, writable"
+                ));
+            },
+        };
+
+        // rules are written relative to the shared directory, with or without
+        // the leading slash
+        let mut normalized = PathBuf::new();
+        for component in Path::new(path).components() {
+            match component {
+                Component::Normal(part) => normalized.push(part),
+                Component::RootDir | Component::CurDir => {},
+                Component::ParentDir | Component::Prefix(_) => {
+                    return Err(format!("Invalid rule path '{path}'"));
+                },
+            }
+        }
+
+        Ok(Self {
+            path: normalized,
+            mode,
+        })
+    }
+}
+
+impl fmt::Display for PathRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mode = match self.mode {
+            RuleMode::Hidden => "hidden",
+            RuleMode::ReadOnly => "read-only",
+            RuleMode::Writable => "writable",
+        };
+        write!(f, "/{}={mode}", self.path.display())
+    }
+}
+
+impl AppConfig {
+    /// Access of `user` to `path` (relative to the shared directory).
+    ///
+    /// Users without a role (and everyone when accounts are disabled) get
+    /// write access only when uploads are enabled. The state directory is
+    /// hidden from everyone.
+    pub fn access(&self, user: Option<&User>, path: &Path) -> Access {
+        if path.starts_with(STATE_DIR) {
+            return Access::Hidden;
+        }
+
+        let role = user.and_then(|user| user.role);
+
+        let base = match role {
+            Some(Role::Admin) => return Access::Write,
+            Some(Role::Uploader) => Access::Write,
+            Some(Role::ReadOnly) => Access::Read,
+            None if self.allow_upload => Access::Write,
+            None => Access::Read,
+        };
+
+        let rule = self
+            .rules
+            .iter()
+            .filter(|rule| path.starts_with(&rule.path))
+            .max_by_key(|rule| rule.path.components().count());
+
+        match rule.map(|rule| rule.mode) {
+            None => base,
+            Some(RuleMode::Hidden) => Access::Hidden,
+            Some(RuleMode::ReadOnly) => base.min(Access::Read),
+            Some(RuleMode::Writable) => Access::Write,
+        }
+    }
+
+    /// Whether `user` can change `path` and everything below it, including
+    /// folders with rules of their own.
+    pub fn can_write_tree(&self, user: Option<&User>, path: &Path) -> bool {
+        self.access(user, path).can_write()
+            && self
+                .rules
+                .iter()
+                .filter(|rule| rule.path.starts_with(path))
+                .all(|rule| self.access(user, &rule.path).can_write())
+    }
+
+    /// The error for `user` not being allowed to change something, which is
+    /// [`AppError::Disabled`] when uploads are turned off for them.
+    pub fn write_denied(&self, user: Option<&User>, message: String) -> AppError {
+        if !self.allow_upload && user.and_then(|user| user.role).is_none() {
+            AppError::Disabled(message)
+        } else {
+            AppError::Forbidden(message)
+        }
+    }
+}
+
+/// Access of the user of the current request to `path`.
+pub fn current_access(path: &Path) -> Access {
+    expect_context::<AppConfig>().access(current_user().as_ref(), path)
+}
+
+/// The error for the user of the current request not being allowed to change
+/// something, see [`AppConfig::write_denied`].
+pub fn write_denied(message: String) -> AppError {
+    expect_context::<AppConfig>().write_denied(current_user().as_ref(), message)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::conflict::ConflictPolicy;
+
+    #[test]
+    pub fn test_access() {
+        let config = AppConfig {
+            target_dir: PathBuf::from("/srv/share"),
+            allow_upload: false,
+            rules: [
+                "/incoming=writable",
+                "releases=read-only",
+                "/private=hidden",
+            ]
+            .into_iter()
+            .map(|rule| rule.parse().unwrap())
+            .collect(),
+            on_conflict: ConflictPolicy::Reject,
+            trash_retention: Duration::ZERO,
+        };
+
+        let user = |role| User {
+            name: "user".into(),
+            role: Some(role),
+        };
+        let access = |user: Option<&User>, path: &str| config.access(user, Path::new(path));
+
+        assert_eq!(access(None, ""), Access::Read);
+        assert_eq!(access(None, "incoming/a"), Access::Write);
+        assert_eq!(access(None, "private/a"), Access::Hidden);
+        assert_eq!(access(None, "private2"), Access::Read);
+
+        let uploader = user(Role::Uploader);
+        assert_eq!(access(Some(&uploader), "docs"), Access::Write);
+        assert_eq!(access(Some(&uploader), "releases/v1"), Access::Read);
+
+        let admin = user(Role::Admin);
+        assert_eq!(access(Some(&admin), "private"), Access::Write);
+        assert_eq!(access(Some(&admin), ".file-share/x"), Access::Hidden);
+
+        assert!(config.can_write_tree(Some(&uploader), Path::new("docs")));
+        assert!(!config.can_write_tree(Some(&uploader), Path::new("")));
+        assert!(config.can_write_tree(None, Path::new("incoming")));
+        assert!(!config.can_write_tree(None, Path::new("private")));
+
+        assert!("../up=hidden".parse::<PathRule>().is_err());
+        assert!("/a=secret".parse::<PathRule>().is_err());
+    }
+}
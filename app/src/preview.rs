@@ -0,0 +1,413 @@
+use std::{
+    ffi::OsStr,
+    path::{Component, Path},
+};
+
+use cfg_if::cfg_if;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    server::ServerEntry,
+    utils::{decode_path, encode_path},
+};
+
+cfg_if! { if #[cfg(feature = "ssr")] {
+    use std::sync::LazyLock;
+
+    use pulldown_cmark::{CowStr, Event, Options, Parser, Tag};
+    use syntect::{highlighting::ThemeSet, html::highlighted_html_for_string, parsing::SyntaxSet};
+
+    static SYNTAXES: LazyLock<SyntaxSet> = LazyLock::new(SyntaxSet::load_defaults_newlines);
+    static THEMES: LazyLock<ThemeSet> = LazyLock::new(ThemeSet::load_defaults);
+}}
+
+/// Previews only load this many bytes of a file.
+pub const PREVIEW_LIMIT: u64 = 1024 * 1024;
+
+/// Larger previews are shown without syntax highlighting, which is slow.
+pub const HIGHLIGHT_LIMIT: usize = 256 * 1024;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum PreviewKind {
+    /// Rendered as HTML
+    Markdown,
+    /// Shown as it is, highlighted when the syntax is known
+    Text,
+}
+
+/// A text file rendered as HTML that is safe to show as it is.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct FilePreview {
+    pub kind: PreviewKind,
+    pub html: String,
+    /// Size of the whole file
+    pub size: u64,
+    /// Whether the file is larger than [`PREVIEW_LIMIT`] and only its start
+    /// is shown
+    pub truncated: bool,
+}
+
+const MARKDOWN_EXTENSIONS: [&str; 3] = ["markdown", "md", "mdown"];
+
+const TEXT_EXTENSIONS: [&str; 56] = [
+    "bash",
+    "bat",
+    "c",
+    "cc",
+    "cfg",
+    "conf",
+    "cpp",
+    "cs",
+    "css",
+    "csv",
+    "diff",
+    "env",
+    "fish",
+    "go",
+    "gradle",
+    "h",
+    "hpp",
+    "htm",
+    "html",
+    "ini",
+    "java",
+    "js",
+    "json",
+    "jsx",
+    "kt",
+    "less",
+    "log",
+    "lua",
+    "mjs",
+    "nix",
+    "patch",
+    "php",
+    "pl",
+    "properties",
+    "ps1",
+    "py",
+    "r",
+    "rb",
+    "rs",
+    "rst",
+    "sass",
+    "scss",
+    "sh",
+    "sql",
+    "svelte",
+    "swift",
+    "tex",
+    "toml",
+    "ts",
+    "tsx",
+    "tsv",
+    "txt",
+    "vue",
+    "xml",
+    "yaml",
+    "yml",
+];
+
+/// Files without a telling extension that are text anyway.
+const TEXT_FILENAMES: [&str; 11] = [
+    ".editorconfig",
+    ".env",
+    ".gitattributes",
+    ".gitignore",
+    "changelog",
+    "copying",
+    "dockerfile",
+    "license",
+    "makefile",
+    "procfile",
+    "readme",
+];
+
+/// How a file named `name` is previewed, `None` if it isn't.
+pub fn preview_kind(name: &OsStr) -> Option<PreviewKind> {
+    let name = name.to_str()?.to_ascii_lowercase();
+
+    if TEXT_FILENAMES.contains(&name.as_str()) {
+        return Some(PreviewKind::Text);
+    }
+
+    let extension = Path::new(&name).extension()?.to_str()?;
+
+    if MARKDOWN_EXTENSIONS.contains(&extension) {
+        Some(PreviewKind::Markdown)
+    } else if TEXT_EXTENSIONS.contains(&extension) {
+        Some(PreviewKind::Text)
+    } else {
+        None
+    }
+}
+
+/// Names of the files explaining a folder, shown below its listing, in the
+/// order of preference (ignoring case).
+const README_NAMES: [&str; 2] = ["readme.md", "readme.txt"];
+
+/// The name of the README among `entries`, if there is one.
+pub fn find_readme(entries: &[ServerEntry]) -> Option<String> {
+    README_NAMES.iter().find_map(|readme| {
+        entries.iter().find_map(|entry| match entry {
+            ServerEntry::File { name, .. } if name.eq_ignore_ascii_case(readme) => {
+                Some(name.clone())
+            },
+            _ => None,
+        })
+    })
+}
+
+/// Where a relative link in a Markdown file in `base` points to, `None` for
+/// absolute links and anchors, which work as they are.
+///
+/// Images are served directly, links go to the listing of a folder, the
+/// preview of a file when it has one, or the file itself. Links that climb
+/// above the shared directory go nowhere.
+pub fn resolve_relative_link(base: &Path, url: &str, is_image: bool) -> Option<String> {
+    let has_scheme = url
+        .split_once(':')
+        .is_some_and(|(scheme, _)| !scheme.is_empty() && !scheme.contains(['/', '?', '#']));
+
+    if has_scheme || url.is_empty() || url.starts_with(['/', '#', '?']) {
+        return None;
+    }
+
+    let (target, suffix) = url.split_at(url.find(['?', '#']).unwrap_or(url.len()));
+
+    // the whole path is a single segment of the URL, so the browser can't
+    // resolve `..` in it
+    let mut path = base.to_path_buf();
+    for component in decode_path(target).components() {
+        match component {
+            Component::Normal(part) => path.push(part),
+            Component::CurDir => {},
+            Component::ParentDir => {
+                if !path.pop() {
+                    return Some("#".into());
+                }
+            },
+            Component::RootDir | Component::Prefix(_) => return Some("#".into()),
+        }
+    }
+
+    let route = if is_image {
+        "files"
+    } else if target.ends_with('/') {
+        "index"
+    } else if path
+        .file_name()
+        .is_some_and(|name| preview_kind(name).is_some())
+    {
+        "preview"
+    } else {
+        "files"
+    };
+
+    Some(format!("/{route}/{}{suffix}", encode_path(&path)))
+}
+
+/// Whether following `url` could run a script.
+#[cfg(feature = "ssr")]
+fn is_unsafe_url(url: &str) -> bool {
+    let url = url.trim_start().to_ascii_lowercase();
+
+    ["javascript:", "vbscript:", "data:"]
+        .iter()
+        .any(|scheme| url.starts_with(scheme))
+}
+
+/// Text of a file of which only the first [`PREVIEW_LIMIT`] bytes were read,
+/// without the cut off last line when `truncated`.
+pub fn decode_text(bytes: &[u8], truncated: bool) -> String {
+    let mut text = String::from_utf8_lossy(bytes).into_owned();
+
+    if truncated {
+        if let Some(end) = text.rfind('\n') {
+            text.truncate(end + 1);
+        }
+    }
+
+    text
+}
+
+#[cfg(feature = "ssr")]
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+
+    escaped
+}
+
+/// Render Markdown from a file in `base` as HTML.
+///
+/// Raw HTML is shown as text and links that could run scripts are removed,
+/// so the result can be shown as it is.
+#[cfg(feature = "ssr")]
+pub fn render_markdown(text: &str, base: &Path) -> String {
+    let options = Options::ENABLE_TABLES
+        | Options::ENABLE_STRIKETHROUGH
+        | Options::ENABLE_TASKLISTS
+        | Options::ENABLE_FOOTNOTES;
+
+    let rewrite = |url: CowStr<'_>, is_image| -> CowStr<'static> {
+        if is_unsafe_url(&url) {
+            return CowStr::Borrowed("#");
+        }
+
+        resolve_relative_link(base, &url, is_image)
+            .unwrap_or_else(|| url.to_string())
+            .into()
+    };
+
+    let events = Parser::new_ext(text, options).map(|event| match event {
+        Event::Html(html) | Event::InlineHtml(html) => Event::Text(html),
+        Event::Start(Tag::Link {
+            link_type,
+            dest_url,
+            title,
+            id,
+        }) => Event::Start(Tag::Link {
+            link_type,
+            dest_url: rewrite(dest_url, false),
+            title,
+            id,
+        }),
+        Event::Start(Tag::Image {
+            link_type,
+            dest_url,
+            title,
+            id,
+        }) => Event::Start(Tag::Image {
+            link_type,
+            dest_url: rewrite(dest_url, true),
+            title,
+            id,
+        }),
+        event => event,
+    });
+
+    let mut html = String::new();
+    pulldown_cmark::html::push_html(&mut html, events);
+
+    html
+}
+
+/// Render the text of a file named `name` as HTML, highlighting its syntax
+/// when it's known and the text isn't too large.
+#[cfg(feature = "ssr")]
+pub fn highlight(text: &str, name: &str) -> String {
+    let plain = || format!("<pre>{}</pre>", escape_html(text));
+
+    if text.len() > HIGHLIGHT_LIMIT {
+        return plain();
+    }
+
+    let extension = Path::new(name)
+        .extension()
+        .and_then(OsStr::to_str)
+        .unwrap_or(name);
+
+    let syntax = SYNTAXES
+        .find_syntax_by_extension(extension)
+        .or_else(|| SYNTAXES.find_syntax_by_token(extension))
+        .or_else(|| SYNTAXES.find_syntax_by_first_line(text.lines().next().unwrap_or_default()))
+        .unwrap_or_else(|| SYNTAXES.find_syntax_plain_text());
+
+    highlighted_html_for_string(text, &SYNTAXES, syntax, &THEMES.themes["InspiredGitHub"])
+        .unwrap_or_else(|_| plain())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn test_preview_kind() {
+        let kind = |name: &str| preview_kind(OsStr::new(name));
+
+        assert_eq!(kind("README.md"), Some(PreviewKind::Markdown));
+        assert_eq!(kind("server.LOG"), Some(PreviewKind::Text));
+        assert_eq!(kind("Makefile"), Some(PreviewKind::Text));
+        assert_eq!(kind("photo.jpg"), None);
+        assert_eq!(kind("archive.tar.gz"), None);
+    }
+
+    #[test]
+    pub fn test_find_readme() {
+        let file = |name: &str| ServerEntry::File {
+            name: name.into(),
+            encoded_name: name.into(),
+            size: 0,
+            last_modified: crate::utils::SystemTime(0, 0),
+        };
+
+        assert_eq!(
+            find_readme(&[file("notes.md"), file("readme.txt"), file("README.md")]).as_deref(),
+            Some("README.md")
+        );
+        assert_eq!(
+            find_readme(&[file("Readme.TXT")]).as_deref(),
+            Some("Readme.TXT")
+        );
+        assert_eq!(find_readme(&[file("notes.md")]), None);
+    }
+
+    #[test]
+    pub fn test_resolve_relative_link() {
+        let base = Path::new("docs/guide");
+        let resolve = |url| resolve_relative_link(base, url, false);
+
+        assert_eq!(resolve("https://example.com"), None);
+        assert_eq!(resolve("mailto:someone@example.com"), None);
+        assert_eq!(resolve("#usage"), None);
+        assert_eq!(resolve("/index"), None);
+        assert_eq!(
+            resolve("setup.md#linux").as_deref(),
+            Some("/preview/docs%2Fguide%2Fsetup.md#linux")
+        );
+        assert_eq!(
+            resolve("images/").as_deref(),
+            Some("/index/docs%2Fguide%2Fimages")
+        );
+        assert_eq!(
+            resolve("data.zip").as_deref(),
+            Some("/files/docs%2Fguide%2Fdata.zip")
+        );
+        assert_eq!(
+            resolve("./my%20notes.txt").as_deref(),
+            Some("/preview/docs%2Fguide%2Fmy%20notes.txt")
+        );
+        assert_eq!(
+            resolve("../README.md").as_deref(),
+            Some("/preview/docs%2FREADME.md")
+        );
+        assert_eq!(
+            resolve("../../src/main.rs?raw").as_deref(),
+            Some("/preview/src%2Fmain.rs?raw")
+        );
+        assert_eq!(resolve("../../").as_deref(), Some("/index/"));
+        assert_eq!(resolve("../../../etc/passwd").as_deref(), Some("#"));
+        assert_eq!(resolve("images/../../../../x.png").as_deref(), Some("#"));
+        assert_eq!(
+            resolve_relative_link(Path::new(""), "logo.png", true).as_deref(),
+            Some("/files/logo.png")
+        );
+    }
+
+    #[test]
+    pub fn test_decode_text() {
+        assert_eq!(decode_text(b"first\nsecond\nthi", true), "first\nsecond\n");
+        assert_eq!(decode_text(b"first\nsecond", false), "first\nsecond");
+    }
+}
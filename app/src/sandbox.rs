@@ -0,0 +1,375 @@
+use std::{
+    io,
+    path::{Component, Path, PathBuf},
+};
+
+use thiserror::Error;
+use tokio::fs;
+
+use crate::{AppConfig, auth::User, permissions::Access};
+
+#[derive(Debug, Error)]
+pub enum SandboxError {
+    /// The path contains a `..` component
+    #[error("Path must not contain parent directory references")]
+    Traversal,
+
+    /// The path is absolute or has a prefix (e.g. `C:`)
+    #[error("Path must be relative to the shared directory")]
+    Absolute,
+
+    /// The path resolves (through symlinks) outside of the shared directory
+    #[error("Path escapes the shared directory")]
+    Escape,
+
+    /// A single file name is empty, `.`, `..` or contains a separator
+    #[error("Invalid file name: {0:?}")]
+    InvalidName(String),
+
+    /// The path doesn't exist
+    #[error("Requested path not found")]
+    NotFound,
+
+    /// Any other IO error encountered while resolving the path
+    #[error(transparent)]
+    Io(io::Error),
+}
+
+impl From<io::Error> for SandboxError {
+    fn from(err: io::Error) -> Self {
+        match err.kind() {
+            io::ErrorKind::NotFound => SandboxError::NotFound,
+            _ => SandboxError::Io(err),
+        }
+    }
+}
+
+/// A path inside the shared directory.
+///
+/// Construction only validates the path lexically (no `..`, no absolute
+/// paths), so file names containing dots like `a..b.txt` are fine. Symlinks
+/// are checked when the path is turned into a real filesystem path by
+/// [`SandboxedPath::resolve`] or [`SandboxedPath::resolve_new`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SandboxedPath {
+    root: PathBuf,
+    relative: PathBuf,
+}
+
+impl SandboxedPath {
+    /// The root of the sandbox itself.
+    pub fn root(root: impl Into<PathBuf>) -> Self {
+        Self {
+            root: root.into(),
+            relative: PathBuf::new(),
+        }
+    }
+
+    /// Validate `requested` as a path relative to `root`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the path contains `..` or is absolute.
+    pub fn new(
+        root: impl Into<PathBuf>,
+        requested: impl AsRef<Path>,
+    ) -> Result<Self, SandboxError> {
+        Self::root(root).join(requested)
+    }
+
+    /// Append a relative path.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the path contains `..` or is absolute.
+    pub fn join(&self, requested: impl AsRef<Path>) -> Result<Self, SandboxError> {
+        let mut relative = self.relative.clone();
+
+        for component in requested.as_ref().components() {
+            match component {
+                Component::Normal(part) => relative.push(part),
+                Component::CurDir => {},
+                Component::ParentDir => return Err(SandboxError::Traversal),
+                Component::RootDir | Component::Prefix(_) => return Err(SandboxError::Absolute),
+            }
+        }
+
+        Ok(Self {
+            root: self.root.clone(),
+            relative,
+        })
+    }
+
+    /// Append a single file or folder name, e.g. from an upload or a form.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the name is empty, `.`, `..` or contains a path
+    /// separator.
+    pub fn join_name(&self, name: &str) -> Result<Self, SandboxError> {
+        if name.is_empty()
+            || name == "."
+            || name == ".."
+            || name.contains('\0')
+            || name.chars().any(std::path::is_separator)
+        {
+            return Err(SandboxError::InvalidName(name.to_owned()));
+        }
+
+        Ok(Self {
+            root: self.root.clone(),
+            relative: self.relative.join(name),
+        })
+    }
+
+    /// Path relative to the shared directory.
+    pub fn relative(&self) -> &Path {
+        &self.relative
+    }
+
+    /// Path joined onto the shared directory, without resolving symlinks.
+    pub fn full(&self) -> PathBuf {
+        self.root.join(&self.relative)
+    }
+
+    pub fn is_root(&self) -> bool {
+        self.relative.as_os_str().is_empty()
+    }
+
+    /// Resolve an existing path, following symlinks.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the path doesn't exist or resolves outside of the
+    /// shared directory.
+    pub async fn resolve(&self) -> Result<PathBuf, SandboxError> {
+        let root = fs::canonicalize(&self.root).await?;
+        let path = fs::canonicalize(self.full()).await?;
+
+        if path.starts_with(&root) {
+            Ok(path)
+        } else {
+            Err(SandboxError::Escape)
+        }
+    }
+
+    /// Where `resolved`, returned by one of the `resolve` methods, really is
+    /// relative to the shared directory. It differs from
+    /// [`SandboxedPath::relative`] when the path goes through symlinks.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `resolved` is outside of the shared directory.
+    pub async fn real_relative(&self, resolved: &Path) -> Result<PathBuf, SandboxError> {
+        let root = fs::canonicalize(&self.root).await?;
+
+        resolved
+            .strip_prefix(&root)
+            .map(Path::to_owned)
+            .map_err(|_| SandboxError::Escape)
+    }
+
+    /// Resolve an existing path without following a symlink in its last
+    /// component, for operations on the entry itself (renaming, deleting).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the path doesn't exist or its parent resolves
+    /// outside of the shared directory.
+    pub async fn resolve_entry(&self) -> Result<PathBuf, SandboxError> {
+        let Some(name) = self.relative.file_name() else {
+            return self.resolve().await;
+        };
+
+        let parent = Self {
+            root: self.root.clone(),
+            relative: self.relative.parent().unwrap_or(Path::new("")).to_owned(),
+        };
+
+        let path = parent.resolve().await?.join(name);
+        fs::symlink_metadata(&path).await?;
+
+        Ok(path)
+    }
+
+    /// Resolve a path that is about to be created.
+    ///
+    /// The deepest existing ancestor is resolved and checked, the rest is
+    /// appended as is. A dangling symlink anywhere in the missing part is
+    /// rejected, since writing through it could create a file outside of the
+    /// shared directory.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the path resolves outside of the shared directory.
+    pub async fn resolve_new(&self) -> Result<PathBuf, SandboxError> {
+        let root = fs::canonicalize(&self.root).await?;
+
+        let mut existing = self.relative.as_path();
+        let mut missing = Vec::new();
+
+        let mut path = loop {
+            let full = self.root.join(existing);
+
+            match fs::canonicalize(&full).await {
+                Ok(path) => break path,
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                    if fs::symlink_metadata(&full).await.is_ok() {
+                        return Err(SandboxError::Escape);
+                    }
+
+                    let Some(name) = existing.file_name() else {
+                        return Err(SandboxError::NotFound);
+                    };
+
+                    missing.push(name);
+                    existing = existing.parent().unwrap_or(Path::new(""));
+                },
+                Err(e) => return Err(e.into()),
+            }
+        };
+
+        if !path.starts_with(&root) {
+            return Err(SandboxError::Escape);
+        }
+
+        path.extend(missing.into_iter().rev());
+
+        Ok(path)
+    }
+}
+
+impl AppConfig {
+    /// The shared directory as a sandbox root.
+    pub fn sandbox(&self) -> SandboxedPath {
+        SandboxedPath::root(&self.target_dir)
+    }
+
+    /// Access of `user` to `path`, whose real location after following
+    /// symlinks is `resolved`. It's the lower of the access to both, so that a
+    /// symlink can't lead around the rule of its target.
+    pub async fn real_access(
+        &self,
+        user: Option<&User>,
+        path: &SandboxedPath,
+        resolved: &Path,
+    ) -> Access {
+        let access = self.access(user, path.relative());
+
+        match path.real_relative(resolved).await {
+            Ok(real) => access.min(self.access(user, &real)),
+            Err(_) => Access::Hidden,
+        }
+    }
+
+    /// Resolve an existing `path` that `user` can read, where it really is
+    /// included.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the path doesn't exist or resolves outside of the
+    /// shared directory. Paths `user` can't read are indistinguishable from
+    /// missing ones.
+    pub async fn resolve_readable(
+        &self,
+        user: Option<&User>,
+        path: &SandboxedPath,
+    ) -> Result<PathBuf, SandboxError> {
+        if !self.access(user, path.relative()).can_read() {
+            return Err(SandboxError::NotFound);
+        }
+
+        let resolved = path.resolve().await?;
+
+        if self.real_access(user, path, &resolved).await.can_read() {
+            Ok(resolved)
+        } else {
+            Err(SandboxError::NotFound)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn test_join() {
+        let root = SandboxedPath::root("/srv/share");
+
+        assert_eq!(root.join("a/b").unwrap().relative(), Path::new("a/b"));
+        assert_eq!(
+            root.join("./a..b.txt").unwrap().relative(),
+            Path::new("a..b.txt")
+        );
+        assert!(root.join("").unwrap().is_root());
+
+        assert!(matches!(root.join("a/../b"), Err(SandboxError::Traversal)));
+        assert!(matches!(root.join(".."), Err(SandboxError::Traversal)));
+        assert!(matches!(root.join("/etc"), Err(SandboxError::Absolute)));
+    }
+
+    #[test]
+    pub fn test_join_name() {
+        let root = SandboxedPath::root("/srv/share");
+
+        assert_eq!(
+            root.join_name("..hidden").unwrap().full(),
+            Path::new("/srv/share/..hidden")
+        );
+
+        for name in ["", ".", "..", "a/b", "/etc"] {
+            assert!(matches!(
+                root.join_name(name),
+                Err(SandboxError::InvalidName(_))
+            ));
+        }
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    pub async fn test_resolve_readable_symlink() {
+        use std::{os::unix::fs::symlink, time::Duration};
+
+        use crate::conflict::ConflictPolicy;
+
+        let root = std::env::temp_dir().join(format!("file-share-sandbox-{}", std::process::id()));
+        _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(root.join("private")).unwrap();
+        std::fs::create_dir_all(root.join("public")).unwrap();
+        std::fs::write(root.join("private/secret.txt"), "secret").unwrap();
+        std::fs::write(root.join("public/notes.txt"), "notes").unwrap();
+        symlink(root.join("private"), root.join("public/private")).unwrap();
+        symlink(root.join("public/notes.txt"), root.join("notes.txt")).unwrap();
+
+        let config = AppConfig {
+            target_dir: root.clone(),
+            allow_upload: false,
+            rules: vec!["/private=hidden".parse().unwrap()],
+            on_conflict: ConflictPolicy::Reject,
+            trash_retention: Duration::ZERO,
+        };
+        let resolve = async |path: &str| {
+            config
+                .resolve_readable(None, &config.sandbox().join(path).unwrap())
+                .await
+        };
+
+        assert!(resolve("notes.txt").await.is_ok());
+        assert!(resolve("public/notes.txt").await.is_ok());
+        assert!(matches!(
+            resolve("private/secret.txt").await,
+            Err(SandboxError::NotFound)
+        ));
+        assert!(matches!(
+            resolve("public/private").await,
+            Err(SandboxError::NotFound)
+        ));
+        assert!(matches!(
+            resolve("public/private/secret.txt").await,
+            Err(SandboxError::NotFound)
+        ));
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,223 @@
+use std::path::PathBuf;
+
+use cfg_if::cfg_if;
+use serde::{Deserialize, Serialize};
+
+use crate::utils::SystemTime;
+
+cfg_if! { if #[cfg(feature = "ssr")] {
+    use std::{path::Path, sync::Arc};
+
+    use async_walkdir::{Filtering, WalkDir};
+    use futures::{Stream, StreamExt as _};
+
+    use crate::{AppConfig, auth::User};
+}}
+
+/// Searches stop after this many hits.
+pub const MAX_RESULTS: usize = 1000;
+
+/// A file or folder found by a search.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct SearchHit {
+    /// Folder containing the hit, relative to the shared directory
+    pub folder: PathBuf,
+    pub name: String,
+    /// `None` for folders
+    pub size: Option<u64>,
+    pub last_modified: SystemTime,
+}
+
+/// What a file name has to match, ignoring case.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Pattern {
+    /// `*` matches any number of characters, `?` exactly one
+    Glob(Vec<char>),
+    /// The query has to appear anywhere in the name
+    Substring(String),
+}
+
+impl Pattern {
+    /// A glob when the query contains `*` or `?`, a substring otherwise.
+    pub fn new(query: &str) -> Self {
+        let query = query.to_lowercase();
+
+        if query.contains(['*', '?']) {
+            Pattern::Glob(query.chars().collect())
+        } else {
+            Pattern::Substring(query)
+        }
+    }
+
+    pub fn matches(&self, name: &str) -> bool {
+        let name = name.to_lowercase();
+
+        match self {
+            Pattern::Substring(query) => name.contains(query),
+            Pattern::Glob(pattern) => glob_matches(pattern, &name.chars().collect::<Vec<_>>()),
+        }
+    }
+}
+
+fn glob_matches(pattern: &[char], name: &[char]) -> bool {
+    let (mut p, mut n) = (0, 0);
+    // position of the last `*` and the part of the name it swallowed so far,
+    // a mismatch retries with it swallowing one more character
+    let mut star = None;
+
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, n));
+                p += 1;
+            },
+            Some('?') => {
+                p += 1;
+                n += 1;
+            },
+            Some(c) if *c == name[n] => {
+                p += 1;
+                n += 1;
+            },
+            _ => match star {
+                Some((star_p, star_n)) => {
+                    p = star_p + 1;
+                    n = star_n + 1;
+                    star = Some((star_p, star_n + 1));
+                },
+                None => return false,
+            },
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// Everything a hit has to match.
+#[cfg(feature = "ssr")]
+#[derive(Debug, Clone)]
+pub struct SearchFilter {
+    pub pattern: Pattern,
+    /// Size limits in bytes, folders never match when one is set
+    pub min_size: Option<u64>,
+    pub max_size: Option<u64>,
+    /// Modification time limits, the end is exclusive
+    pub modified_after: Option<SystemTime>,
+    pub modified_before: Option<SystemTime>,
+}
+
+#[cfg(feature = "ssr")]
+impl SearchFilter {
+    fn matches_metadata(&self, size: Option<u64>, last_modified: SystemTime) -> bool {
+        let size_matches = match size {
+            Some(size) => {
+                self.min_size.is_none_or(|min| size >= min)
+                    && self.max_size.is_none_or(|max| size <= max)
+            },
+            None => self.min_size.is_none() && self.max_size.is_none(),
+        };
+
+        size_matches
+            && self
+                .modified_after
+                .is_none_or(|after| last_modified >= after)
+            && self
+                .modified_before
+                .is_none_or(|before| last_modified < before)
+    }
+}
+
+/// Search `dir` recursively for entries matching `filter`, skipping
+/// everything `user` can't read. `root` is the resolved shared directory.
+#[cfg(feature = "ssr")]
+pub fn search(
+    app_config: AppConfig,
+    user: Option<User>,
+    root: PathBuf,
+    dir: &Path,
+    filter: SearchFilter,
+) -> impl Stream<Item = SearchHit> + Send + 'static {
+    // the path relative to the shared directory, if it's readable
+    let readable = Arc::new(move |path: &Path| {
+        path.strip_prefix(&root)
+            .ok()
+            .filter(|relative| app_config.access(user.as_ref(), relative).can_read())
+            .map(Path::to_owned)
+    });
+
+    let filter_readable = readable.clone();
+
+    WalkDir::new(dir)
+        .filter(move |entry| {
+            let readable = filter_readable.clone();
+            async move {
+                if readable(&entry.path()).is_some() {
+                    Filtering::Continue
+                } else {
+                    Filtering::IgnoreDir
+                }
+            }
+        })
+        .filter_map(move |entry| {
+            let readable = readable.clone();
+            let filter = filter.clone();
+            async move {
+                let entry = entry.ok()?;
+                // hits are sent as JSON, which can't hold paths that aren't
+                // valid UTF-8
+                let name = entry.file_name().into_string().ok()?;
+
+                if !filter.pattern.matches(&name) {
+                    return None;
+                }
+
+                // symlinks are neither, they are only followed by listings
+                let metadata = entry.metadata().await.ok()?;
+                let size = if metadata.is_file() {
+                    Some(metadata.len())
+                } else if metadata.is_dir() {
+                    None
+                } else {
+                    return None;
+                };
+
+                let last_modified = metadata.modified().ok()?.into();
+
+                if !filter.matches_metadata(size, last_modified) {
+                    return None;
+                }
+
+                let relative =
+                    readable(&entry.path()).filter(|relative| relative.to_str().is_some())?;
+
+                Some(SearchHit {
+                    folder: relative.parent().map(Path::to_owned).unwrap_or_default(),
+                    name,
+                    size,
+                    last_modified,
+                })
+            }
+        })
+        .take(MAX_RESULTS)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn test_pattern() {
+        let matches = |query, name| Pattern::new(query).matches(name);
+
+        assert!(matches("report", "Annual Report 2024.pdf"));
+        assert!(!matches("report", "summary.pdf"));
+
+        assert!(matches("*.PDF", "report.pdf"));
+        assert!(!matches("*.pdf", "report.pdf.bak"));
+        assert!(matches("img_????.jpg", "IMG_0042.jpg"));
+        assert!(!matches("img_????.jpg", "IMG_042.jpg"));
+        assert!(matches("a*b*c", "aXbYbZc"));
+        assert!(matches("*", ""));
+        assert!(!matches("?", ""));
+    }
+}
@@ -1,71 +1,119 @@
-use std::path::PathBuf;
+use std::{ffi::OsString, path::PathBuf};
 
 cfg_if! { if #[cfg(feature = "ssr")] {
-    use leptos::logging::warn;
+    use std::{io, path::Path};
+
+    use leptos::logging::{log, warn};
     use tokio::fs;
 
-    use crate::config::AppConfig;
+    use crate::{
+        auth::current_user,
+        config::AppConfig,
+        metrics,
+        permissions::{Access, current_access, write_denied},
+        sandbox::{SandboxError, SandboxedPath},
+        trash,
+        utils::{display_os_string, encode_path},
+    };
 }}
 
 use cfg_if::cfg_if;
 use leptos::prelude::*;
 use serde::{Deserialize, Serialize};
 
-use crate::utils::SystemTime;
+use crate::{
+    error_template::AppError,
+    utils::{SystemTime, decode_path},
+};
 
 pub type Entries = Vec<ServerEntry>;
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, PartialOrd, Ord, Eq)]
 pub enum ServerEntry {
     Folder {
+        /// Name for display, with invalid UTF-8 replaced
         name: String,
+        /// The exact name, encoded by [`crate::utils::encode_path`]
+        encoded_name: String,
         last_modified: SystemTime,
     },
     File {
+        /// Name for display, with invalid UTF-8 replaced
         name: String,
+        /// The exact name, encoded by [`crate::utils::encode_path`]
+        encoded_name: String,
         size: u64,
         last_modified: SystemTime,
     },
 }
 
+impl ServerEntry {
+    /// The exact name, even if it isn't valid UTF-8.
+    pub fn os_name(&self) -> OsString {
+        match self {
+            ServerEntry::Folder { encoded_name, .. } | ServerEntry::File { encoded_name, .. } => {
+                decode_path(encoded_name).into_os_string()
+            },
+        }
+    }
+}
+
 #[server(name = ListDir, prefix = "/api", endpoint = "list_dir")]
-pub async fn list_dir(path: PathBuf) -> Result<Entries, ServerFnError> {
-    let base_path = expect_context::<AppConfig>().target_dir;
+pub async fn list_dir(path: PathBuf) -> Result<Entries, AppError> {
+    metrics::add(&metrics::LIST_DIR_CALLS, 1);
 
-    let Ok(path) = base_path.join(&path).canonicalize() else {
-        warn!("Attempt to access invalid path: {path:?}");
-        return Err(ServerFnError::ServerError(
-            "Requested path not found".into(),
-        ));
+    let app_config = expect_context::<AppConfig>();
+    let user = current_user();
+
+    let dir = match app_config.sandbox().join(&path) {
+        Ok(dir) => dir,
+        Err(e) => return Err(invalid_path(&path, &e)),
     };
 
-    if !path.starts_with(&base_path) {
-        warn!("Attempt to access forbidden path: {path:?}");
-        return Err(ServerFnError::ServerError(
-            "Requested path not found".into(),
-        ));
-    }
+    let resolved = match app_config.resolve_readable(user.as_ref(), &dir).await {
+        Ok(resolved) => resolved,
+        Err(e) => return Err(invalid_path(&path, &e)),
+    };
 
     let mut entries = Vec::new();
 
-    let mut directory = fs::read_dir(path).await?;
+    let mut directory = fs::read_dir(resolved).await?;
 
     while let Some(entry) = directory.next_entry().await? {
-        let name = entry
-            .file_name()
-            .into_string()
-            .expect("Filename is valid UTF-8");
+        let os_name = entry.file_name();
+        let name = display_os_string(&os_name);
+        let encoded_name = encode_path(&os_name);
+
+        let target = dir.join(&os_name)?;
+
+        if !current_access(target.relative()).can_read() {
+            continue;
+        }
+
+        // hide symlinks pointing outside of the shared directory or to paths
+        // the user can't read
+        if entry.file_type().await?.is_symlink()
+            && app_config
+                .resolve_readable(user.as_ref(), &target)
+                .await
+                .is_err()
+        {
+            continue;
+        }
+
         let metadata = entry.metadata().await?;
         let last_modified = metadata.modified()?.into();
 
         if metadata.is_dir() {
             entries.push(ServerEntry::Folder {
                 name,
+                encoded_name,
                 last_modified,
             });
         } else if metadata.is_file() {
             entries.push(ServerEntry::File {
                 name,
+                encoded_name,
                 size: metadata.len(),
                 last_modified,
             });
@@ -75,17 +123,237 @@ pub async fn list_dir(path: PathBuf) -> Result<Entries, ServerFnError> {
     Ok(entries)
 }
 
+/// Listings don't tell why a path can't be used, so that they don't reveal
+/// what exists outside of the shared directory.
+#[cfg(feature = "ssr")]
+fn invalid_path(path: &Path, err: &SandboxError) -> AppError {
+    warn!("Attempt to access invalid path {path:?}: {err}");
+    AppError::NotFound
+}
+
 #[server(name = NewFolder, prefix = "/api", endpoint = "new_folder")]
-pub async fn new_folder(name: String, path: PathBuf) -> Result<(), ServerFnError> {
-    let app_config = expect_context::<AppConfig>();
+pub async fn new_folder(name: String, path: PathBuf) -> Result<(), AppError> {
+    let path = expect_context::<AppConfig>()
+        .sandbox()
+        .join(path)?
+        .join_name(&name)?;
+
+    if !current_access(path.relative()).can_write() {
+        return Err(write_denied("Creating folders here is not allowed".into()));
+    }
+
+    let resolved = path.resolve_new().await?;
 
-    if !app_config.allow_upload {
-        return Err(ServerFnError::ServerError("Uploads are disabled".into()));
+    if !real_access(&path, &resolved).await.can_write() {
+        return Err(write_denied("Creating folders here is not allowed".into()));
     }
 
-    let path = app_config.target_dir.join(path).join(name);
+    fs::create_dir(resolved).await?;
+
+    Ok(())
+}
+
+/// Access of the current user to `path`, limited by where its symlinks lead
+/// to, see [`AppConfig::real_access`].
+#[cfg(feature = "ssr")]
+async fn real_access(path: &SandboxedPath, resolved: &Path) -> Access {
+    expect_context::<AppConfig>()
+        .real_access(current_user().as_ref(), path, resolved)
+        .await
+}
+
+/// `name` in `path`, if the current user can see it.
+#[cfg(feature = "ssr")]
+pub fn visible_entry(path: &Path, name: &str) -> Result<SandboxedPath, AppError> {
+    let entry = expect_context::<AppConfig>()
+        .sandbox()
+        .join(path)?
+        .join_name(name)?;
+
+    if !current_access(entry.relative()).can_read() {
+        return Err(invalid_path(entry.relative(), &SandboxError::NotFound));
+    }
+
+    Ok(entry)
+}
+
+/// Whether the current user can change `entry` and everything below it,
+/// including folders with rules of their own.
+#[cfg(feature = "ssr")]
+pub fn can_write_tree(entry: &SandboxedPath) -> bool {
+    expect_context::<AppConfig>().can_write_tree(current_user().as_ref(), entry.relative())
+}
+
+#[cfg(feature = "ssr")]
+fn not_allowed(action: &str, entry: &SandboxedPath) -> AppError {
+    write_denied(format!(
+        "{action} '{}' is not allowed",
+        display_os_string(entry.relative())
+    ))
+}
+
+/// Resolve the target of a rename, move or copy, which must not exist yet.
+#[cfg(feature = "ssr")]
+async fn resolve_target(to: &SandboxedPath) -> Result<PathBuf, AppError> {
+    if !current_access(to.relative()).can_write() {
+        return Err(not_allowed("Writing to", to));
+    }
+
+    // symlinks count even when dangling
+    if fs::symlink_metadata(to.full()).await.is_ok() {
+        return Err(AppError::Conflict(format!(
+            "'{}' already exists",
+            display_os_string(to.relative())
+        )));
+    }
+
+    let target = to.resolve_new().await?;
+
+    if !real_access(to, &target).await.can_write() {
+        return Err(not_allowed("Writing to", to));
+    }
+
+    Ok(target)
+}
+
+#[cfg(feature = "ssr")]
+async fn move_entry_to(from: &SandboxedPath, to: &SandboxedPath) -> Result<(), AppError> {
+    if !can_write_tree(from) {
+        return Err(not_allowed("Moving", from));
+    }
+
+    // the rules below the target apply to what's moved there
+    if !can_write_tree(to) {
+        return Err(not_allowed("Moving to", to));
+    }
+
+    let source = from.resolve_entry().await?;
+    let target = resolve_target(to).await?;
+
+    if target.starts_with(&source) {
+        return Err(AppError::BadRequest(
+            "A folder can't be moved into itself".into(),
+        ));
+    }
+
+    match fs::rename(&source, &target).await {
+        Ok(()) => {},
+        Err(e) if e.kind() == io::ErrorKind::CrossesDevices => {
+            return Err(AppError::BadRequest(
+                "Moving between filesystems isn't supported, copy and delete instead".into(),
+            ));
+        },
+        Err(e) => return Err(e.into()),
+    }
+
+    log!("Moved {source:?} to {target:?}");
+
+    Ok(())
+}
+
+/// Copy a file, or a folder with everything in it the current user can
+/// read. Symlinks inside of folders are skipped rather than followed.
+#[cfg(feature = "ssr")]
+async fn copy_tree(from: &SandboxedPath, source: &Path, target: &Path) -> io::Result<()> {
+    if !fs::metadata(source).await?.is_dir() {
+        fs::copy(source, target).await?;
+        return Ok(());
+    }
+
+    let mut folders = vec![(from.clone(), source.to_owned(), target.to_owned())];
+
+    while let Some((folder, source, target)) = folders.pop() {
+        fs::create_dir(&target).await?;
+
+        let mut entries = fs::read_dir(&source).await?;
+
+        while let Some(entry) = entries.next_entry().await? {
+            let name = entry.file_name();
+
+            let Ok(child) = folder.join(&name) else {
+                continue;
+            };
+
+            if !current_access(child.relative()).can_read() {
+                continue;
+            }
+
+            let file_type = entry.file_type().await?;
+
+            if file_type.is_dir() {
+                folders.push((child, entry.path(), target.join(&name)));
+            } else if file_type.is_file() {
+                fs::copy(entry.path(), target.join(&name)).await?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Rename `name` in `path` to `new_name`.
+#[server(name = RenameEntry, prefix = "/api", endpoint = "rename")]
+pub async fn rename_entry(path: PathBuf, name: String, new_name: String) -> Result<(), AppError> {
+    let from = visible_entry(&path, &name)?;
+    let to = expect_context::<AppConfig>()
+        .sandbox()
+        .join(&path)?
+        .join_name(&new_name)?;
+
+    move_entry_to(&from, &to).await
+}
+
+/// Move `name` in `path` into the folder `destination`.
+#[server(name = MoveEntry, prefix = "/api", endpoint = "move")]
+pub async fn move_entry(path: PathBuf, name: String, destination: PathBuf) -> Result<(), AppError> {
+    let from = visible_entry(&path, &name)?;
+    let to = expect_context::<AppConfig>()
+        .sandbox()
+        .join(destination)?
+        .join_name(&name)?;
+
+    move_entry_to(&from, &to).await
+}
+
+/// Copy `name` in `path` into the folder `destination`, folders with all of
+/// their contents.
+#[server(name = CopyEntry, prefix = "/api", endpoint = "copy")]
+pub async fn copy_entry(path: PathBuf, name: String, destination: PathBuf) -> Result<(), AppError> {
+    let from = visible_entry(&path, &name)?;
+    let to = expect_context::<AppConfig>()
+        .sandbox()
+        .join(destination)?
+        .join_name(&name)?;
+
+    let source = expect_context::<AppConfig>()
+        .resolve_readable(current_user().as_ref(), &from)
+        .await?;
+    let target = resolve_target(&to).await?;
+
+    if target.starts_with(&source) {
+        return Err(AppError::BadRequest(
+            "A folder can't be copied into itself".into(),
+        ));
+    }
+
+    copy_tree(&from, &source, &target).await?;
+
+    log!("Copied {source:?} to {target:?}");
+
+    Ok(())
+}
+
+/// Move `name` in `path` to the trash, from where it can be restored until
+/// it's purged.
+#[server(name = DeleteEntry, prefix = "/api", endpoint = "delete")]
+pub async fn delete_entry(path: PathBuf, name: String) -> Result<(), AppError> {
+    let entry = visible_entry(&path, &name)?;
+
+    if !can_write_tree(&entry) {
+        return Err(not_allowed("Deleting", &entry));
+    }
 
-    fs::create_dir(path).await?;
+    trash::move_to_trash(&expect_context::<AppConfig>(), &entry).await?;
 
     Ok(())
 }
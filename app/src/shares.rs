@@ -0,0 +1,394 @@
+use std::path::PathBuf;
+
+use cfg_if::cfg_if;
+use serde::{Deserialize, Serialize};
+
+use crate::utils::SystemTime;
+
+cfg_if! { if #[cfg(feature = "ssr")] {
+    use std::{
+        collections::HashMap,
+        io,
+        net::IpAddr,
+        path::Path,
+        sync::{Arc, LazyLock},
+        time::{self, Duration},
+    };
+
+    use leptos::logging;
+    use rand_core::{OsRng, RngCore as _};
+    use thiserror::Error;
+    use tokio::{fs, sync::Mutex};
+
+    use crate::{
+        AppConfig,
+        auth::users::{hash_password, verify_password},
+        sandbox::{SandboxError, SandboxedPath},
+    };
+
+    /// One lock per link, held while it's read and written back, so that
+    /// concurrent downloads are all counted.
+    static LOCKS: LazyLock<Mutex<HashMap<String, Arc<Mutex<()>>>>> =
+        LazyLock::new(Default::default);
+
+    /// When each client's download of a link was last counted, their
+    /// requests for the rest of the file are free for [`RESUME_WINDOW`].
+    static COUNTED: LazyLock<Mutex<HashMap<(String, IpAddr), time::Instant>>> =
+        LazyLock::new(Default::default);
+
+    const RESUME_WINDOW: Duration = Duration::from_secs(24 * 60 * 60);
+}}
+
+/// A link giving anyone who has it access to a single file or folder,
+/// without an account.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ShareLink {
+    /// Random and unguessable, the link is `/share/<id>`
+    pub id: String,
+    /// The shared entry, relative to the shared directory
+    pub path: PathBuf,
+    pub is_folder: bool,
+    pub created: SystemTime,
+    pub expires: Option<SystemTime>,
+    pub max_downloads: Option<u64>,
+    pub downloads: u64,
+    pub has_password: bool,
+}
+
+impl ShareLink {
+    /// Path of the page the link leads to.
+    pub fn href(&self) -> String {
+        format!("/share/{}", self.id)
+    }
+
+    pub fn downloads_left(&self) -> Option<u64> {
+        self.max_downloads
+            .map(|max| max.saturating_sub(self.downloads))
+    }
+}
+
+/// A [`ShareLink`] as it's stored, in `shares/<id>.json` in the state
+/// directory.
+#[cfg(feature = "ssr")]
+#[derive(Debug, Serialize, Deserialize)]
+struct StoredLink {
+    #[serde(flatten)]
+    link: ShareLink,
+    /// Argon2 hash of the password, never sent to clients
+    password_hash: Option<String>,
+}
+
+#[cfg(feature = "ssr")]
+#[derive(Debug, Error)]
+pub enum ShareError {
+    /// The link was revoked, cleaned up or never existed
+    #[error("This link doesn't exist or was revoked")]
+    Unknown,
+
+    /// The expiry time of the link passed
+    #[error("This link has expired")]
+    Expired,
+
+    /// The link was downloaded as many times as allowed
+    #[error("This link reached its download limit")]
+    Exhausted,
+
+    /// The password is missing or doesn't match
+    #[error("Wrong password")]
+    WrongPassword,
+
+    /// The password can't be hashed, e.g. because it's too long
+    #[error("Invalid password\ncaused by: {0}")]
+    InvalidPassword(String),
+
+    #[error(transparent)]
+    Sandbox(#[from] SandboxError),
+
+    #[error(transparent)]
+    Io(#[from] io::Error),
+}
+
+#[cfg(feature = "ssr")]
+fn shares_dir(app_config: &AppConfig) -> PathBuf {
+    app_config.state_dir().join("shares")
+}
+
+/// File of the link `id`, which comes straight from a request.
+#[cfg(feature = "ssr")]
+fn link_file(app_config: &AppConfig, id: &str) -> Result<PathBuf, ShareError> {
+    if id.is_empty() || !id.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(ShareError::Unknown);
+    }
+
+    Ok(shares_dir(app_config).join(id).with_extension("json"))
+}
+
+#[cfg(feature = "ssr")]
+async fn read_link(file: &Path) -> io::Result<StoredLink> {
+    let json = fs::read(file).await?;
+    serde_json::from_slice(&json).map_err(io::Error::other)
+}
+
+/// The link `id` as it's stored.
+#[cfg(feature = "ssr")]
+async fn read_stored(app_config: &AppConfig, id: &str) -> Result<StoredLink, ShareError> {
+    match read_link(&link_file(app_config, id)?).await {
+        Ok(stored) => Ok(stored),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Err(ShareError::Unknown),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// The lock of the link `id`.
+#[cfg(feature = "ssr")]
+async fn link_lock(id: &str) -> Arc<Mutex<()>> {
+    let mut locks = LOCKS.lock().await;
+
+    // forget the locks of links nobody is using
+    locks.retain(|_, lock| Arc::strong_count(lock) > 1);
+
+    locks.entry(id.to_owned()).or_default().clone()
+}
+
+#[cfg(feature = "ssr")]
+async fn write_link(app_config: &AppConfig, stored: &StoredLink) -> Result<(), ShareError> {
+    let file = link_file(app_config, &stored.link.id)?;
+    let json = serde_json::to_vec(stored).map_err(io::Error::other)?;
+
+    // renamed into place, so that a link is never read half written
+    let partial = file.with_extension("partial");
+    fs::write(&partial, json).await?;
+    fs::rename(&partial, &file).await?;
+
+    Ok(())
+}
+
+/// Share `entry`, until `expires_in` passes or it was downloaded
+/// `max_downloads` times if given, requiring `password` if there is one.
+///
+/// # Errors
+///
+/// Returns an error if the entry doesn't exist or the link can't be stored.
+#[cfg(feature = "ssr")]
+pub async fn create(
+    app_config: &AppConfig,
+    entry: &SandboxedPath,
+    expires_in: Option<Duration>,
+    max_downloads: Option<u64>,
+    password: Option<&str>,
+) -> Result<ShareLink, ShareError> {
+    let resolved = entry.resolve().await?;
+    let now = time::SystemTime::now();
+
+    let password_hash = password
+        .map(hash_password)
+        .transpose()
+        .map_err(|e| ShareError::InvalidPassword(e.to_string()))?;
+
+    let link = ShareLink {
+        id: format!("{:016x}{:016x}", OsRng.next_u64(), OsRng.next_u64()),
+        path: entry.relative().to_owned(),
+        is_folder: fs::metadata(&resolved).await?.is_dir(),
+        created: now.into(),
+        // too far in the future to be represented means never
+        expires: expires_in
+            .and_then(|duration| now.checked_add(duration))
+            .map(Into::into),
+        max_downloads,
+        downloads: 0,
+        has_password: password_hash.is_some(),
+    };
+
+    fs::create_dir_all(shares_dir(app_config)).await?;
+    write_link(
+        app_config,
+        &StoredLink {
+            link: link.clone(),
+            password_hash,
+        },
+    )
+    .await?;
+
+    logging::log!("[{}]\tshared {resolved:?}", link.id);
+
+    Ok(link)
+}
+
+/// Why `link` can't be used anymore, if it can't.
+#[cfg(feature = "ssr")]
+fn check_active(link: &ShareLink) -> Result<(), ShareError> {
+    let now = SystemTime::from(time::SystemTime::now());
+
+    if link.expires.is_some_and(|expires| expires <= now) {
+        Err(ShareError::Expired)
+    } else if link.downloads_left() == Some(0) {
+        Err(ShareError::Exhausted)
+    } else {
+        Ok(())
+    }
+}
+
+/// All links, including the expired ones not cleaned up yet, most recently
+/// created first.
+///
+/// # Errors
+///
+/// Returns an error if the links can't be read.
+#[cfg(feature = "ssr")]
+pub async fn list(app_config: &AppConfig) -> io::Result<Vec<ShareLink>> {
+    let mut links = Vec::new();
+
+    let mut dir = match fs::read_dir(shares_dir(app_config)).await {
+        Ok(dir) => dir,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(links),
+        Err(e) => return Err(e),
+    };
+
+    while let Some(entry) = dir.next_entry().await? {
+        let path = entry.path();
+
+        if path.extension().is_none_or(|extension| extension != "json") {
+            continue;
+        }
+
+        match read_link(&path).await {
+            Ok(stored) => links.push(stored.link),
+            Err(e) => logging::warn!("Skipping broken share link {path:?}: {e}"),
+        }
+    }
+
+    links.sort_unstable_by(|a, b| b.created.cmp(&a.created));
+
+    Ok(links)
+}
+
+/// Look up the link `id`, which has to be usable.
+///
+/// # Errors
+///
+/// Returns an error if there's no such link, or it expired or ran out of
+/// downloads.
+#[cfg(feature = "ssr")]
+pub async fn get(app_config: &AppConfig, id: &str) -> Result<ShareLink, ShareError> {
+    let link = read_stored(app_config, id).await?.link;
+
+    check_active(&link)?;
+
+    Ok(link)
+}
+
+/// Check the `password` of the link `id` and count a download of it.
+///
+/// A request that `resumes` a download isn't counted when the download of
+/// the same `client` was counted recently, so that resuming or seeking
+/// doesn't use up the link.
+///
+/// # Errors
+///
+/// Returns an error if the link can't be used or the password is wrong.
+#[cfg(feature = "ssr")]
+pub async fn redeem(
+    app_config: &AppConfig,
+    id: &str,
+    password: Option<&str>,
+    client: Option<IpAddr>,
+    resumes: bool,
+) -> Result<ShareLink, ShareError> {
+    // checked without the lock, hashing is slow and guessing passwords
+    // mustn't hold up the downloads of others
+    let checked = read_stored(app_config, id).await?;
+    check_active(&checked.link)?;
+
+    if let Some(hash) = &checked.password_hash {
+        let password = password.ok_or(ShareError::WrongPassword)?;
+
+        if !verify_password(hash, password).await {
+            return Err(ShareError::WrongPassword);
+        }
+    }
+
+    let key = client.map(|client| (id.to_owned(), client));
+
+    if let (true, Some(key)) = (resumes, &key) {
+        let recent = COUNTED
+            .lock()
+            .await
+            .get(key)
+            .is_some_and(|counted| counted.elapsed() < RESUME_WINDOW);
+
+        if recent {
+            return Ok(checked.link);
+        }
+    }
+
+    let lock = link_lock(id).await;
+    let _lock = lock.lock().await;
+
+    // the link may have been used up or revoked in the meantime
+    let mut stored = read_stored(app_config, id).await?;
+    check_active(&stored.link)?;
+
+    if stored.password_hash != checked.password_hash {
+        return Err(ShareError::WrongPassword);
+    }
+
+    stored.link.downloads += 1;
+    write_link(app_config, &stored).await?;
+
+    if let Some(key) = key {
+        COUNTED.lock().await.insert(key, time::Instant::now());
+    }
+
+    Ok(stored.link)
+}
+
+/// Delete the link `id`, it stops working immediately.
+///
+/// # Errors
+///
+/// Returns an error if there's no such link or it can't be deleted.
+#[cfg(feature = "ssr")]
+pub async fn revoke(app_config: &AppConfig, id: &str) -> Result<(), ShareError> {
+    let file = link_file(app_config, id)?;
+
+    let lock = link_lock(id).await;
+    let _lock = lock.lock().await;
+
+    match fs::remove_file(&file).await {
+        Ok(()) => {},
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Err(ShareError::Unknown),
+        Err(e) => return Err(e.into()),
+    }
+
+    logging::log!("[{id}]\trevoked share link");
+
+    Ok(())
+}
+
+/// Delete links that expired or ran out of downloads, and forget downloads
+/// that can't be resumed for free anymore.
+#[cfg(feature = "ssr")]
+pub async fn cleanup(app_config: &AppConfig) {
+    COUNTED
+        .lock()
+        .await
+        .retain(|_, counted| counted.elapsed() < RESUME_WINDOW);
+
+    let links = match list(app_config).await {
+        Ok(links) => links,
+        Err(e) => {
+            logging::warn!("Failed to list the share links: {e}");
+            return;
+        },
+    };
+
+    for link in links {
+        if check_active(&link).is_ok() {
+            continue;
+        }
+
+        if let Err(e) = revoke(app_config, &link.id).await {
+            logging::warn!("[{}]\tfailed to delete used up share link: {e}", link.id);
+        }
+    }
+}
@@ -1,10 +1,23 @@
 use axum::extract::FromRef;
-use leptos::prelude::LeptosOptions;
+use leptos::prelude::{LeptosOptions, provide_context};
 
-use crate::AppConfig;
+use crate::{AppConfig, auth::Auth};
 
 #[derive(FromRef, Clone, Debug)]
 pub struct AppState {
     pub app_config: AppConfig,
     pub leptos_options: LeptosOptions,
+    pub auth: Option<Auth>,
+}
+
+impl AppState {
+    /// Provide the contexts used by server functions and server-side
+    /// rendering.
+    pub fn provide_contexts(&self) {
+        provide_context(self.app_config.clone());
+
+        if let Some(auth) = &self.auth {
+            provide_context(auth.clone());
+        }
+    }
 }
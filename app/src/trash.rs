@@ -0,0 +1,241 @@
+use std::path::PathBuf;
+
+use cfg_if::cfg_if;
+use serde::{Deserialize, Serialize};
+
+use crate::utils::SystemTime;
+
+cfg_if! { if #[cfg(feature = "ssr")] {
+    use std::{io, path::Path, time};
+
+    use leptos::logging;
+    use rand_core::{OsRng, RngCore as _};
+    use thiserror::Error;
+    use tokio::fs;
+
+    use crate::{
+        AppConfig,
+        sandbox::{SandboxError, SandboxedPath},
+        utils::display_os_string,
+    };
+
+    /// Name of the deleted entry inside of its trash directory
+    const ITEM: &str = "item";
+    /// Name of the [`TrashEntry`] describing it
+    const INFO: &str = "info.json";
+}}
+
+/// An entry in the trash.
+///
+/// Every deleted entry gets its own directory `trash/<id>/` in the state
+/// directory, holding the entry itself and this description of it.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct TrashEntry {
+    pub id: String,
+    /// Where the entry was deleted from, relative to the shared directory
+    pub original: PathBuf,
+    pub deleted: SystemTime,
+    pub is_folder: bool,
+}
+
+#[cfg(feature = "ssr")]
+#[derive(Debug, Error)]
+pub enum TrashError {
+    /// The entry was restored, purged or never existed
+    #[error("Unknown trash entry '{0}'")]
+    Unknown(String),
+
+    /// Something was created in place of the entry since it was deleted
+    #[error("'{0}' already exists")]
+    Exists(String),
+
+    #[error(transparent)]
+    Sandbox(#[from] SandboxError),
+
+    #[error(transparent)]
+    Io(#[from] io::Error),
+}
+
+#[cfg(feature = "ssr")]
+fn trash_dir(app_config: &AppConfig) -> PathBuf {
+    app_config.state_dir().join("trash")
+}
+
+/// Directory of the entry `id`, which comes straight from a request.
+#[cfg(feature = "ssr")]
+fn entry_dir(app_config: &AppConfig, id: &str) -> Result<PathBuf, TrashError> {
+    if id.is_empty() || !id.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(TrashError::Unknown(id.to_owned()));
+    }
+
+    Ok(trash_dir(app_config).join(id))
+}
+
+#[cfg(feature = "ssr")]
+async fn read_info(dir: &Path) -> io::Result<TrashEntry> {
+    let info = fs::read(dir.join(INFO)).await?;
+    serde_json::from_slice(&info).map_err(io::Error::other)
+}
+
+/// Move `entry` into the trash.
+///
+/// # Errors
+///
+/// Returns an error if the entry doesn't exist or can't be moved, e.g.
+/// because it's on another filesystem than the shared directory.
+#[cfg(feature = "ssr")]
+pub async fn move_to_trash(
+    app_config: &AppConfig,
+    entry: &SandboxedPath,
+) -> Result<TrashEntry, TrashError> {
+    let resolved = entry.resolve_entry().await?;
+
+    let info = TrashEntry {
+        id: format!("{:016x}", OsRng.next_u64()),
+        original: entry.relative().to_owned(),
+        deleted: time::SystemTime::now().into(),
+        // a symlink to a folder is trashed itself, not what it points to
+        is_folder: fs::symlink_metadata(&resolved).await?.is_dir(),
+    };
+
+    let dir = trash_dir(app_config).join(&info.id);
+    fs::create_dir_all(&dir).await?;
+
+    let result = async {
+        let json = serde_json::to_vec(&info).map_err(io::Error::other)?;
+        fs::write(dir.join(INFO), json).await?;
+        fs::rename(&resolved, dir.join(ITEM)).await
+    }
+    .await;
+
+    if let Err(e) = result {
+        _ = fs::remove_dir_all(&dir).await;
+        return Err(e.into());
+    }
+
+    logging::log!("[{}]\ttrashed {resolved:?}", info.id);
+
+    Ok(info)
+}
+
+/// All entries in the trash, most recently deleted first.
+///
+/// # Errors
+///
+/// Returns an error if the trash directory can't be read.
+#[cfg(feature = "ssr")]
+pub async fn list(app_config: &AppConfig) -> io::Result<Vec<TrashEntry>> {
+    let mut entries = Vec::new();
+
+    let mut dir = match fs::read_dir(trash_dir(app_config)).await {
+        Ok(dir) => dir,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(entries),
+        Err(e) => return Err(e),
+    };
+
+    while let Some(entry) = dir.next_entry().await? {
+        match read_info(&entry.path()).await {
+            Ok(info) => entries.push(info),
+            Err(e) => logging::warn!("Skipping broken trash entry {:?}: {e}", entry.path()),
+        }
+    }
+
+    entries.sort_unstable_by(|a, b| b.deleted.cmp(&a.deleted));
+
+    Ok(entries)
+}
+
+/// Look up the entry `id`.
+///
+/// # Errors
+///
+/// Returns an error if there's no such entry.
+#[cfg(feature = "ssr")]
+pub async fn get(app_config: &AppConfig, id: &str) -> Result<TrashEntry, TrashError> {
+    match read_info(&entry_dir(app_config, id)?).await {
+        Ok(info) => Ok(info),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Err(TrashError::Unknown(id.to_owned())),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Move the entry `id` back to where it was deleted from, recreating missing
+/// parent folders. Returns the restored path.
+///
+/// # Errors
+///
+/// Returns an error if there's no such entry or its original path is taken.
+#[cfg(feature = "ssr")]
+pub async fn restore(app_config: &AppConfig, id: &str) -> Result<PathBuf, TrashError> {
+    let info = get(app_config, id).await?;
+    let dir = entry_dir(app_config, id)?;
+
+    let target = app_config.sandbox().join(&info.original)?;
+
+    if fs::symlink_metadata(target.full()).await.is_ok() {
+        return Err(TrashError::Exists(display_os_string(&info.original)));
+    }
+
+    let resolved = target.resolve_new().await?;
+
+    if let Some(parent) = resolved.parent() {
+        fs::create_dir_all(parent).await?;
+    }
+
+    fs::rename(dir.join(ITEM), &resolved).await?;
+    fs::remove_dir_all(&dir).await?;
+
+    logging::log!("[{id}]\trestored {resolved:?}");
+
+    Ok(resolved)
+}
+
+/// Delete the entry `id` for good.
+///
+/// # Errors
+///
+/// Returns an error if there's no such entry or it can't be deleted.
+#[cfg(feature = "ssr")]
+pub async fn purge(app_config: &AppConfig, id: &str) -> Result<(), TrashError> {
+    let dir = entry_dir(app_config, id)?;
+
+    match fs::remove_dir_all(&dir).await {
+        Ok(()) => {},
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            return Err(TrashError::Unknown(id.to_owned()));
+        },
+        Err(e) => return Err(e.into()),
+    }
+
+    logging::log!("[{id}]\tpurged from the trash");
+
+    Ok(())
+}
+
+/// Purge entries deleted more than the configured retention period ago.
+#[cfg(feature = "ssr")]
+pub async fn cleanup(app_config: &AppConfig) {
+    let entries = match list(app_config).await {
+        Ok(entries) => entries,
+        Err(e) => {
+            logging::warn!("Failed to list the trash: {e}");
+            return;
+        },
+    };
+
+    let now = chrono::Utc::now();
+
+    for entry in entries {
+        let expired = (now - chrono::DateTime::from(entry.deleted))
+            .to_std()
+            .is_ok_and(|age| age > app_config.trash_retention);
+
+        if !expired {
+            continue;
+        }
+
+        if let Err(e) = purge(app_config, &entry.id).await {
+            logging::warn!("[{}]\tfailed to purge expired trash entry: {e}", entry.id);
+        }
+    }
+}
@@ -0,0 +1,474 @@
+use std::{
+    collections::HashMap,
+    fmt::Write as _,
+    io,
+    path::{Path, PathBuf},
+    sync::{Arc, LazyLock},
+    time::{Duration, SystemTime},
+};
+
+use futures::{Stream, StreamExt as _};
+use leptos::logging;
+use rand_core::{OsRng, RngCore as _};
+use sha2::{Digest as _, Sha256};
+use thiserror::Error;
+use tokio::{
+    fs::{self, OpenOptions},
+    io::AsyncWriteExt as _,
+    sync::Mutex,
+};
+
+use crate::{
+    AppConfig,
+    conflict::ConflictPolicy,
+    metrics,
+    sandbox::{SandboxError, SandboxedPath},
+};
+
+/// Unfinished uploads untouched for this long are deleted.
+pub const PARTIAL_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+#[derive(Debug, Error)]
+pub enum UploadError {
+    /// The upload was finished, cleaned up or never started
+    #[error("Unknown upload '{0}'")]
+    Unknown(String),
+
+    /// The chunk doesn't start where the last committed one ended
+    #[error("Chunk starts at offset {got}, expected {expected}")]
+    OffsetMismatch { expected: u64, got: u64 },
+
+    /// The key the client identifies the file with is empty or too long
+    #[error("Invalid upload key")]
+    InvalidKey,
+
+    /// The chunk goes past the announced size of the file
+    #[error("Chunk exceeds the size of the upload")]
+    TooLarge,
+
+    /// A file with the name of the upload already exists and the conflict
+    /// policy is to reject it
+    #[error("File already exists")]
+    Exists,
+
+    #[error(transparent)]
+    Sandbox(#[from] SandboxError),
+
+    #[error(transparent)]
+    Io(#[from] io::Error),
+}
+
+struct Upload {
+    target: SandboxedPath,
+    size: u64,
+    policy: ConflictPolicy,
+    partial: PathBuf,
+}
+
+/// Uploads in progress, each behind its own lock so chunks of one file are
+/// written one at a time.
+static UPLOADS: LazyLock<Mutex<HashMap<String, Arc<Mutex<Upload>>>>> =
+    LazyLock::new(Default::default);
+
+/// Size and time of the uploads that were moved to their targets, for
+/// clients that didn't get the response to their last chunk.
+static COMPLETED: LazyLock<Mutex<HashMap<String, (u64, SystemTime)>>> =
+    LazyLock::new(Default::default);
+
+fn partial_dir(app_config: &AppConfig) -> PathBuf {
+    app_config.state_dir().join("partial")
+}
+
+fn temp_dir(app_config: &AppConfig) -> PathBuf {
+    app_config.state_dir().join("tmp")
+}
+
+/// Longest key a client may identify a file with.
+const MAX_KEY_LEN: usize = 128;
+
+/// Id of an upload, stable across restarts and upgrades so that a client can
+/// resume by starting the same upload again.
+///
+/// The `key` the client identifies the file with is part of it, so another
+/// file of the same name and size never continues the upload of this one.
+fn upload_id(target: &SandboxedPath, size: u64, key: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(target.relative().as_os_str().as_encoded_bytes());
+    hasher.update(size.to_le_bytes());
+    hasher.update(key.as_bytes());
+
+    hasher.finalize()[..8]
+        .iter()
+        .fold(String::new(), |mut id, byte| {
+            let _ = write!(id, "{byte:02x}");
+            id
+        })
+}
+
+/// Start a new upload of `size` bytes to `target`, or resume an existing one
+/// that was started with the same `key`.
+///
+/// Returns the id of the upload and the number of bytes already committed.
+///
+/// # Errors
+///
+/// Returns an error if the key is invalid, the partial file can't be
+/// created, the target is outside of the shared directory or it exists and
+/// `policy` rejects it.
+pub async fn begin(
+    app_config: &AppConfig,
+    target: SandboxedPath,
+    size: u64,
+    key: &str,
+    policy: ConflictPolicy,
+) -> Result<(String, u64), UploadError> {
+    if key.is_empty() || key.len() > MAX_KEY_LEN {
+        return Err(UploadError::InvalidKey);
+    }
+
+    let resolved = target.resolve_new().await?;
+
+    let id = upload_id(&target, size, key);
+    let dir = partial_dir(app_config);
+    let partial = dir.join(&id);
+
+    // the client is resuming an upload it lost track of, wait until its
+    // last chunk is done
+    let running = UPLOADS.lock().await.get(&id).cloned();
+    let _running = match &running {
+        Some(upload) => Some(upload.lock().await),
+        None => None,
+    };
+
+    // fail early rather than after uploading the whole file, resumed uploads
+    // were already checked when they started
+    let resuming = running.is_some() || fs::try_exists(&partial).await?;
+    if !resuming && policy.resolve(&resolved).await?.is_none() {
+        return Err(UploadError::Exists);
+    }
+
+    // the same file is uploaded again
+    COMPLETED.lock().await.remove(&id);
+
+    fs::create_dir_all(&dir).await?;
+
+    let file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&partial)
+        .await?;
+
+    let mut offset = file.metadata().await?.len();
+
+    if offset > size {
+        logging::warn!("[{id}]\tpartial file is larger than the upload, restarting");
+        file.set_len(0).await?;
+        offset = 0;
+    }
+
+    drop(file);
+
+    let upload = Upload {
+        target,
+        size,
+        policy,
+        partial,
+    };
+
+    if offset == size {
+        finish(&id, &upload).await?;
+    } else if running.is_none() {
+        UPLOADS
+            .lock()
+            .await
+            .insert(id.clone(), Arc::new(Mutex::new(upload)));
+    }
+
+    Ok((id, offset))
+}
+
+/// The offset to continue the upload `id` from, for a client that lost track
+/// of it. Unlike [`begin`], this never starts the upload over, so completed
+/// uploads report their whole size.
+///
+/// # Errors
+///
+/// Returns an error if the upload isn't running and didn't complete
+/// recently, or its partial file can't be read.
+pub async fn resume(id: &str) -> Result<u64, UploadError> {
+    let running = UPLOADS.lock().await.get(id).cloned();
+
+    if let Some(upload) = running {
+        // wait for the chunk being written
+        let upload = upload.lock().await;
+
+        if let Some((size, _)) = COMPLETED.lock().await.get(id) {
+            return Ok(*size);
+        }
+
+        return Ok(fs::metadata(&upload.partial).await?.len());
+    }
+
+    COMPLETED
+        .lock()
+        .await
+        .get(id)
+        .map(|(size, _)| *size)
+        .ok_or_else(|| UploadError::Unknown(id.to_owned()))
+}
+
+/// The target of a running upload, for permission checks.
+pub async fn target(id: &str) -> Result<SandboxedPath, UploadError> {
+    let upload = UPLOADS
+        .lock()
+        .await
+        .get(id)
+        .cloned()
+        .ok_or_else(|| UploadError::Unknown(id.to_owned()))?;
+
+    Ok(upload.lock().await.target.clone())
+}
+
+/// Append a chunk starting at `offset` to an upload, calling `on_write` with
+/// the length of every piece written.
+///
+/// The chunk is committed as a whole: if the stream fails midway, the partial
+/// file is truncated back to `offset`. Once the last byte arrives, the file
+/// is moved to its target.
+///
+/// Returns the new committed offset.
+///
+/// # Errors
+///
+/// Returns an error if the upload doesn't exist, the offset doesn't match,
+/// the chunk is too large or writing fails.
+pub async fn append<S, B, E>(
+    id: &str,
+    offset: u64,
+    mut chunk: S,
+    mut on_write: impl AsyncFnMut(usize),
+) -> Result<u64, UploadError>
+where
+    S: Stream<Item = Result<B, E>> + Unpin,
+    B: AsRef<[u8]>,
+    E: std::error::Error + Send + Sync + 'static,
+{
+    let upload = UPLOADS
+        .lock()
+        .await
+        .get(id)
+        .cloned()
+        .ok_or_else(|| UploadError::Unknown(id.to_owned()))?;
+
+    let upload = upload.lock().await;
+
+    let mut file = OpenOptions::new()
+        .append(true)
+        .open(&upload.partial)
+        .await?;
+
+    let committed = file.metadata().await?.len();
+
+    if committed != offset {
+        return Err(UploadError::OffsetMismatch {
+            expected: committed,
+            got: offset,
+        });
+    }
+
+    let result: Result<u64, UploadError> = async {
+        let mut written = offset;
+
+        while let Some(bytes) = chunk.next().await {
+            let bytes = bytes.map_err(io::Error::other)?;
+            let bytes = bytes.as_ref();
+
+            written += bytes.len() as u64;
+            if written > upload.size {
+                return Err(UploadError::TooLarge);
+            }
+
+            file.write_all(bytes).await?;
+            metrics::add(&metrics::UPLOAD_BYTES, bytes.len() as u64);
+            on_write(bytes.len()).await;
+        }
+
+        file.sync_data().await?;
+
+        Ok(written)
+    }
+    .await;
+
+    let written = match result {
+        Ok(written) => written,
+        Err(e) => {
+            file.set_len(offset).await?;
+            return Err(e);
+        },
+    };
+
+    drop(file);
+
+    if written == upload.size {
+        finish(id, &upload).await?;
+    }
+
+    Ok(written)
+}
+
+/// Move a complete partial file to its target.
+async fn finish(id: &str, upload: &Upload) -> Result<(), UploadError> {
+    let target = upload.target.resolve_new().await?;
+
+    // someone else might have uploaded a file with the same name meanwhile
+    let resolved = upload.policy.resolve(&target).await?;
+
+    UPLOADS.lock().await.remove(id);
+
+    let Some(target) = resolved else {
+        fs::remove_file(&upload.partial).await?;
+        return Err(UploadError::Exists);
+    };
+
+    persist(&upload.partial, &target).await?;
+    COMPLETED
+        .lock()
+        .await
+        .insert(id.to_owned(), (upload.size, SystemTime::now()));
+    metrics::add(&metrics::UPLOADS, 1);
+    logging::log!("[{id}]\tfinished: {target:?}");
+
+    Ok(())
+}
+
+/// Move a finished file from the state directory to `target`.
+async fn persist(from: &Path, target: &Path) -> io::Result<()> {
+    // the state directory can be on another filesystem than a symlinked
+    // target directory
+    if fs::rename(from, target).await.is_err() {
+        fs::copy(from, target).await?;
+        fs::remove_file(from).await?;
+    }
+
+    Ok(())
+}
+
+/// Stream a whole file into a temporary file and move it to `target` once
+/// it's complete and synced to disk, calling `on_write` with the length of
+/// every piece written.
+///
+/// When `target` exists, `policy` decides where the file goes. A failed
+/// upload leaves nothing behind. Returns the final path and the size of the
+/// file.
+///
+/// # Errors
+///
+/// Returns an error if `target` exists and `policy` rejects it, the stream
+/// fails or writing fails.
+pub async fn write_file<S, B, E>(
+    app_config: &AppConfig,
+    target: &Path,
+    policy: ConflictPolicy,
+    mut content: S,
+    mut on_write: impl AsyncFnMut(usize),
+) -> Result<(PathBuf, u64), UploadError>
+where
+    S: Stream<Item = Result<B, E>> + Unpin,
+    B: AsRef<[u8]>,
+    E: std::error::Error + Send + Sync + 'static,
+{
+    if policy.resolve(target).await?.is_none() {
+        return Err(UploadError::Exists);
+    }
+
+    let dir = temp_dir(app_config);
+    fs::create_dir_all(&dir).await?;
+
+    let temp = dir.join(format!("{:016x}", OsRng.next_u64()));
+
+    let result: Result<(PathBuf, u64), UploadError> = async {
+        let mut file = OpenOptions::new()
+            .create_new(true)
+            .write(true)
+            .open(&temp)
+            .await?;
+
+        let mut written = 0;
+
+        while let Some(bytes) = content.next().await {
+            let bytes = bytes.map_err(io::Error::other)?;
+            let bytes = bytes.as_ref();
+
+            file.write_all(bytes).await?;
+            written += bytes.len() as u64;
+            metrics::add(&metrics::UPLOAD_BYTES, bytes.len() as u64);
+            on_write(bytes.len()).await;
+        }
+
+        file.sync_all().await?;
+        drop(file);
+
+        // checked again, the upload might have taken a while
+        let target = policy.resolve(target).await?.ok_or(UploadError::Exists)?;
+        persist(&temp, &target).await?;
+        metrics::add(&metrics::UPLOADS, 1);
+
+        Ok((target, written))
+    }
+    .await;
+
+    if result.is_err() {
+        // the temp file might not even exist, nothing to do about other errors
+        _ = fs::remove_file(&temp).await;
+    }
+
+    result
+}
+
+/// Delete partial and temporary files that weren't written to for
+/// [`PARTIAL_TTL`].
+pub async fn cleanup(app_config: &AppConfig) {
+    let now = SystemTime::now();
+
+    COMPLETED.lock().await.retain(|_, (_, completed)| {
+        now.duration_since(*completed)
+            .is_ok_and(|age| age <= PARTIAL_TTL)
+    });
+
+    for dir in [partial_dir(app_config), temp_dir(app_config)] {
+        let Ok(mut entries) = fs::read_dir(dir).await else {
+            continue;
+        };
+
+        while let Ok(Some(entry)) = entries.next_entry().await {
+            let modified = entry.metadata().await.and_then(|meta| meta.modified());
+
+            let expired = modified
+                .ok()
+                .and_then(|modified| now.duration_since(modified).ok())
+                .is_some_and(|age| age > PARTIAL_TTL);
+
+            if !expired {
+                continue;
+            }
+
+            let id = entry.file_name().to_string_lossy().into_owned();
+
+            // don't pull the file from under a chunk that's being written
+            let mut uploads = UPLOADS.lock().await;
+            if uploads
+                .get(&id)
+                .is_some_and(|upload| upload.try_lock().is_err())
+            {
+                continue;
+            }
+            uploads.remove(&id);
+            drop(uploads);
+
+            match fs::remove_file(entry.path()).await {
+                Ok(()) => logging::log!("[{id}]\tremoved abandoned upload"),
+                Err(e) => logging::warn!("[{id}]\tfailed to remove abandoned upload: {e}"),
+            }
+        }
+    }
+}
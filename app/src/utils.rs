@@ -1,8 +1,6 @@
-use std::{
-    borrow::Cow,
-    time::{self, UNIX_EPOCH},
-};
+use std::time::{self, UNIX_EPOCH};
 
+use cfg_if::cfg_if;
 use chrono::{DateTime, TimeZone, Utc};
 use chrono_humanize::Humanize;
 use leptos::prelude::IntoRender;
@@ -50,25 +48,71 @@ impl SystemTime {
     }
 }
 
-use std::{ffi::OsStr, path::Path};
+use std::{
+    ffi::OsStr,
+    path::{Path, PathBuf},
+};
 
 pub fn display_os_string(str: impl AsRef<OsStr>) -> String {
     str.as_ref().to_string_lossy().to_string()
 }
 
+/// Percent-encode a path for a URL. The bytes of names that aren't valid
+/// UTF-8 are encoded as they are, so that [`decode_path`] gets them back
+/// exactly.
 pub fn encode_path(path: impl AsRef<OsStr>) -> String {
-    urlencoding::encode(path.as_ref().to_string_lossy().as_ref()).into_owned()
+    urlencoding::encode_binary(path.as_ref().as_encoded_bytes()).into_owned()
+}
+
+/// Decode a path encoded by [`encode_path`]. Where the OS doesn't allow
+/// arbitrary bytes in names, invalid UTF-8 is replaced.
+pub fn decode_path(encoded: &str) -> PathBuf {
+    let bytes = urlencoding::decode_binary(encoded.as_bytes()).into_owned();
+
+    cfg_if! { if #[cfg(target_family = "unix")] {
+        use std::{ffi::OsString, os::unix::ffi::OsStringExt as _};
+
+        PathBuf::from(OsString::from_vec(bytes))
+    } else {
+        PathBuf::from(String::from_utf8_lossy(&bytes).into_owned())
+    }}
 }
 
-pub fn try_decode_path(path: &str) -> Cow<'_, str> {
-    urlencoding::decode(path).unwrap_or(Cow::Borrowed(path))
+pub fn format_folder_href(base_path: &Path, name: impl AsRef<OsStr>) -> String {
+    format!("/index/{}", encode_path(base_path.join(name.as_ref())))
 }
-pub fn format_folder_href(base_path: &Path, name: &str) -> String {
-    format!("/index/{}", encode_path(base_path.join(name)))
+
+pub fn format_file_href(base_path: &Path, name: impl AsRef<OsStr>) -> String {
+    format!("/files/{}", encode_path(base_path.join(name.as_ref())))
 }
 
-pub fn format_file_href(base_path: &Path, name: &str) -> String {
-    format!("/files/{}", encode_path(base_path.join(name)))
+pub fn format_preview_href(base_path: &Path, name: impl AsRef<OsStr>) -> String {
+    format!("/preview/{}", encode_path(base_path.join(name.as_ref())))
+}
+
+pub fn format_thumbnail_href(base_path: &Path, name: impl AsRef<OsStr>) -> String {
+    format!("/thumbnail/{}", encode_path(base_path.join(name.as_ref())))
+}
+
+/// `Content-Disposition` value for downloading a file named `file_name`.
+///
+/// Old clients get an ASCII approximation of the name, everything else the
+/// exact one in the RFC 5987 `filename*` parameter.
+pub fn attachment_disposition(file_name: &str) -> String {
+    let fallback = file_name
+        .chars()
+        .map(|c| match c {
+            ' ' => c,
+            '"' | '\\' => '_',
+            c if c.is_ascii_graphic() => c,
+            _ => '_',
+        })
+        .collect::<String>();
+
+    format!(
+        r#"attachment; filename="{fallback}"; filename*=UTF-8''{}"#,
+        urlencoding::encode(file_name)
+    )
 }
 
 #[allow(clippy::cast_possible_truncation)]
@@ -117,4 +161,33 @@ mod tests {
 
         assert_eq!(format_bytes(u64::MAX), "16 EB");
     }
+
+    #[test]
+    pub fn test_encode_path() {
+        let path = Path::new("folder/a b%.txt");
+        assert_eq!(encode_path(path), "folder%2Fa%20b%25.txt");
+        assert_eq!(decode_path(&encode_path(path)), path);
+
+        #[cfg(target_family = "unix")]
+        {
+            use std::os::unix::ffi::OsStrExt as _;
+
+            // "café" in Latin-1
+            let latin1 = Path::new(OsStr::from_bytes(b"caf\xe9"));
+            assert_eq!(encode_path(latin1), "caf%E9");
+            assert_eq!(decode_path(&encode_path(latin1)), latin1);
+        }
+    }
+
+    #[test]
+    pub fn test_attachment_disposition() {
+        assert_eq!(
+            attachment_disposition("notes.tar"),
+            r#"attachment; filename="notes.tar"; filename*=UTF-8''notes.tar"#
+        );
+        assert_eq!(
+            attachment_disposition("Übersicht \"1\".zip"),
+            r#"attachment; filename="_bersicht _1_.zip"; filename*=UTF-8''%C3%9Cbersicht%20%221%22.zip"#
+        );
+    }
 }
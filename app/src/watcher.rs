@@ -0,0 +1,112 @@
+use std::{
+    future,
+    path::{Path, PathBuf},
+    sync::LazyLock,
+};
+
+use async_broadcast::{InactiveReceiver, Sender, broadcast};
+use futures::StreamExt;
+use leptos::logging;
+use notify::{Event, RecommendedWatcher, RecursiveMode, Watcher as _};
+use tokio_stream::Stream;
+
+use crate::{AppConfig, config::STATE_DIR, error_template::AppError};
+
+/// Folders whose entries changed, relative to the target directory. `None`
+/// when the watcher missed events and any folder could have changed.
+type Change = Option<PathBuf>;
+
+static CHANGES: LazyLock<(Sender<Change>, InactiveReceiver<Change>)> = LazyLock::new(|| {
+    let (mut tx, rx) = broadcast(64);
+    tx.set_overflow(true);
+    (tx, rx.deactivate())
+});
+
+/// Start watching the target directory recursively for changes, which are
+/// then streamed by [`changes`]. Watching stops when the returned watcher is
+/// dropped.
+///
+/// # Errors
+///
+/// Returns an error if the platform's watcher can't be set up, e.g. when
+/// the limit of watched folders is reached.
+pub fn start(app_config: &AppConfig) -> notify::Result<RecommendedWatcher> {
+    // some platforms report canonical paths, whatever path was watched
+    let target_dir = app_config.target_dir.canonicalize()?;
+    let state_dir = target_dir.join(STATE_DIR);
+    let watched = target_dir.clone();
+
+    let mut watcher = notify::recommended_watcher(move |event: notify::Result<Event>| {
+        let event = match event {
+            Ok(event) => event,
+            Err(e) => {
+                logging::warn!("Failed to watch for changes: {e}");
+                return;
+            },
+        };
+
+        if event.need_rescan() {
+            _ = CHANGES.0.try_broadcast(None);
+            return;
+        }
+
+        if event.kind.is_access() {
+            return;
+        }
+
+        for path in &event.paths {
+            if let Some(folder) = changed_folder(&target_dir, &state_dir, path) {
+                // fails only when nobody is listening
+                _ = CHANGES.0.try_broadcast(Some(folder));
+            }
+        }
+    })?;
+
+    watcher.watch(&watched, RecursiveMode::Recursive)?;
+
+    Ok(watcher)
+}
+
+/// The folder listing `path`, relative to `target_dir`. Changes to the files
+/// of the server in `state_dir` (uploads in progress, trash) are ignored.
+fn changed_folder(target_dir: &Path, state_dir: &Path, path: &Path) -> Option<PathBuf> {
+    if path.starts_with(state_dir) {
+        return None;
+    }
+
+    path.parent()?
+        .strip_prefix(target_dir)
+        .ok()
+        .map(Path::to_owned)
+}
+
+/// A line for every change to the entries of `folder`, which is relative to
+/// the target directory.
+pub fn changes(folder: PathBuf) -> impl Stream<Item = Result<String, AppError>> {
+    CHANGES
+        .1
+        .activate_cloned()
+        .filter(move |change| future::ready(change.as_ref().is_none_or(|path| *path == folder)))
+        .map(|_| Ok("changed\n".to_owned()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn test_changed_folder() {
+        let target_dir = Path::new("/srv/share");
+        let state_dir = target_dir.join(".file-share");
+        let changed = |path: &str| changed_folder(target_dir, &state_dir, Path::new(path));
+
+        assert_eq!(
+            changed("/srv/share/docs/report.pdf"),
+            Some(PathBuf::from("docs"))
+        );
+        assert_eq!(changed("/srv/share/notes.txt"), Some(PathBuf::new()));
+        assert_eq!(changed("/srv/share/.file-share/uploads/abc"), None);
+        assert_eq!(changed("/srv/share"), None);
+        assert_eq!(changed("/elsewhere/file"), None);
+    }
+}
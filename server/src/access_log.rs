@@ -0,0 +1,342 @@
+use std::{
+    fmt,
+    fs::{self, File, OpenOptions},
+    io::{self, Write as _},
+    net::{IpAddr, SocketAddr},
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    http::{Method, StatusCode, Version, header},
+    middleware::Next,
+    response::Response,
+};
+use chrono::{DateTime, Local};
+use file_share_app::auth::User;
+use leptos::logging;
+use serde::{Deserialize, Serialize};
+
+use crate::counted_body::counted;
+
+/// How the lines of the access log look.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    /// NCSA Common Log Format
+    Common,
+    /// Common Log Format with the referer and user agent
+    #[default]
+    Combined,
+    /// One JSON object per line
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "common" => Ok(LogFormat::Common),
+            "combined" => Ok(LogFormat::Combined),
+            "json" => Ok(LogFormat::Json),
+            _ => Err(format!(
+                "Invalid log format '{value}', expected one of common, combined, json"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for LogFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            LogFormat::Common => "common",
+            LogFormat::Combined => "combined",
+            LogFormat::Json => "json",
+        };
+        write!(f, "{name}")
+    }
+}
+
+/// A file that is rotated to `<path>.1`, `<path>.2`, ... when it grows over
+/// `max_size` bytes, keeping `keep` old files.
+struct LogFile {
+    path: PathBuf,
+    file: File,
+    size: u64,
+    /// `0` never rotates
+    max_size: u64,
+    keep: usize,
+}
+
+fn open_append(path: &Path) -> io::Result<File> {
+    OpenOptions::new().create(true).append(true).open(path)
+}
+
+/// `path` with `.n` appended to its name.
+fn rotated(path: &Path, n: usize) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(format!(".{n}"));
+    PathBuf::from(name)
+}
+
+impl LogFile {
+    fn open(path: PathBuf, max_size: u64, keep: usize) -> io::Result<Self> {
+        let file = open_append(&path)?;
+        let size = file.metadata()?.len();
+
+        Ok(Self {
+            path,
+            file,
+            size,
+            max_size,
+            keep,
+        })
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        if self.keep == 0 {
+            fs::remove_file(&self.path)?;
+        } else {
+            for n in (1..self.keep).rev() {
+                match fs::rename(rotated(&self.path, n), rotated(&self.path, n + 1)) {
+                    Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+                    _ => {},
+                }
+            }
+            fs::rename(&self.path, rotated(&self.path, 1))?;
+        }
+
+        self.file = open_append(&self.path)?;
+        self.size = 0;
+
+        Ok(())
+    }
+
+    fn write_line(&mut self, line: &str) -> io::Result<()> {
+        let len = line.len() as u64 + 1;
+
+        if self.max_size > 0 && self.size > 0 && self.size + len > self.max_size {
+            self.rotate()?;
+        }
+
+        // a single write, so that lines of concurrent requests don't mix
+        self.file.write_all(format!("{line}\n").as_bytes())?;
+        self.size += len;
+
+        Ok(())
+    }
+}
+
+/// Where requests are logged, shared by all connections.
+#[derive(Clone)]
+pub struct AccessLog {
+    format: LogFormat,
+    file: Arc<Mutex<LogFile>>,
+}
+
+impl AccessLog {
+    /// Append to the log at `path`, rotating it when it grows over
+    /// `max_size` bytes.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file can't be opened for writing.
+    pub fn open(path: PathBuf, format: LogFormat, max_size: u64, keep: usize) -> io::Result<Self> {
+        Ok(Self {
+            format,
+            file: Arc::new(Mutex::new(LogFile::open(path, max_size, keep)?)),
+        })
+    }
+
+    fn write(&self, entry: &Entry, bytes: u64, duration: Duration) {
+        let line = entry.format(self.format, bytes, duration);
+
+        let Ok(mut file) = self.file.lock() else {
+            return;
+        };
+
+        if let Err(e) = file.write_line(&line) {
+            logging::warn!("Failed to write to the access log: {e}");
+        }
+    }
+}
+
+/// What's known about a request once its response started.
+struct Entry {
+    time: DateTime<Local>,
+    client: Option<IpAddr>,
+    user: Option<String>,
+    method: Method,
+    /// Path and query, see [`redact`]
+    target: String,
+    version: Version,
+    status: StatusCode,
+    referer: Option<String>,
+    user_agent: Option<String>,
+}
+
+/// `target` with the value of a `password` in its query replaced.
+fn redact(target: &str) -> String {
+    let Some((path, query)) = target.split_once('?') else {
+        return target.to_owned();
+    };
+
+    let query = query
+        .split('&')
+        .map(|param| match param.split_once('=') {
+            Some(("password", _)) => "password=***".to_owned(),
+            _ => param.to_owned(),
+        })
+        .collect::<Vec<_>>()
+        .join("&");
+
+    format!("{path}?{query}")
+}
+
+/// `value` in double quotes, with quotes, backslashes and control characters
+/// escaped like Apache does.
+fn quote(value: &str) -> String {
+    let mut quoted = String::with_capacity(value.len() + 2);
+    quoted.push('"');
+
+    for c in value.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            c if c.is_control() => quoted.push_str(&format!("\\x{:02x}", u32::from(c))),
+            c => quoted.push(c),
+        }
+    }
+
+    quoted.push('"');
+    quoted
+}
+
+/// A JSON line, see [`LogFormat::Json`].
+#[derive(Serialize)]
+struct JsonEntry<'a> {
+    time: String,
+    client: Option<IpAddr>,
+    user: Option<&'a str>,
+    method: &'a str,
+    target: &'a str,
+    protocol: String,
+    status: u16,
+    bytes: u64,
+    duration_ms: f64,
+    referer: Option<&'a str>,
+    user_agent: Option<&'a str>,
+}
+
+impl Entry {
+    fn format(&self, format: LogFormat, bytes: u64, duration: Duration) -> String {
+        if format == LogFormat::Json {
+            let entry = JsonEntry {
+                time: self.time.to_rfc3339(),
+                client: self.client,
+                user: self.user.as_deref(),
+                method: self.method.as_str(),
+                target: &self.target,
+                protocol: format!("{:?}", self.version),
+                status: self.status.as_u16(),
+                bytes,
+                duration_ms: duration.as_secs_f64() * 1000.0,
+                referer: self.referer.as_deref(),
+                user_agent: self.user_agent.as_deref(),
+            };
+
+            return serde_json::to_string(&entry).unwrap_or_default();
+        }
+
+        let client = self
+            .client
+            .map_or_else(|| "-".to_owned(), |client| client.to_string());
+        let user = self.user.as_deref().map_or_else(
+            || "-".to_owned(),
+            |user| user.replace(|c: char| c.is_whitespace() || c.is_control(), "_"),
+        );
+        let bytes = if bytes == 0 {
+            "-".to_owned()
+        } else {
+            bytes.to_string()
+        };
+
+        let mut line = format!(
+            "{client} - {user} [{}] {} {} {bytes}",
+            self.time.format("%d/%b/%Y:%H:%M:%S %z"),
+            quote(&format!(
+                "{} {} {:?}",
+                self.method, self.target, self.version
+            )),
+            self.status.as_u16(),
+        );
+
+        if format == LogFormat::Combined {
+            let quoted = |value: &Option<String>| quote(value.as_deref().unwrap_or("-"));
+            line.push_str(&format!(
+                " {} {}",
+                quoted(&self.referer),
+                quoted(&self.user_agent)
+            ));
+        }
+
+        // an extra field at the end, which tools reading the standard
+        // formats skip
+        line.push_str(&format!(" {}", duration.as_millis()));
+
+        line
+    }
+}
+
+/// Logs every request with the client, user, status, bytes sent and
+/// duration.
+pub async fn record(State(log): State<AccessLog>, request: Request, next: Next) -> Response {
+    let start = Instant::now();
+    let time = Local::now();
+
+    let header_value = |name| {
+        request
+            .headers()
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .map(ToOwned::to_owned)
+    };
+
+    let client = request
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| addr.ip());
+    let method = request.method().clone();
+    let target = request.uri().path_and_query().map_or_else(
+        || request.uri().path().to_owned(),
+        |target| redact(target.as_str()),
+    );
+    let version = request.version();
+    let referer = header_value(header::REFERER);
+    let user_agent = header_value(header::USER_AGENT);
+
+    let response = next.run(request).await;
+
+    let entry = Entry {
+        time,
+        client,
+        // the login middleware passes the user on in the response
+        user: response
+            .extensions()
+            .get::<User>()
+            .map(|user| user.name.clone()),
+        method,
+        target,
+        version,
+        status: response.status(),
+        referer,
+        user_agent,
+    };
+
+    // written once the body was sent, with its final size and duration
+    response.map(|body| counted(body, move |bytes| log.write(&entry, bytes, start.elapsed())))
+}
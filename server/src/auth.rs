@@ -0,0 +1,81 @@
+use std::net::SocketAddr;
+
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    http::{Method, StatusCode, header},
+    middleware::Next,
+    response::{IntoResponse, Redirect, Response},
+};
+use file_share_app::{
+    auth::{Auth, LOCKOUT},
+    utils::encode_path,
+};
+
+use crate::errors::wants_html;
+
+/// Paths reachable without logging in: the login page itself, share links
+/// and the assets they need.
+fn is_public(path: &str) -> bool {
+    matches!(path, "/login" | "/api/login" | "/favicon.ico")
+        || path.starts_with("/pkg/")
+        || path.starts_with("/share/")
+}
+
+/// Rejects requests without a valid session when authentication is enabled.
+///
+/// Browsers navigating to a page are redirected to the login page, other
+/// clients get `401 Unauthorized`, or `429 Too Many Requests` after too many
+/// failed attempts. The authenticated
+/// [`User`](file_share_app::auth::User) is stored in the request extensions.
+pub async fn require_login(
+    State(auth): State<Option<Auth>>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    let Some(auth) = auth else {
+        return next.run(request).await;
+    };
+
+    let client = request
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| addr.ip().to_canonical());
+
+    if let Some(user) = auth.authenticate(request.headers(), client).await {
+        request.extensions_mut().insert(user.clone());
+
+        // for the access log, which sees only the response
+        let mut response = next.run(request).await;
+        response.extensions_mut().insert(user);
+        return response;
+    }
+
+    if is_public(request.uri().path()) {
+        return next.run(request).await;
+    }
+
+    if request.method() == Method::GET && wants_html(request.headers()) {
+        let uri = request.uri();
+        let target = uri
+            .path_and_query()
+            .map_or(uri.path(), |path| path.as_str());
+
+        return Redirect::to(&format!("/login?next={}", encode_path(target))).into_response();
+    }
+
+    if auth.throttled(client).await {
+        return (
+            StatusCode::TOO_MANY_REQUESTS,
+            [(header::RETRY_AFTER, LOCKOUT.as_secs().to_string())],
+            "Too many failed logins",
+        )
+            .into_response();
+    }
+
+    (
+        StatusCode::UNAUTHORIZED,
+        [(header::WWW_AUTHENTICATE, r#"Basic realm="file-share""#)],
+        "Login required",
+    )
+        .into_response()
+}
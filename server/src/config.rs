@@ -1,26 +1,49 @@
-use std::{net::IpAddr, path::PathBuf};
+use std::{
+    fmt, fs, io,
+    net::IpAddr,
+    path::{Path, PathBuf},
+    process,
+    str::FromStr,
+    time::Duration,
+};
 
-use clap::Parser;
+use clap::{ArgMatches, CommandFactory as _, FromArgMatches as _, Parser, parser::ValueSource};
+use file_share_app::{auth::users::hash_password, conflict::ConflictPolicy, permissions::PathRule};
+use serde::Deserialize;
+
+use crate::{
+    access_log::LogFormat,
+    ip_filter::{IpFilter, IpRange, IpRules},
+    throttle::Limits,
+    tls::TlsSource,
+};
 
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
 pub struct Cli {
     /// Path to the directory to share
-    #[arg(default_value = ".")]
+    #[arg(default_value = ".", env = "FILE_SHARE_TARGET_DIR")]
     pub target_dir: PathBuf,
 
     /// Port to listen on
-    #[arg(short, long, default_value = "3000")]
+    #[arg(short, long, default_value = "3000", env = "FILE_SHARE_PORT")]
     pub port: u16,
 
     /// Show QR codes that link to the site
-    #[arg(short, long)]
+    #[arg(short, long, env = "FILE_SHARE_QR")]
     pub qr: bool,
 
     /// IP address(es) of interfaces on which file-share will be available
     ///
     /// Accepts comma separated list of both IPv4 and IPv6 addresses
-    #[arg(short, long, num_args = 1.., value_delimiter = ',', default_value = "0.0.0.0,::")]
+    #[arg(
+        short,
+        long,
+        num_args = 1..,
+        value_delimiter = ',',
+        default_value = "0.0.0.0,::",
+        env = "FILE_SHARE_INTERFACES"
+    )]
     pub interfaces: Vec<IpAddr>,
 
     /// Open a GUI file picker to choose the target directory
@@ -30,25 +53,545 @@ pub struct Cli {
     pub picker: bool,
 
     /// Allow client to upload files
-    #[arg(short, long, default_value = "false")]
+    #[arg(short, long, default_value = "false", env = "FILE_SHARE_UPLOAD")]
     pub upload: bool,
+
+    /// What to do when an upload has the name of an existing file
+    ///
+    /// One of `reject`, `overwrite`, `rename` (to `name (1).ext`) or
+    /// `timestamp` (to `name (YYYY-MM-DD HH.MM.SS).ext`). Uploads can choose
+    /// their own policy, the web UI asks when the policy is `reject`
+    #[arg(
+        long,
+        value_name = "POLICY",
+        default_value = "reject",
+        env = "FILE_SHARE_ON_CONFLICT"
+    )]
+    pub on_conflict: ConflictPolicy,
+
+    /// Days to keep deleted files and folders in the trash
+    ///
+    /// Deleted entries can be restored from the trash page until they're
+    /// purged
+    #[arg(
+        long,
+        value_name = "DAYS",
+        default_value = "30",
+        env = "FILE_SHARE_TRASH_DAYS"
+    )]
+    pub trash_days: u64,
+
+    /// Require users to log in with accounts from this file
+    ///
+    /// Each line has the form `username[:role]:password-hash`, where the
+    /// role is one of `read-only`, `uploader` or `admin`. See
+    /// `--hash-password`
+    #[arg(long, value_name = "FILE", env = "FILE_SHARE_USERS")]
+    pub users: Option<PathBuf>,
+
+    /// Access rule for a directory inside `TARGET_DIR`, can be repeated
+    ///
+    /// MODE is one of `hidden`, `read-only` or `writable`, e.g.
+    /// `--rule /incoming=writable`. The most specific rule wins, admins
+    /// ignore all rules. Several rules can also be listed as `rules` in the
+    /// config file, or separated by `;` in the environment, since paths may
+    /// contain commas
+    #[arg(
+        long = "rule",
+        value_name = "PATH=MODE",
+        value_delimiter = ';',
+        env = "FILE_SHARE_RULES"
+    )]
+    pub rules: Vec<PathRule>,
+
+    /// Serve over HTTPS using this PEM encoded certificate (chain)
+    #[arg(
+        long,
+        value_name = "FILE",
+        requires = "tls_key",
+        env = "FILE_SHARE_TLS_CERT"
+    )]
+    pub tls_cert: Option<PathBuf>,
+
+    /// Private key for `--tls-cert` in PEM format
+    #[arg(
+        long,
+        value_name = "FILE",
+        requires = "tls_cert",
+        env = "FILE_SHARE_TLS_KEY"
+    )]
+    pub tls_key: Option<PathBuf>,
+
+    /// Serve over HTTPS using a self-signed certificate
+    ///
+    /// The certificate is generated on the first run and reused afterwards,
+    /// compare its fingerprint when the browser asks to trust it
+    #[arg(
+        long,
+        default_value = "false",
+        conflicts_with = "tls_cert",
+        env = "FILE_SHARE_TLS_SELF_SIGNED"
+    )]
+    pub tls_self_signed: bool,
+
+    /// Limit all downloads together to this bandwidth
+    ///
+    /// Applies to `/files`, `/archive`, share links and WebDAV
+    #[arg(long, value_name = "KiB/s", env = "FILE_SHARE_DOWNLOAD_LIMIT")]
+    pub download_limit: Option<u64>,
+
+    /// Limit all uploads together to this bandwidth
+    ///
+    /// Applies to the web uploader, `/upload` and WebDAV
+    #[arg(long, value_name = "KiB/s", env = "FILE_SHARE_UPLOAD_LIMIT")]
+    pub upload_limit: Option<u64>,
+
+    /// Limit the downloads of each client (by IP address) to this bandwidth
+    #[arg(long, value_name = "KiB/s", env = "FILE_SHARE_CLIENT_DOWNLOAD_LIMIT")]
+    pub client_download_limit: Option<u64>,
+
+    /// Limit the uploads of each client (by IP address) to this bandwidth
+    #[arg(long, value_name = "KiB/s", env = "FILE_SHARE_CLIENT_UPLOAD_LIMIT")]
+    pub client_upload_limit: Option<u64>,
+
+    /// Accept requests only from these addresses or CIDR ranges
+    ///
+    /// Accepts comma separated list, e.g. `192.168.1.0/24,::1`, and can be
+    /// repeated. Everyone is accepted when it's empty
+    #[arg(
+        long,
+        value_name = "CIDR",
+        value_delimiter = ',',
+        env = "FILE_SHARE_ALLOW"
+    )]
+    pub allow: Vec<IpRange>,
+
+    /// Refuse requests from these addresses or CIDR ranges
+    ///
+    /// Wins over `--allow`, e.g. `--allow 10.0.0.0/8 --deny 10.0.0.1`
+    #[arg(
+        long,
+        value_name = "CIDR",
+        value_delimiter = ',',
+        env = "FILE_SHARE_DENY"
+    )]
+    pub deny: Vec<IpRange>,
+
+    /// Accept requests that change files only from these addresses or ranges
+    ///
+    /// Applies to uploads, changes through the web interface and WebDAV, in
+    /// addition to `--allow` and `--deny`
+    #[arg(
+        long,
+        value_name = "CIDR",
+        value_delimiter = ',',
+        env = "FILE_SHARE_WRITE_ALLOW"
+    )]
+    pub write_allow: Vec<IpRange>,
+
+    /// Refuse requests that change files from these addresses or ranges
+    #[arg(
+        long,
+        value_name = "CIDR",
+        value_delimiter = ',',
+        env = "FILE_SHARE_WRITE_DENY"
+    )]
+    pub write_deny: Vec<IpRange>,
+
+    /// Log every request to this file
+    ///
+    /// Each line records the client, user, request, status, bytes sent and
+    /// duration (in milliseconds as the last field of the text formats)
+    #[arg(long, value_name = "FILE", env = "FILE_SHARE_ACCESS_LOG")]
+    pub access_log: Option<PathBuf>,
+
+    /// Format of the access log
+    ///
+    /// One of `common` (Common Log Format), `combined` (with the referer and
+    /// user agent) or `json` (an object per line)
+    #[arg(
+        long,
+        value_name = "FORMAT",
+        default_value = "combined",
+        env = "FILE_SHARE_ACCESS_LOG_FORMAT"
+    )]
+    pub access_log_format: LogFormat,
+
+    /// Rotate the access log when it grows over this size, `0` never does
+    ///
+    /// The old log is renamed to `FILE.1`, older ones to `FILE.2` and so on
+    #[arg(
+        long,
+        value_name = "MiB",
+        default_value = "10",
+        env = "FILE_SHARE_ACCESS_LOG_MAX_SIZE"
+    )]
+    pub access_log_max_size: u64,
+
+    /// Number of rotated access logs to keep
+    #[arg(
+        long,
+        value_name = "COUNT",
+        default_value = "5",
+        env = "FILE_SHARE_ACCESS_LOG_KEEP"
+    )]
+    pub access_log_keep: usize,
+
+    /// Read settings from this TOML file
+    ///
+    /// Keys are named like the long flags, e.g. `port = 8080` or
+    /// `rules = ["/incoming=writable"]`. Defaults to `file-share/config.toml`
+    /// in the config directory (e.g. `~/.config` on Linux) when it exists.
+    /// Flags override environment variables, which override the file
+    #[arg(long, value_name = "FILE", env = "FILE_SHARE_CONFIG")]
+    pub config: Option<PathBuf>,
+
+    /// Print the effective settings and where they come from, then exit
+    #[arg(long, default_value = "false")]
+    pub print_config: bool,
+
+    /// Read a password from stdin, print its hash for the users file and exit
+    #[arg(long, default_value = "false")]
+    pub hash_password: bool,
+}
+
+/// Settings from the config file, named like the flags of [`Cli`].
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+struct FileConfig {
+    target_dir: Option<PathBuf>,
+    port: Option<u16>,
+    qr: Option<bool>,
+    interfaces: Option<Vec<IpAddr>>,
+    upload: Option<bool>,
+    on_conflict: Option<ConflictPolicy>,
+    trash_days: Option<u64>,
+    users: Option<PathBuf>,
+    rules: Option<Vec<String>>,
+    tls_cert: Option<PathBuf>,
+    tls_key: Option<PathBuf>,
+    tls_self_signed: Option<bool>,
+    download_limit: Option<u64>,
+    upload_limit: Option<u64>,
+    client_download_limit: Option<u64>,
+    client_upload_limit: Option<u64>,
+    allow: Option<Vec<String>>,
+    deny: Option<Vec<String>>,
+    write_allow: Option<Vec<String>>,
+    write_deny: Option<Vec<String>>,
+    access_log: Option<PathBuf>,
+    access_log_format: Option<LogFormat>,
+    access_log_max_size: Option<u64>,
+    access_log_keep: Option<usize>,
+}
+
+/// The config file used when `--config` isn't given.
+fn default_config_file() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("file-share").join("config.toml"))
+}
+
+/// Read the config file at `path`, or the default one if it exists.
+///
+/// Returns the path of the file that was read, if any.
+fn read_config_file(path: Option<PathBuf>) -> Result<(Option<PathBuf>, FileConfig), String> {
+    let path = match path {
+        Some(path) => path,
+        None => match default_config_file() {
+            Some(path) if path.is_file() => path,
+            _ => return Ok((None, FileConfig::default())),
+        },
+    };
+
+    let text = fs::read_to_string(&path)
+        .map_err(|e| format!("Failed to read config file {}: {e}", path.display()))?;
+
+    let mut config: FileConfig = toml::from_str(&text)
+        .map_err(|e| format!("Invalid config file {}: {e}", path.display()))?;
+
+    // paths in the file are relative to it, not to where the server is started
+    let base = path.parent().unwrap_or(Path::new(""));
+    for relative in [
+        &mut config.target_dir,
+        &mut config.users,
+        &mut config.tls_cert,
+        &mut config.tls_key,
+        &mut config.access_log,
+    ]
+    .into_iter()
+    .flatten()
+    {
+        *relative = base.join(&*relative);
+    }
+
+    Ok((Some(path), config))
+}
+
+/// Where an effective setting comes from, in the order of precedence.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Source {
+    CommandLine,
+    Environment,
+    File,
+    Default,
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Source::CommandLine => "command line",
+            Source::Environment => "environment",
+            Source::File => "config file",
+            Source::Default => "default",
+        };
+        write!(f, "{name}")
+    }
+}
+
+/// Replace `value` of the argument `id` by the one from the config file,
+/// unless it was given on the command line or in the environment.
+fn pick<T>(matches: &ArgMatches, id: &str, value: &mut T, file: Option<T>) -> Source {
+    match (matches.value_source(id), file) {
+        (Some(ValueSource::CommandLine), _) => Source::CommandLine,
+        (Some(ValueSource::EnvVariable), _) => Source::Environment,
+        (_, Some(file)) => {
+            *value = file;
+            Source::File
+        },
+        (_, None) => Source::Default,
+    }
+}
+
+/// A setting as shown by `--print-config`, `None` when it's unset.
+type Setting = (&'static str, Option<toml::Value>, Source);
+
+fn path_value(path: &Path) -> toml::Value {
+    toml::Value::from(path.to_string_lossy().into_owned())
+}
+
+fn list_value(items: &[impl ToString]) -> toml::Value {
+    toml::Value::from(items.iter().map(ToString::to_string).collect::<Vec<_>>())
+}
+
+/// Parse the items of a list from the config file.
+fn parse_list<T: FromStr<Err = String>>(
+    items: Option<Vec<String>>,
+) -> Result<Option<Vec<T>>, String> {
+    items
+        .map(|items| items.iter().map(|item| item.parse()).collect())
+        .transpose()
+}
+
+/// Fill in the settings of `cli` that weren't given on the command line or
+/// in the environment from the config `file`.
+///
+/// Returns the effective settings and where they come from.
+#[allow(clippy::too_many_lines)] // a flat list of settings
+fn merge(matches: &ArgMatches, cli: &mut Cli, file: FileConfig) -> Result<Vec<Setting>, String> {
+    let file_rules = parse_list::<PathRule>(file.rules)?;
+
+    let mut settings = Vec::new();
+
+    let source = pick(matches, "target_dir", &mut cli.target_dir, file.target_dir);
+    settings.push(("target-dir", Some(path_value(&cli.target_dir)), source));
+
+    let source = pick(matches, "port", &mut cli.port, file.port);
+    settings.push(("port", Some(i64::from(cli.port).into()), source));
+
+    let source = pick(matches, "qr", &mut cli.qr, file.qr);
+    settings.push(("qr", Some(cli.qr.into()), source));
+
+    let source = pick(matches, "interfaces", &mut cli.interfaces, file.interfaces);
+    settings.push(("interfaces", Some(list_value(&cli.interfaces)), source));
+
+    let source = pick(matches, "upload", &mut cli.upload, file.upload);
+    settings.push(("upload", Some(cli.upload.into()), source));
+
+    let source = pick(
+        matches,
+        "on_conflict",
+        &mut cli.on_conflict,
+        file.on_conflict,
+    );
+    settings.push((
+        "on-conflict",
+        Some(cli.on_conflict.to_string().into()),
+        source,
+    ));
+
+    let source = pick(matches, "trash_days", &mut cli.trash_days, file.trash_days);
+    let trash_days = i64::try_from(cli.trash_days).unwrap_or(i64::MAX);
+    settings.push(("trash-days", Some(trash_days.into()), source));
+
+    let source = pick(matches, "users", &mut cli.users, file.users.map(Some));
+    settings.push(("users", cli.users.as_deref().map(path_value), source));
+
+    let source = pick(matches, "rules", &mut cli.rules, file_rules);
+    settings.push(("rules", Some(list_value(&cli.rules)), source));
+
+    let source = pick(
+        matches,
+        "tls_cert",
+        &mut cli.tls_cert,
+        file.tls_cert.map(Some),
+    );
+    settings.push(("tls-cert", cli.tls_cert.as_deref().map(path_value), source));
+
+    let source = pick(matches, "tls_key", &mut cli.tls_key, file.tls_key.map(Some));
+    settings.push(("tls-key", cli.tls_key.as_deref().map(path_value), source));
+
+    let source = pick(
+        matches,
+        "tls_self_signed",
+        &mut cli.tls_self_signed,
+        file.tls_self_signed,
+    );
+    settings.push(("tls-self-signed", Some(cli.tls_self_signed.into()), source));
+
+    for (id, key, value, file) in [
+        (
+            "download_limit",
+            "download-limit",
+            &mut cli.download_limit,
+            file.download_limit,
+        ),
+        (
+            "upload_limit",
+            "upload-limit",
+            &mut cli.upload_limit,
+            file.upload_limit,
+        ),
+        (
+            "client_download_limit",
+            "client-download-limit",
+            &mut cli.client_download_limit,
+            file.client_download_limit,
+        ),
+        (
+            "client_upload_limit",
+            "client-upload-limit",
+            &mut cli.client_upload_limit,
+            file.client_upload_limit,
+        ),
+    ] {
+        let source = pick(matches, id, value, file.map(Some));
+        let limit = value.map(|limit| i64::try_from(limit).unwrap_or(i64::MAX).into());
+        settings.push((key, limit, source));
+    }
+
+    for (id, key, value, file) in [
+        ("allow", "allow", &mut cli.allow, file.allow),
+        ("deny", "deny", &mut cli.deny, file.deny),
+        (
+            "write_allow",
+            "write-allow",
+            &mut cli.write_allow,
+            file.write_allow,
+        ),
+        (
+            "write_deny",
+            "write-deny",
+            &mut cli.write_deny,
+            file.write_deny,
+        ),
+    ] {
+        let source = pick(matches, id, value, parse_list(file)?);
+        settings.push((key, Some(list_value(value.as_slice())), source));
+    }
+
+    let source = pick(
+        matches,
+        "access_log",
+        &mut cli.access_log,
+        file.access_log.map(Some),
+    );
+    settings.push((
+        "access-log",
+        cli.access_log.as_deref().map(path_value),
+        source,
+    ));
+
+    let source = pick(
+        matches,
+        "access_log_format",
+        &mut cli.access_log_format,
+        file.access_log_format,
+    );
+    settings.push((
+        "access-log-format",
+        Some(cli.access_log_format.to_string().into()),
+        source,
+    ));
+
+    let source = pick(
+        matches,
+        "access_log_max_size",
+        &mut cli.access_log_max_size,
+        file.access_log_max_size,
+    );
+    let max_size = i64::try_from(cli.access_log_max_size).unwrap_or(i64::MAX);
+    settings.push(("access-log-max-size", Some(max_size.into()), source));
+
+    let source = pick(
+        matches,
+        "access_log_keep",
+        &mut cli.access_log_keep,
+        file.access_log_keep,
+    );
+    let keep = i64::try_from(cli.access_log_keep).unwrap_or(i64::MAX);
+    settings.push(("access-log-keep", Some(keep.into()), source));
+
+    Ok(settings)
+}
+
+/// Print the settings as a config file, with the source of each as a
+/// comment. Unset ones are left out.
+fn print_settings(file: Option<&Path>, settings: &[Setting]) {
+    match file {
+        Some(file) => println!("# Config file: {}", file.display()),
+        None => println!("# No config file"),
+    }
+
+    for (key, value, source) in settings {
+        if let Some(value) = value {
+            println!("{key} = {value} # {source}");
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct Config {
     pub target_dir: PathBuf,
     pub allow_upload: bool,
+    pub on_conflict: ConflictPolicy,
+    pub trash_retention: Duration,
     pub port: u16,
     pub qr: bool,
     pub interfaces: Vec<IpAddr>,
+    pub users: Option<PathBuf>,
+    pub rules: Vec<PathRule>,
+    pub tls: Option<TlsSource>,
+    pub limits: Limits,
+    pub ip_filter: IpFilter,
+    pub access_log: Option<AccessLogConfig>,
 }
 
-/// Get the config from CLI arguments.
+/// Where and how requests are logged.
+#[derive(Debug, Clone)]
+pub struct AccessLogConfig {
+    pub path: PathBuf,
+    pub format: LogFormat,
+    /// In bytes
+    pub max_size: u64,
+    pub keep: usize,
+}
+
+/// Get the config from CLI arguments, environment variables and the config
+/// file, in this order of precedence.
 ///
 /// # Errors
 ///
-/// Returns error if `CWD`/`target_dir` is unreadable or when there's no free
-/// port.
+/// Returns error if the config file is invalid, `CWD`/`target_dir` is
+/// unreadable or when there's no free port.
 ///
 /// # Panics
 ///
@@ -56,6 +599,22 @@ pub struct Config {
 /// process.
 #[allow(clippy::unused_async)] // it's used only in release build
 pub async fn get_config() -> Result<Config, String> {
+    let matches = Cli::command().get_matches();
+    let mut cli = Cli::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+
+    if cli.hash_password {
+        print_password_hash()?;
+        process::exit(0);
+    }
+
+    let (config_file, file) = read_config_file(cli.config.take())?;
+    let settings = merge(&matches, &mut cli, file)?;
+
+    if cli.print_config {
+        print_settings(config_file.as_deref(), &settings);
+        process::exit(0);
+    }
+
     let Cli {
         target_dir,
         port,
@@ -63,7 +622,28 @@ pub async fn get_config() -> Result<Config, String> {
         interfaces,
         picker,
         upload,
-    } = Cli::parse();
+        on_conflict,
+        trash_days,
+        users,
+        rules,
+        tls_cert,
+        tls_key,
+        tls_self_signed,
+        download_limit,
+        upload_limit,
+        client_download_limit,
+        client_upload_limit,
+        allow,
+        deny,
+        write_allow,
+        write_deny,
+        access_log,
+        access_log_format,
+        access_log_max_size,
+        access_log_keep,
+        ..
+    } = cli;
+
     let target_dir = if picker {
         rfd::AsyncFileDialog::new()
             .set_title("Select directory to share")
@@ -81,11 +661,145 @@ pub async fn get_config() -> Result<Config, String> {
         .or_else(port_check::free_local_port)
         .ok_or("Couldn't find an open port")?;
 
+    let tls = match (tls_cert, tls_key) {
+        (Some(cert), Some(key)) => Some(TlsSource::Files { cert, key }),
+        (None, None) => tls_self_signed.then_some(TlsSource::SelfSigned),
+        _ => return Err("tls-cert and tls-key have to be set together".into()),
+    };
+
     Ok(Config {
         target_dir,
         allow_upload: upload,
+        on_conflict,
+        trash_retention: Duration::from_secs(trash_days.saturating_mul(24 * 60 * 60)),
         port,
         qr,
         interfaces,
+        users,
+        rules,
+        tls,
+        limits: Limits {
+            download: download_limit.map(kib),
+            upload: upload_limit.map(kib),
+            client_download: client_download_limit.map(kib),
+            client_upload: client_upload_limit.map(kib),
+        },
+        ip_filter: IpFilter {
+            read: IpRules { allow, deny },
+            write: IpRules {
+                allow: write_allow,
+                deny: write_deny,
+            },
+        },
+        access_log: access_log.map(|path| AccessLogConfig {
+            path,
+            format: access_log_format,
+            max_size: access_log_max_size.saturating_mul(1024 * 1024),
+            keep: access_log_keep,
+        }),
     })
 }
+
+/// Bytes per second of a limit in KiB/s.
+fn kib(limit: u64) -> u64 {
+    limit.saturating_mul(1024)
+}
+
+fn print_password_hash() -> Result<(), String> {
+    let mut password = String::new();
+    io::stdin()
+        .read_line(&mut password)
+        .map_err(|e| e.to_string())?;
+
+    let hash = hash_password(password.trim_end_matches(['\r', '\n'])).map_err(|e| e.to_string())?;
+    println!("{hash}");
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn merged(args: &[&str], toml: &str) -> (Cli, Vec<Setting>) {
+        let matches = Cli::command().try_get_matches_from(args).unwrap();
+        let mut cli = Cli::from_arg_matches(&matches).unwrap();
+        let file = toml::from_str(toml).unwrap();
+        let settings = merge(&matches, &mut cli, file).unwrap();
+
+        (cli, settings)
+    }
+
+    fn source(settings: &[Setting], key: &str) -> Source {
+        settings
+            .iter()
+            .find_map(|(name, _, source)| (*name == key).then_some(*source))
+            .unwrap()
+    }
+
+    fn rules(rules: &[&str]) -> Vec<PathRule> {
+        rules.iter().map(|rule| rule.parse().unwrap()).collect()
+    }
+
+    // a single test, since it changes the environment that all of them read
+    #[test]
+    pub fn test_merge() {
+        const FILE: &str = r#"
+            port = 8080
+            qr = true
+            rules = ["/a=hidden"]
+        "#;
+
+        let (cli, settings) = merged(&["file-share"], "");
+        assert_eq!((cli.port, cli.qr), (3000, false));
+        assert_eq!(cli.rules, rules(&[]));
+        for key in ["port", "qr", "rules"] {
+            assert_eq!(source(&settings, key), Source::Default);
+        }
+
+        let (cli, settings) = merged(&["file-share"], FILE);
+        assert_eq!((cli.port, cli.qr), (8080, true));
+        assert_eq!(cli.rules, rules(&["/a=hidden"]));
+        for key in ["port", "qr", "rules"] {
+            assert_eq!(source(&settings, key), Source::File);
+        }
+
+        // SAFETY: no other test reads or changes the environment
+        unsafe {
+            std::env::set_var("FILE_SHARE_PORT", "9090");
+            std::env::set_var("FILE_SHARE_QR", "false");
+            std::env::set_var("FILE_SHARE_RULES", "/b,c=writable;/d=read-only");
+        }
+
+        let (cli, settings) = merged(&["file-share"], FILE);
+        assert_eq!((cli.port, cli.qr), (9090, false));
+        assert_eq!(cli.rules, rules(&["/b,c=writable", "/d=read-only"]));
+        for key in ["port", "qr", "rules"] {
+            assert_eq!(source(&settings, key), Source::Environment);
+        }
+
+        let (cli, settings) = merged(
+            &[
+                "file-share",
+                "--port",
+                "7070",
+                "--qr",
+                "--rule",
+                "/e=writable",
+            ],
+            FILE,
+        );
+        assert_eq!((cli.port, cli.qr), (7070, true));
+        assert_eq!(cli.rules, rules(&["/e=writable"]));
+        for key in ["port", "qr", "rules"] {
+            assert_eq!(source(&settings, key), Source::CommandLine);
+        }
+
+        // SAFETY: see above
+        unsafe {
+            std::env::remove_var("FILE_SHARE_PORT");
+            std::env::remove_var("FILE_SHARE_QR");
+            std::env::remove_var("FILE_SHARE_RULES");
+        }
+    }
+}
@@ -0,0 +1,62 @@
+use std::{
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use axum::body::{Body, Bytes, HttpBody};
+use http_body::{Frame, SizeHint};
+
+/// A response body that counts the bytes sent and reports them once it's
+/// dropped, which is when it was sent completely or the client went away.
+/// Streamed responses like archives are reported with their final size.
+struct CountedBody {
+    inner: Body,
+    bytes: u64,
+    on_done: Option<Box<dyn FnOnce(u64) + Send>>,
+}
+
+/// Wrap `body`, calling `on_done` with the number of bytes sent when it's
+/// done.
+pub fn counted(body: Body, on_done: impl FnOnce(u64) + Send + 'static) -> Body {
+    Body::new(CountedBody {
+        inner: body,
+        bytes: 0,
+        on_done: Some(Box::new(on_done)),
+    })
+}
+
+impl HttpBody for CountedBody {
+    type Data = Bytes;
+    type Error = axum::Error;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let poll = Pin::new(&mut self.inner).poll_frame(cx);
+
+        if let Poll::Ready(Some(Ok(frame))) = &poll {
+            if let Some(data) = frame.data_ref() {
+                self.bytes += data.len() as u64;
+            }
+        }
+
+        poll
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+impl Drop for CountedBody {
+    fn drop(&mut self) {
+        if let Some(on_done) = self.on_done.take() {
+            on_done(self.bytes);
+        }
+    }
+}
@@ -0,0 +1,389 @@
+use std::{
+    future,
+    path::{Path, PathBuf},
+};
+
+use axum::{
+    Extension, Router,
+    extract::Request,
+    http::{HeaderMap, Method, StatusCode},
+    response::{IntoResponse, Response},
+    routing::{MethodRouter, any},
+};
+use dav_server::{
+    DavHandler,
+    davpath::DavPath,
+    fs::{
+        DavDirEntry, DavFile, DavFileSystem, DavMetaData, FsError, FsFuture, FsResult, FsStream,
+        GuardedFileSystem, OpenOptions, ReadDirMeta,
+    },
+    localfs::LocalFs,
+    ls::DavLockSystem as _,
+    memls::MemLs,
+};
+use file_share_app::{
+    AppConfig, AppState,
+    auth::User,
+    permissions::Access,
+    sandbox::{SandboxError, SandboxedPath},
+    trash::{self, TrashError},
+};
+use futures::StreamExt;
+use leptos::logging;
+
+/// Where the shared directory is mounted over WebDAV.
+pub const PREFIX: &str = "/dav";
+
+/// Routes serving the shared directory over WebDAV (class 1 and 2) under
+/// [`PREFIX`], with the same sandboxing and permissions as the web interface.
+pub fn router(app_config: &AppConfig) -> Router<AppState> {
+    let fs = SandboxedFs {
+        app_config: app_config.clone(),
+        local: *LocalFs::new(&app_config.target_dir, true, false, false),
+    };
+    let locks = *MemLs::new();
+
+    let handler = DavHandler::<Option<User>>::builder()
+        .strip_prefix(PREFIX)
+        .filesystem(Box::new(fs.clone()))
+        .locksystem(Box::new(locks.clone()))
+        .build_handler();
+
+    let service: MethodRouter<AppState> =
+        any(move |user: Option<Extension<User>>, request: Request| {
+            let handler = handler.clone();
+            let fs = fs.clone();
+            let locks = locks.clone();
+            async move {
+                let user = user.map(|Extension(user)| user);
+
+                if request.method() == Method::DELETE {
+                    let path = request.uri().path();
+                    return delete(&fs, &locks, user.as_ref(), path, request.headers()).await;
+                }
+
+                handler.handle_guarded(request, user).await.into_response()
+            }
+        });
+
+    Router::new()
+        .route(PREFIX, service.clone())
+        .route(&format!("{PREFIX}/"), service.clone())
+        .route(&format!("{PREFIX}/{{*path}}"), service)
+}
+
+/// Handles DELETE by moving the entry to the trash, like deleting in the web
+/// interface does. The handler of `dav_server` would remove folders entry by
+/// entry and for good.
+async fn delete(
+    fs: &SandboxedFs,
+    locks: &MemLs,
+    user: Option<&User>,
+    path: &str,
+    headers: &HeaderMap,
+) -> Response {
+    let result = async {
+        let mut path = DavPath::new(path).map_err(|_| StatusCode::BAD_REQUEST)?;
+        path.set_prefix(PREFIX)
+            .map_err(|_| StatusCode::BAD_REQUEST)?;
+
+        fs.removable(user, &path).await.map_err(fs_status)?;
+        fs.tree_writable(user, &path).map_err(fs_status)?;
+
+        // locks of other clients on the entry or below it
+        if locks
+            .check(&path, None, false, true, lock_tokens(headers))
+            .await
+            .is_err()
+        {
+            return Err(StatusCode::LOCKED);
+        }
+
+        let (entry, _) = fs.access(user, &path).map_err(fs_status)?;
+
+        match trash::move_to_trash(&fs.app_config, &entry).await {
+            Ok(_) => {},
+            Err(TrashError::Sandbox(SandboxError::NotFound)) => return Err(StatusCode::NOT_FOUND),
+            Err(e) => {
+                logging::error!("Failed to move {:?} to the trash: {e}", entry.relative());
+                return Err(StatusCode::INTERNAL_SERVER_ERROR);
+            },
+        }
+
+        _ = locks.delete(&path).await;
+
+        Ok::<_, StatusCode>(())
+    }
+    .await;
+
+    match result {
+        Ok(()) => StatusCode::NO_CONTENT.into_response(),
+        Err(status) => status.into_response(),
+    }
+}
+
+/// The lock tokens submitted in the `If` header of a request.
+fn lock_tokens(headers: &HeaderMap) -> Vec<&str> {
+    headers
+        .get_all("if")
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(['<', '>']))
+        .filter(|token| token.starts_with("urn:uuid:"))
+        .collect()
+}
+
+fn fs_status(err: FsError) -> StatusCode {
+    match err {
+        FsError::NotFound => StatusCode::NOT_FOUND,
+        FsError::Forbidden => StatusCode::FORBIDDEN,
+        _ => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
+
+/// The local filesystem behind the checks of [`SandboxedPath`] and the
+/// permissions of the user of a request. Hidden entries look like missing
+/// ones, writing without permission is forbidden.
+#[derive(Clone)]
+struct SandboxedFs {
+    app_config: AppConfig,
+    local: LocalFs,
+}
+
+fn fs_error(err: &SandboxError) -> FsError {
+    match err {
+        SandboxError::NotFound => FsError::NotFound,
+        SandboxError::Traversal
+        | SandboxError::Absolute
+        | SandboxError::Escape
+        | SandboxError::InvalidName(_) => FsError::Forbidden,
+        SandboxError::Io(_) => FsError::GeneralFailure,
+    }
+}
+
+/// Whether opening a file with `options` can change it.
+fn writes(options: &OpenOptions) -> bool {
+    options.write || options.append || options.truncate || options.create || options.create_new
+}
+
+impl SandboxedFs {
+    fn access(&self, user: Option<&User>, path: &DavPath) -> FsResult<(SandboxedPath, Access)> {
+        let sandboxed = self
+            .app_config
+            .sandbox()
+            .join(path.as_rel_ospath())
+            .map_err(|e| fs_error(&e))?;
+
+        let access = self.app_config.access(user, sandboxed.relative());
+
+        // hidden paths are indistinguishable from missing ones
+        if access.can_read() {
+            Ok((sandboxed, access))
+        } else {
+            Err(FsError::NotFound)
+        }
+    }
+
+    /// Check that `path` exists and can be read by `user`, also where its
+    /// symlinks lead.
+    async fn readable(&self, user: Option<&User>, path: &DavPath) -> FsResult<SandboxedPath> {
+        let (sandboxed, _) = self.access(user, path)?;
+        self.app_config
+            .resolve_readable(user, &sandboxed)
+            .await
+            .map_err(|e| fs_error(&e))?;
+
+        Ok(sandboxed)
+    }
+
+    /// Check that `user` can create `path` or change it, when it exists.
+    async fn writable(&self, user: Option<&User>, path: &DavPath) -> FsResult<()> {
+        let (sandboxed, access) = self.access(user, path)?;
+
+        if !access.can_write() {
+            return Err(FsError::Forbidden);
+        }
+
+        let resolved = sandboxed.resolve_new().await.map_err(|e| fs_error(&e))?;
+
+        // a symlink must not lead to a path with a stricter rule
+        if !self
+            .app_config
+            .real_access(user, &sandboxed, &resolved)
+            .await
+            .can_write()
+        {
+            return Err(FsError::Forbidden);
+        }
+
+        Ok(())
+    }
+
+    /// Check that `user` can change `path` and everything below it, so that
+    /// moving a folder doesn't carry entries out of or into stricter rules.
+    fn tree_writable(&self, user: Option<&User>, path: &DavPath) -> FsResult<()> {
+        let (sandboxed, _) = self.access(user, path)?;
+
+        if self.app_config.can_write_tree(user, sandboxed.relative()) {
+            Ok(())
+        } else {
+            Err(FsError::Forbidden)
+        }
+    }
+
+    /// Check that `user` can remove or move the existing entry at `path`,
+    /// which may be a symlink.
+    async fn removable(&self, user: Option<&User>, path: &DavPath) -> FsResult<()> {
+        let (sandboxed, access) = self.access(user, path)?;
+
+        if !access.can_write() || sandboxed.is_root() {
+            return Err(FsError::Forbidden);
+        }
+
+        sandboxed.resolve_entry().await.map_err(|e| fs_error(&e))?;
+
+        Ok(())
+    }
+}
+
+/// Path of a directory entry named `name` (as raw bytes) inside `dir`.
+fn entry_path(dir: &Path, name: &[u8]) -> PathBuf {
+    #[cfg(unix)]
+    {
+        use std::{ffi::OsStr, os::unix::ffi::OsStrExt as _};
+
+        dir.join(OsStr::from_bytes(name))
+    }
+
+    #[cfg(not(unix))]
+    {
+        dir.join(String::from_utf8_lossy(name).as_ref())
+    }
+}
+
+impl GuardedFileSystem<Option<User>> for SandboxedFs {
+    fn open<'a>(
+        &'a self,
+        path: &'a DavPath,
+        options: OpenOptions,
+        user: &'a Option<User>,
+    ) -> FsFuture<'a, Box<dyn DavFile>> {
+        Box::pin(async move {
+            if writes(&options) {
+                self.writable(user.as_ref(), path).await?;
+            } else {
+                self.readable(user.as_ref(), path).await?;
+            }
+
+            DavFileSystem::open(&self.local, path, options).await
+        })
+    }
+
+    fn read_dir<'a>(
+        &'a self,
+        path: &'a DavPath,
+        meta: ReadDirMeta,
+        user: &'a Option<User>,
+    ) -> FsFuture<'a, FsStream<Box<dyn DavDirEntry>>> {
+        Box::pin(async move {
+            let dir = self.readable(user.as_ref(), path).await?;
+            let dir = dir.relative().to_owned();
+            let app_config = self.app_config.clone();
+            let user = user.clone();
+
+            let entries = DavFileSystem::read_dir(&self.local, path, meta)
+                .await?
+                .filter(move |entry| {
+                    let visible = match entry {
+                        Ok(entry) => app_config
+                            .access(user.as_ref(), &entry_path(&dir, &entry.name()))
+                            .can_read(),
+                        // errors are reported by the handler
+                        Err(_) => true,
+                    };
+                    future::ready(visible)
+                });
+
+            Ok(Box::pin(entries) as FsStream<Box<dyn DavDirEntry>>)
+        })
+    }
+
+    fn metadata<'a>(
+        &'a self,
+        path: &'a DavPath,
+        user: &'a Option<User>,
+    ) -> FsFuture<'a, Box<dyn DavMetaData>> {
+        Box::pin(async move {
+            self.readable(user.as_ref(), path).await?;
+            DavFileSystem::metadata(&self.local, path).await
+        })
+    }
+
+    fn symlink_metadata<'a>(
+        &'a self,
+        path: &'a DavPath,
+        user: &'a Option<User>,
+    ) -> FsFuture<'a, Box<dyn DavMetaData>> {
+        Box::pin(async move {
+            let (sandboxed, _) = self.access(user.as_ref(), path)?;
+            sandboxed.resolve_entry().await.map_err(|e| fs_error(&e))?;
+
+            DavFileSystem::symlink_metadata(&self.local, path).await
+        })
+    }
+
+    fn create_dir<'a>(&'a self, path: &'a DavPath, user: &'a Option<User>) -> FsFuture<'a, ()> {
+        Box::pin(async move {
+            self.writable(user.as_ref(), path).await?;
+            DavFileSystem::create_dir(&self.local, path).await
+        })
+    }
+
+    // only reached when MOVE or COPY replace an existing destination, DELETE
+    // moves entries to the trash in `delete`
+    fn remove_dir<'a>(&'a self, path: &'a DavPath, user: &'a Option<User>) -> FsFuture<'a, ()> {
+        Box::pin(async move {
+            self.removable(user.as_ref(), path).await?;
+            DavFileSystem::remove_dir(&self.local, path).await
+        })
+    }
+
+    fn remove_file<'a>(&'a self, path: &'a DavPath, user: &'a Option<User>) -> FsFuture<'a, ()> {
+        Box::pin(async move {
+            self.removable(user.as_ref(), path).await?;
+            DavFileSystem::remove_file(&self.local, path).await
+        })
+    }
+
+    fn rename<'a>(
+        &'a self,
+        from: &'a DavPath,
+        to: &'a DavPath,
+        user: &'a Option<User>,
+    ) -> FsFuture<'a, ()> {
+        Box::pin(async move {
+            self.removable(user.as_ref(), from).await?;
+            self.writable(user.as_ref(), to).await?;
+            // folders are moved as a whole
+            self.tree_writable(user.as_ref(), from)?;
+            self.tree_writable(user.as_ref(), to)?;
+            DavFileSystem::rename(&self.local, from, to).await
+        })
+    }
+
+    fn copy<'a>(
+        &'a self,
+        from: &'a DavPath,
+        to: &'a DavPath,
+        user: &'a Option<User>,
+    ) -> FsFuture<'a, ()> {
+        // only called for files, folders are copied entry by entry through
+        // `read_dir`, which leaves out hidden entries
+        Box::pin(async move {
+            self.readable(user.as_ref(), from).await?;
+            self.writable(user.as_ref(), to).await?;
+            self.tree_writable(user.as_ref(), to)?;
+            DavFileSystem::copy(&self.local, from, to).await
+        })
+    }
+}
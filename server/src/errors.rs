@@ -0,0 +1,79 @@
+use axum::{
+    body::{Body, to_bytes},
+    extract::Request,
+    http::{HeaderMap, HeaderValue, header},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use file_share_app::error_template::{AppError, error_page};
+use leptos::server_fn::error::SERVER_FN_ERROR_HEADER;
+
+/// Server function errors are short JSON objects, anything larger isn't one.
+const MAX_SERVER_FN_ERROR: usize = 64 * 1024;
+
+/// Whether the client is a browser that wants a page.
+pub fn wants_html(headers: &HeaderMap) -> bool {
+    headers
+        .get(header::ACCEPT)
+        .and_then(|accept| accept.to_str().ok())
+        .is_some_and(|accept| accept.contains("text/html"))
+}
+
+/// `error` as an error page for browsers and as JSON for other clients.
+pub fn error_response(error: AppError, html: bool) -> Response {
+    let mut response = error.into_response();
+    set_body(&mut response, html);
+    response
+}
+
+/// Replaces the plain text body of a response created from an [`AppError`],
+/// keeping its status, headers and extensions.
+fn set_body(response: &mut Response, html: bool) {
+    let Some(error) = response.extensions_mut().remove::<AppError>() else {
+        return;
+    };
+
+    let (content_type, body) = if html {
+        ("text/html; charset=utf-8", error_page(error))
+    } else {
+        ("application/json", error.to_json())
+    };
+
+    let headers = response.headers_mut();
+    headers.insert(header::CONTENT_TYPE, HeaderValue::from_static(content_type));
+    headers.remove(header::CONTENT_LENGTH);
+
+    *response.body_mut() = Body::from(body);
+}
+
+/// Turns errors of handlers into error pages or JSON, depending on what the
+/// client accepts.
+///
+/// Server functions always answer errors with `500 Internal Server Error`,
+/// so their responses get the status of the error instead.
+pub async fn render(request: Request, next: Next) -> Response {
+    let html = wants_html(request.headers());
+
+    let mut response = next.run(request).await;
+
+    if response.extensions().get::<AppError>().is_some() {
+        set_body(&mut response, html);
+        return response;
+    }
+
+    if !response.headers().contains_key(SERVER_FN_ERROR_HEADER) {
+        return response;
+    }
+
+    let (mut parts, body) = response.into_parts();
+
+    let Ok(bytes) = to_bytes(body, MAX_SERVER_FN_ERROR).await else {
+        return Response::from_parts(parts, Body::empty());
+    };
+
+    if let Ok(error) = serde_json::from_slice::<AppError>(&bytes) {
+        parts.status = error.status_code();
+    }
+
+    Response::from_parts(parts, Body::from(bytes))
+}
@@ -1,26 +1,34 @@
 mod archive;
+mod thumbnail;
 
-use std::{
-    collections::HashMap,
-    ops::Not,
-    path::{self, PathBuf},
-};
+use std::{collections::HashMap, net::SocketAddr, path::PathBuf, pin::pin, sync::Arc};
 
-pub use archive::Method;
+pub use archive::{Filter, Method};
 use axum::{
+    Extension,
     body::Body,
-    extract::{Multipart, Path, Query, State},
-    http::{HeaderValue, Request, StatusCode, Uri, header},
-    response::IntoResponse,
+    extract::{ConnectInfo, Form, Multipart, Path, Query, State},
+    http::{HeaderMap, HeaderValue, Method as HttpMethod, Request, StatusCode, Uri, header},
+    response::{IntoResponse, Response},
 };
 use file_share_app::{
-    AppConfig, AppState, shell,
-    utils::{format_bytes, try_decode_path},
+    AppConfig, AppState,
+    auth::User,
+    error_template::AppError,
+    metrics, progress,
+    sandbox::SandboxedPath,
+    shares::{self, ShareError},
+    shell,
+    uploads::{self, UploadError},
+    utils::{attachment_disposition, decode_path, format_bytes},
 };
-use leptos::{logging, prelude::provide_context};
+use leptos::logging;
+use rand_core::{OsRng, RngCore as _};
 use rust_embed::RustEmbed;
-use tokio::io::AsyncWriteExt;
+use serde::Deserialize;
+use tokio::io::AsyncWriteExt as _;
 use tokio_util::io::ReaderStream;
+use tower_http::services::ServeFile;
 
 #[derive(RustEmbed)]
 #[folder = "../target/site"]
@@ -48,61 +56,211 @@ pub async fn file_and_error_handler(
         return (StatusCode::OK, [header], file.data).into_response();
     }
 
+    let leptos_options = app_state.leptos_options.clone();
     let handler = leptos_axum::render_app_to_stream_with_context(
-        move || provide_context(app_state.app_config.clone()),
-        move || shell(app_state.leptos_options.clone()),
+        move || app_state.provide_contexts(),
+        move || shell(leptos_options.clone()),
     );
     handler(request).await.into_response()
 }
 
+/// The exact path requested below `prefix`.
+///
+/// The [`Path`] extractor rejects names that aren't valid UTF-8, so the path
+/// is decoded from the URI instead.
+fn requested_path(uri: &Uri, prefix: &str) -> PathBuf {
+    decode_path(uri.path().strip_prefix(prefix).unwrap_or_default())
+}
+
+/// Serves a single file from the shared directory.
+///
+/// # Errors
+///
+/// Returns an error if the file doesn't exist, is hidden from the user or
+/// can't be read.
+pub async fn serve_file(
+    State(app_config): State<AppConfig>,
+    user: Option<Extension<User>>,
+    uri: Uri,
+    request: Request<Body>,
+) -> Result<Response, AppError> {
+    let user = user.map(|Extension(user)| user);
+    let path = requested_path(&uri, "/files/");
+
+    let path = resolve_readable(&app_config, user.as_ref(), &path).await?;
+
+    if !tokio::fs::metadata(&path).await.is_ok_and(|m| m.is_file()) {
+        return Err(AppError::NotFound);
+    }
+
+    match ServeFile::new(path).try_call(request).await {
+        Ok(response) => Ok(response.into_response()),
+        Err(err) => Err(AppError::Io(format!("Failed to serve file: {err}"))),
+    }
+}
+
+/// Serves a thumbnail of an image from the shared directory.
+///
+/// # Errors
+///
+/// Returns an error if the file doesn't exist, is hidden from the user or
+/// isn't a supported image.
+pub async fn serve_thumbnail(
+    State(app_config): State<AppConfig>,
+    user: Option<Extension<User>>,
+    uri: Uri,
+) -> Result<Response, AppError> {
+    let user = user.map(|Extension(user)| user);
+    let path = requested_path(&uri, "/thumbnail/");
+
+    let file = resolve_readable(&app_config, user.as_ref(), &path).await?;
+
+    let modified = match tokio::fs::metadata(&file).await {
+        Ok(metadata) if metadata.is_file() => metadata.modified(),
+        _ => return Err(AppError::NotFound),
+    };
+
+    let Ok(modified) = modified else {
+        return Err(AppError::Io("Modification times are not supported".into()));
+    };
+
+    let cache_dir = thumbnail_dir(&app_config);
+
+    match thumbnail::thumbnail(&cache_dir, &path, &file, modified).await {
+        Ok(bytes) => {
+            let headers = [
+                (header::CONTENT_TYPE, thumbnail::mimetype(&bytes)),
+                (header::CACHE_CONTROL, "private, max-age=3600"),
+            ];
+            Ok((headers, bytes).into_response())
+        },
+        Err(thumbnail::Error::Image(e)) => Err(AppError::UnsupportedMediaType(e.to_string())),
+        Err(e) => {
+            logging::error!("Failed to create a thumbnail of {}: {e}", file.display());
+            Err(AppError::Io(format!("Failed to create a thumbnail: {e}")))
+        },
+    }
+}
+
+fn thumbnail_dir(app_config: &AppConfig) -> PathBuf {
+    app_config.state_dir().join("thumbnails")
+}
+
+/// Delete cached thumbnails that weren't created recently.
+pub async fn cleanup_thumbnails(app_config: &AppConfig) {
+    thumbnail::cleanup(&thumbnail_dir(app_config)).await;
+}
+
 /// Handles archive requests.
-#[allow(clippy::implicit_hasher)]
 pub async fn handle_archive_with_path<'a>(
-    State(AppConfig { target_dir, .. }): State<AppConfig>,
-    Path(path): Path<String>,
-    Query(params): Query<HashMap<String, String>>,
+    State(app_config): State<AppConfig>,
+    user: Option<Extension<User>>,
+    uri: Uri,
+    Query(params): Query<Vec<(String, String)>>,
 ) -> impl IntoResponse + use<'a> {
+    let path = requested_path(&uri, "/archive/");
+
     logging::log!("Handling archive with path '{path:?}' and params '{params:?}'");
 
-    let Some(path) = safe_join_path(&target_dir, try_decode_path(&path).as_ref()) else {
-        return (StatusCode::BAD_REQUEST, format!("Invalid path: {path}")).into_response();
-    };
+    let user = user.map(|Extension(user)| user);
 
-    handle_archive(path, params.get("method"))
-        .await
-        .into_response()
+    archive_request(app_config, user, &path, &params).await
 }
 
 /// Handles archive requests.
-#[allow(clippy::implicit_hasher)]
 pub async fn handle_archive_without_path(
-    State(AppConfig { target_dir, .. }): State<AppConfig>,
-    Query(params): Query<HashMap<String, String>>,
+    State(app_config): State<AppConfig>,
+    user: Option<Extension<User>>,
+    Query(params): Query<Vec<(String, String)>>,
 ) -> impl IntoResponse + use<> {
     logging::log!("Handling archive without path and with params '{params:?}'");
-    handle_archive(target_dir, params.get("method")).await
+
+    let user = user.map(|Extension(user)| user);
+
+    archive_request(app_config, user, std::path::Path::new(""), &params).await
+}
+
+/// Archives the folder `path`, or only the entries of it named by repeated
+/// `name` parameters when the `selection` parameter is present.
+async fn archive_request(
+    app_config: AppConfig,
+    user: Option<User>,
+    path: &std::path::Path,
+    params: &[(String, String)],
+) -> Result<Response, AppError> {
+    let values = |key: &'static str| {
+        params
+            .iter()
+            .filter(move |(name, _)| name == key)
+            .map(|(_, value)| value)
+    };
+
+    let dir = resolve_readable(&app_config, user.as_ref(), path).await?;
+
+    let Some(dir_name) = dir.file_name() else {
+        return Err(AppError::InvalidPath(format!(
+            "Invalid path (missing folder name): '{}'",
+            dir.display()
+        )));
+    };
+
+    let (roots, name) = if values("selection").next().is_none() {
+        (vec![dir.clone()], dir_name.display().to_string())
+    } else {
+        let mut roots = Vec::new();
+
+        for name in values("name") {
+            let entry = path.join(name);
+            roots.push(resolve_readable(&app_config, user.as_ref(), &entry).await?);
+        }
+
+        if roots.is_empty() {
+            return Err(AppError::BadRequest("No entries selected".into()));
+        }
+
+        (roots, format!("{} (selection)", dir_name.display()))
+    };
+
+    let filter = archive_filter(app_config, user).await?;
+
+    handle_archive(roots, &name, values("method").next(), filter).await
+}
+
+/// Creates a filter that leaves out paths hidden from the user.
+///
+/// Archives don't follow symlinks below their roots, so the paths passed to
+/// the filter are where the entries really are.
+async fn archive_filter(app_config: AppConfig, user: Option<User>) -> Result<Filter, AppError> {
+    let root = app_config
+        .sandbox()
+        .resolve()
+        .await
+        .map_err(|e| AppError::invalid_path(std::path::Path::new(""), e))?;
+
+    Ok(Arc::new(move |path: &std::path::Path| {
+        path.strip_prefix(&root)
+            .is_ok_and(|relative| app_config.access(user.as_ref(), relative).can_read())
+    }))
 }
 
 #[allow(clippy::unused_async)] // has to be in an async context, but doesn't await directly
-async fn handle_archive(path: PathBuf, method: Option<&String>) -> impl IntoResponse + use<> {
+async fn handle_archive(
+    roots: Vec<PathBuf>,
+    name: &str,
+    method: Option<&String>,
+    filter: Filter,
+) -> Result<Response, AppError> {
     let method = method.map_or_else(Default::default, String::as_str);
 
     let Ok(archive_method) = Method::try_from(method) else {
-        return (
-            StatusCode::BAD_REQUEST,
-            format!("Invalid archive method: {method}"),
-        )
-            .into_response();
+        return Err(AppError::BadRequest(format!(
+            "Invalid archive method: {method}"
+        )));
     };
 
-    let Some(name) = path.file_name() else {
-        return (
-            StatusCode::BAD_REQUEST,
-            format!("Invalid path (missing folder name): '{}'", path.display()),
-        )
-            .into_response();
-    };
-    let file_name = format!("{}.{}", name.display(), archive_method);
+    let file_name = format!("{name}.{archive_method}");
+
+    metrics::count_archive(&archive_method.to_string());
 
     logging::log!("Creating: {file_name}");
 
@@ -110,7 +268,10 @@ async fn handle_archive(path: PathBuf, method: Option<&String>) -> impl IntoResp
     let stream = ReaderStream::new(reader);
 
     tokio::spawn(async move {
-        if let Err(err) = archive_method.create_archive(path, &mut writer).await {
+        if let Err(err) = archive_method
+            .create_archive(&roots, &mut writer, filter)
+            .await
+        {
             logging::error!("Error during archive creation: {err:?}");
             writer.shutdown().await.expect("Failed to shutdown writer");
         }
@@ -119,7 +280,7 @@ async fn handle_archive(path: PathBuf, method: Option<&String>) -> impl IntoResp
     let headers: [(_, HeaderValue); 6] = [
         (
             header::CONTENT_DISPOSITION,
-            format!(r#"attachment; filename="{file_name}""#).parse(),
+            attachment_disposition(&file_name).parse(),
         ),
         (header::CONTENT_TYPE, archive_method.mimetype().parse()),
         (header::TRANSFER_ENCODING, "chunked".parse()),
@@ -129,96 +290,317 @@ async fn handle_archive(path: PathBuf, method: Option<&String>) -> impl IntoResp
     ]
     .map(|(key, value)| (key, value.expect("The headers are valid")));
 
-    (headers, Body::from_stream(stream)).into_response()
+    Ok((headers, Body::from_stream(stream)).into_response())
+}
+
+/// Parameters of a share link download, from the query or a form.
+#[derive(Debug, Deserialize)]
+pub struct ShareDownload {
+    /// Only taken from POSTed forms
+    password: Option<String>,
+    /// Archive method, for folders
+    method: Option<String>,
+}
+
+/// Whether a request resumes a download or seeks in it, i.e. its first
+/// `Range` starts somewhere after the beginning of the file.
+///
+/// Ranges from the end (`bytes=-500`) may cover the whole file, they and
+/// anything that isn't a valid range start a download.
+fn resumes_download(headers: &HeaderMap) -> bool {
+    let Some(ranges) = headers
+        .get(header::RANGE)
+        .and_then(|range| range.to_str().ok())
+        .and_then(|range| range.trim().strip_prefix("bytes="))
+    else {
+        return false;
+    };
+
+    let first = ranges.split(',').next().unwrap_or_default().trim();
+
+    let Some((start, end)) = first.split_once('-') else {
+        return false;
+    };
+
+    let end_is_valid = end.is_empty() || end.parse::<u64>().is_ok();
+
+    start.parse::<u64>().is_ok_and(|start| start > 0) && end_is_valid
+}
+
+/// Serves the file or folder of a share link, to anyone who has the link.
+///
+/// The password is checked and the download counted before anything is
+/// sent. Requests for later parts of a file don't count when the client's
+/// download was counted before, so that resuming or seeking doesn't use up
+/// the link.
+///
+/// # Errors
+///
+/// Returns an error if the link is unknown, used up, expired or the
+/// password is wrong, or its entry can't be served.
+pub async fn serve_share(
+    State(app_config): State<AppConfig>,
+    Path(id): Path<String>,
+    connect_info: Option<Extension<ConnectInfo<SocketAddr>>>,
+    request_method: HttpMethod,
+    headers: HeaderMap,
+    Form(params): Form<ShareDownload>,
+) -> Result<Response, AppError> {
+    let link = shares::get(&app_config, &id)
+        .await
+        .map_err(|e| share_error(&id, e))?;
+
+    // the link is used without an account, so it sees what anonymous users do
+    let path = match app_config.sandbox().join(&link.path) {
+        Ok(entry) => app_config.resolve_readable(None, &entry).await,
+        Err(e) => Err(e),
+    }
+    .map_err(|e| AppError::invalid_path(&link.path, e))?;
+
+    // in a URL, the password would end up in the browser history and logs
+    let password = if request_method == HttpMethod::POST {
+        params.password.as_deref()
+    } else {
+        None
+    };
+
+    let client = connect_info.map(|Extension(ConnectInfo(addr))| addr.ip().to_canonical());
+    let resumes = !link.is_folder && resumes_download(&headers);
+
+    shares::redeem(&app_config, &id, password, client, resumes)
+        .await
+        .map_err(|e| share_error(&id, e))?;
+
+    let name = link
+        .path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+
+    if link.is_folder {
+        let filter = archive_filter(app_config, None).await?;
+
+        return handle_archive(vec![path], &name, params.method.as_ref(), filter).await;
+    }
+
+    // downloads may be POSTed with the password, but files are served like GETs
+    let mut request = Request::new(Body::empty());
+    *request.headers_mut() = headers;
+
+    match ServeFile::new(path).try_call(request).await {
+        Ok(mut response) => {
+            if let Ok(disposition) = attachment_disposition(&name).parse() {
+                response
+                    .headers_mut()
+                    .insert(header::CONTENT_DISPOSITION, disposition);
+            }
+            Ok(response.into_response())
+        },
+        Err(err) => Err(AppError::Io(format!("Failed to serve file: {err}"))),
+    }
 }
 
-const UPLOAD_DISABLED: (StatusCode, &str) = (StatusCode::FORBIDDEN, "Upload is not enabled");
+fn share_error(id: &str, err: ShareError) -> AppError {
+    logging::warn!("[{id}]\tshare link refused: {err}");
+    err.into()
+}
 
-fn safe_join_path(base_dir: &path::Path, path: &str) -> Option<PathBuf> {
-    path.contains("..").not().then(|| base_dir.join(path))
+/// Resolves a requested path to an existing path inside the shared directory
+/// that the user is allowed to read.
+async fn resolve_readable(
+    app_config: &AppConfig,
+    user: Option<&User>,
+    path: &std::path::Path,
+) -> Result<PathBuf, AppError> {
+    let sandboxed = app_config
+        .sandbox()
+        .join(path)
+        .map_err(|e| AppError::invalid_path(path, e))?;
+
+    app_config
+        .resolve_readable(user, &sandboxed)
+        .await
+        .map_err(|e| AppError::invalid_path(path, e))
 }
 
+const UPLOAD_DISABLED: &str = "Upload is not allowed to this location";
+
+/// Uploads to the folder `path`, see [`file_upload`].
+///
+/// # Errors
+///
+/// Returns an error if `path` is invalid or the upload fails.
 pub async fn file_upload_with_path(
     State(AppState { app_config, .. }): State<AppState>,
+    user: Option<Extension<User>>,
     Path(path): Path<String>,
+    Query(params): Query<HashMap<String, String>>,
     multipart: Multipart,
-) -> impl IntoResponse {
-    if !app_config.allow_upload {
-        return UPLOAD_DISABLED.into_response();
-    }
+) -> Result<Response, AppError> {
+    let base_path = app_config
+        .sandbox()
+        .join(&path)
+        .map_err(|e| AppError::invalid_path(std::path::Path::new(&path), e))?;
 
-    let Some(base_path) = safe_join_path(&app_config.target_dir, &path) else {
-        return (StatusCode::BAD_REQUEST, format!("Invalid path: {path}")).into_response();
-    };
+    let user = user.map(|Extension(user)| user);
 
-    file_upload(base_path, multipart).await.into_response()
+    file_upload(&app_config, user.as_ref(), base_path, &params, multipart).await
 }
 
+/// Uploads to the shared directory, see [`file_upload`].
+///
+/// # Errors
+///
+/// Returns an error if the upload fails.
 pub async fn file_upload_without_path(
     State(AppState { app_config, .. }): State<AppState>,
+    user: Option<Extension<User>>,
+    Query(params): Query<HashMap<String, String>>,
     multipart: Multipart,
-) -> impl IntoResponse {
-    if !app_config.allow_upload {
-        return UPLOAD_DISABLED.into_response();
+) -> Result<Response, AppError> {
+    let user = user.map(|Extension(user)| user);
+
+    file_upload(
+        &app_config,
+        user.as_ref(),
+        app_config.sandbox(),
+        &params,
+        multipart,
+    )
+    .await
+}
+
+/// Stores every file of a multipart body in `base_dir`.
+///
+/// Files are streamed to disk, so their size isn't limited by the available
+/// memory. Progress is reported under the `progress` id (or a random one
+/// when missing), the same way as for the web uploader. Existing
+/// files are handled according to the `conflict` policy (or the configured
+/// one when missing).
+///
+/// # Errors
+///
+/// Returns an error if the user can't write to `base_dir`, the body is
+/// invalid or a file can't be stored.
+pub async fn file_upload(
+    app_config: &AppConfig,
+    user: Option<&User>,
+    base_dir: SandboxedPath,
+    params: &HashMap<String, String>,
+    mut multipart: Multipart,
+) -> Result<Response, AppError> {
+    if !app_config.access(user, base_dir.relative()).can_write() {
+        return Err(app_config.write_denied(user, UPLOAD_DISABLED.into()));
     }
 
-    file_upload(app_config.target_dir, multipart)
-        .await
-        .into_response()
-}
+    let policy = match params.get("conflict").map(|policy| policy.parse()) {
+        None => app_config.on_conflict,
+        Some(Ok(policy)) => policy,
+        Some(Err(e)) => return Err(AppError::BadRequest(e)),
+    };
+
+    // concurrent uploads to the same folder must not share a progress entry,
+    // the first one to finish would close it for the others
+    let progress_id = params
+        .get("progress")
+        .map_or_else(|| format!("{:016x}", OsRng.next_u64()), Clone::clone);
+
+    let result = loop {
+        let field = match multipart.next_field().await {
+            Ok(Some(field)) => field,
+            Ok(None) => break Ok(StatusCode::OK.into_response()),
+            Err(e) if e.status() == StatusCode::PAYLOAD_TOO_LARGE => {
+                break Err(AppError::PayloadTooLarge(e.body_text()));
+            },
+            Err(e) => {
+                break Err(AppError::BadRequest(format!(
+                    "Invalid multipart body: {}",
+                    e.body_text()
+                )));
+            },
+        };
 
-pub async fn file_upload(base_dir: PathBuf, mut multipart: Multipart) -> impl IntoResponse {
-    while let Ok(Some(field)) = multipart.next_field().await {
-        let Some(file_name) = field.file_name() else {
+        let Some(file_name) = field.file_name().map(ToOwned::to_owned) else {
             continue;
         };
 
-        let Some(path) = safe_join_path(&base_dir, file_name) else {
-            return (
-                StatusCode::BAD_REQUEST,
-                format!("Invalid file name: {file_name}"),
-            )
-                .into_response();
+        let path = match base_dir.join_name(&file_name) {
+            Ok(path) => path,
+            Err(e) => break Err(AppError::invalid_path(std::path::Path::new(&file_name), e)),
         };
 
-        logging::log!("Uploading to {path:?}");
+        if !app_config.access(user, path.relative()).can_write() {
+            break Err(app_config.write_denied(user, UPLOAD_DISABLED.into()));
+        }
 
-        let mut file = match tokio::fs::File::create_new(&path).await {
-            Ok(file) => file,
-            Err(err) => {
-                return (
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    format!("Failed to create file: {err}"),
-                )
-                    .into_response();
-            },
+        let resolved = match path.resolve_new().await {
+            Ok(resolved) => resolved,
+            Err(e) => break Err(AppError::invalid_path(std::path::Path::new(&file_name), e)),
         };
 
-        let bytes = match field.bytes().await {
-            Ok(bytes) => bytes,
+        if !app_config
+            .real_access(user, &path, &resolved)
+            .await
+            .can_write()
+        {
+            break Err(app_config.write_denied(user, UPLOAD_DISABLED.into()));
+        }
+
+        logging::log!("Uploading to {resolved:?}");
+
+        let written =
+            uploads::write_file(app_config, &resolved, policy, pin!(field), async |len| {
+                progress::add_chunk(&progress_id, len).await
+            })
+            .await;
+
+        match written {
+            Ok((path, written)) => {
+                logging::log!("Wrote {} to {}", format_bytes(written), path.display());
+            },
+            Err(UploadError::Exists) => {
+                break Err(AppError::Conflict(format!(
+                    "File '{file_name}' already exists"
+                )));
+            },
             Err(e) => {
-                return (
-                    StatusCode::BAD_REQUEST,
-                    format!("Invalid file content: {e}"),
-                )
-                    .into_response();
+                logging::error!("Failed to upload {}: {e}", resolved.display());
+                break Err(AppError::Io(format!("Failed to write file: {e}")));
             },
-        };
+        }
+    };
 
-        logging::log!(
-            "Writing {} bytes to {}",
-            format_bytes(bytes.len() as u64),
-            path.display()
-        );
+    progress::finish(&progress_id).await;
 
-        if let Err(err) = file.write_all(&bytes).await {
-            return (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                format!("Failed to write file: {err}"),
-            )
-                .into_response();
-        }
-    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn test_resumes_download() {
+        let resumes = |range: Option<&str>| {
+            let mut headers = HeaderMap::new();
+            if let Some(range) = range {
+                headers.insert(header::RANGE, HeaderValue::from_str(range).unwrap());
+            }
+            resumes_download(&headers)
+        };
 
-    StatusCode::OK.into_response()
+        assert!(!resumes(None));
+        assert!(!resumes(Some("bytes=0-")));
+        assert!(!resumes(Some("bytes=00-")));
+        assert!(!resumes(Some("bytes=0-499")));
+        assert!(!resumes(Some("bytes=-99999999999")));
+        assert!(!resumes(Some("bytes=0-0, 1-")));
+        assert!(!resumes(Some("items=1-")));
+        assert!(!resumes(Some("bytes=x-")));
+        assert!(!resumes(Some("bytes=1-x")));
+
+        assert!(resumes(Some("bytes=1-")));
+        assert!(resumes(Some("bytes=500-999")));
+        assert!(resumes(Some(" bytes=500-, 0-99")));
+    }
 }
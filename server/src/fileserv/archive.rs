@@ -1,11 +1,11 @@
 #![allow(clippy::items_after_statements)]
 
-use std::{fmt, path::Path};
+use std::{ffi::OsStr, fmt, path::Path, sync::Arc};
 
 use async_compression::tokio::write::{GzipEncoder, ZstdEncoder};
-use async_walkdir::WalkDir;
+use async_walkdir::{Filtering, WalkDir};
 use async_zip::{
-    tokio::write::ZipFileWriter, Compression, StringEncoding, ZipEntryBuilder, ZipString,
+    Compression, StringEncoding, ZipEntryBuilder, ZipString, tokio::write::ZipFileWriter,
 };
 use cfg_if::cfg_if;
 use thiserror::Error as ThisError;
@@ -37,6 +37,9 @@ pub enum Error {
     ArchiveCreation(String, Box<Error>),
 }
 
+/// Decides whether a path (and everything below it) goes into the archive.
+pub type Filter = Arc<dyn Fn(&Path) -> bool + Send + Sync>;
+
 #[derive(Default, Debug, Clone, Copy)]
 pub enum Method {
     #[default]
@@ -57,25 +60,33 @@ impl Method {
         }
     }
 
-    /// Create an archive from given dir using current method.
+    /// Create an archive from given files and dirs using current method.
     ///
-    /// Writes an output stream into a passed [`AsyncWrite`] sink.
+    /// Every root is stored under its own name at the top of the archive,
+    /// dirs with everything below them, except that zips of a single dir
+    /// only hold its content. Writes an output stream into a passed
+    /// [`AsyncWrite`] sink. Paths rejected by `filter` are left out.
     ///
     /// # Errors
     ///
     /// This function will return an error if there is any error during the
-    /// archive creation, usually due to IO or invalid input dir.
-    pub async fn create_archive<P, W>(self, dir: P, out: W) -> Result<(), Error>
+    /// archive creation, usually due to IO or invalid input paths.
+    pub async fn create_archive<P, W>(
+        self,
+        roots: &[P],
+        out: W,
+        filter: Filter,
+    ) -> Result<(), Error>
     where
         P: AsRef<Path>,
         W: AsyncWrite + Unpin + Send + Sync,
     {
-        let dir = dir.as_ref();
+        let roots = roots.iter().map(AsRef::as_ref).collect::<Vec<_>>();
         match self {
-            Method::Tar => tar_dir(dir, out).await,
-            Method::TarGz => tar_gz(dir, out).await,
-            Method::TarZstd => tar_zstd(dir, out).await,
-            Method::Zip => zip_dir(dir, out).await,
+            Method::Tar => tar(&roots, out, filter).await,
+            Method::TarGz => tar_gz(&roots, out, filter).await,
+            Method::TarZstd => tar_zstd(&roots, out, filter).await,
+            Method::Zip => zip(&roots, out, filter).await,
         }
     }
 }
@@ -106,14 +117,38 @@ impl fmt::Display for Method {
     }
 }
 
-/// Write a gzipped tarball of `dir` in `out`.
-async fn tar_gz<W>(dir: &Path, out: W) -> Result<(), Error>
+/// Name of a root in the archive.
+fn root_name(root: &Path) -> Result<&OsStr, Error> {
+    root.file_name()
+        .ok_or_else(|| Error::InvalidPath("Directory name terminates in \"..\"".to_string()))
+}
+
+async fn is_dir(path: &Path) -> bool {
+    fs::metadata(path).await.is_ok_and(|meta| meta.is_dir())
+}
+
+/// Walk `dir` recursively, skipping whatever `filter` rejects.
+fn walk_filtered(dir: &Path, filter: Filter) -> WalkDir {
+    WalkDir::new(dir).filter(move |entry| {
+        let filter = filter.clone();
+        async move {
+            if filter(&entry.path()) {
+                Filtering::Continue
+            } else {
+                Filtering::IgnoreDir
+            }
+        }
+    })
+}
+
+/// Write a gzipped tarball of `roots` in `out`.
+async fn tar_gz<W>(roots: &[&Path], out: W, filter: Filter) -> Result<(), Error>
 where
     W: AsyncWrite + Unpin + Send + Sync,
 {
     let mut encoder = GzipEncoder::new(out);
 
-    tar_dir(dir, &mut encoder).await?;
+    tar(roots, &mut encoder, filter).await?;
 
     encoder.shutdown().await.map_err(|e| {
         Error::ArchiveCreation(
@@ -128,14 +163,14 @@ where
     Ok(())
 }
 
-/// Write a zstd-compressed tarball of `dir` in `out`.
-async fn tar_zstd<W>(dir: &Path, out: W) -> Result<(), Error>
+/// Write a zstd-compressed tarball of `roots` in `out`.
+async fn tar_zstd<W>(roots: &[&Path], out: W, filter: Filter) -> Result<(), Error>
 where
     W: AsyncWrite + Unpin + Send + Sync,
 {
     let mut encoder = ZstdEncoder::new(out);
 
-    tar_dir(dir, &mut encoder).await?;
+    tar(roots, &mut encoder, filter).await?;
 
     encoder
         .shutdown()
@@ -145,31 +180,18 @@ where
     Ok(())
 }
 
-/// Write a tarball of `dir` in `out`.
-async fn tar_dir<W>(dir: &Path, out: W) -> Result<(), Error>
+/// Write a tarball of `roots` in `out`.
+async fn tar<W>(roots: &[&Path], out: W, filter: Filter) -> Result<(), Error>
 where
     W: AsyncWrite + Unpin + Send + Sync,
 {
-    let folder_name = dir
-        .file_name()
-        .ok_or_else(|| Error::InvalidPath("Directory name terminates in \"..\"".to_string()))?;
-
     let mut builder = Builder::new_non_terminated(out);
 
     builder.follow_symlinks(false);
 
-    builder
-        .append_dir_all(folder_name, dir)
-        .await
-        .map_err(|e| {
-            Error::Io(
-                format!(
-                    "Failed to append the content of {} to the TAR archive",
-                    dir.display()
-                ),
-                e,
-            )
-        })?;
+    for root in roots {
+        append_to_tar(&mut builder, root, filter.clone()).await?;
+    }
 
     builder
         .finish()
@@ -179,9 +201,70 @@ where
     Ok(())
 }
 
-/// Write a zip archive of `dir` in `out`.
-/// The content of `dir` will be saved in the archive as a folder named `dir`.
-async fn zip_dir<W>(dir: &Path, out: W) -> Result<(), Error>
+/// Append `root` to a tarball under its own name, a dir with everything
+/// below it.
+async fn append_to_tar<W>(
+    builder: &mut Builder<W>,
+    root: &Path,
+    filter: Filter,
+) -> Result<(), Error>
+where
+    W: AsyncWrite + Unpin + Send + Sync,
+{
+    let root_name = root_name(root)?;
+
+    let append_error = |path: &Path, e| {
+        Error::Io(
+            format!("Failed to append {} to the TAR archive", path.display()),
+            e,
+        )
+    };
+
+    if !is_dir(root).await {
+        builder
+            .append_path_with_name(root, root_name)
+            .await
+            .map_err(|e| append_error(root, e))?;
+
+        return Ok(());
+    }
+
+    builder
+        .append_dir(root_name, root)
+        .await
+        .map_err(|e| append_error(root, e))?;
+
+    let mut walker = walk_filtered(root, filter);
+
+    while let Some(entry) = walker.next().await {
+        let Ok(entry) = entry else {
+            continue;
+        };
+
+        let path = entry.path();
+        let name = path.strip_prefix(root).map_err(|_| {
+            Error::InvalidPath(format!(
+                "Failed to strip {} from {}",
+                root.display(),
+                path.display()
+            ))
+        })?;
+
+        builder
+            .append_path_with_name(&path, Path::new(root_name).join(name))
+            .await
+            .map_err(|e| append_error(&path, e))?;
+    }
+
+    Ok(())
+}
+
+/// Write a zip archive of `roots` in `out`.
+///
+/// A single dir is stored with its content at the top of the archive, like
+/// zips of whole folders always were. Otherwise every root is stored under
+/// its own name.
+async fn zip<W>(roots: &[&Path], out: W, filter: Filter) -> Result<(), Error>
 where
     W: AsyncWrite + Unpin,
 {
@@ -192,18 +275,37 @@ where
         chrono::Local::now().to_rfc2822()
     ));
 
-    let mut walker = WalkDir::new(dir);
-
-    while let Some(entry) = walker.next().await {
-        let Ok(entry) = entry else {
-            continue;
+    let single_dir = match roots {
+        [root] => is_dir(root).await,
+        _ => false,
+    };
+
+    for root in roots {
+        // names are relative to the parent when they start with the root
+        let base_dir = if single_dir {
+            root
+        } else {
+            root.parent().unwrap_or(root)
         };
 
-        if !entry.file_type().await.is_ok_and(|t| t.is_file()) {
+        if !is_dir(root).await {
+            add_file_to_zip(root, base_dir, &mut zip).await?;
             continue;
         }
 
-        add_file_to_zip(&entry.path(), dir, &mut zip).await?;
+        let mut walker = walk_filtered(root, filter.clone());
+
+        while let Some(entry) = walker.next().await {
+            let Ok(entry) = entry else {
+                continue;
+            };
+
+            if !entry.file_type().await.is_ok_and(|t| t.is_file()) {
+                continue;
+            }
+
+            add_file_to_zip(&entry.path(), base_dir, &mut zip).await?;
+        }
     }
 
     zip.close().await.map_err(|e| {
@@ -232,8 +334,9 @@ where
         ))
     })?;
 
+    // names that aren't valid UTF-8 are stored as raw bytes instead
     let zip_name = ZipString::new(
-        name.to_string_lossy().as_bytes().to_owned(),
+        name.as_os_str().as_encoded_bytes().to_owned(),
         StringEncoding::Utf8,
     );
 
@@ -0,0 +1,156 @@
+use std::{
+    fmt::Write as _,
+    io::{self, Cursor},
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use image::{DynamicImage, ImageFormat, ImageReader, Limits};
+use leptos::logging;
+use rand_core::{OsRng, RngCore as _};
+use sha2::{Digest as _, Sha256};
+use thiserror::Error as ThisError;
+use tokio::fs;
+
+/// Thumbnails fit into a square of this size.
+pub const SIZE: u32 = 256;
+
+/// Cached thumbnails are deleted after this long and created again when
+/// they're requested, so that the thumbnails of deleted or changed images
+/// don't pile up.
+const CACHE_TTL: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
+/// Larger images aren't decoded, a few crafted bytes could claim any size.
+const MAX_DIMENSION: u32 = 16 * 1024;
+const MAX_ALLOC: u64 = 512 * 1024 * 1024;
+
+#[derive(Debug, ThisError)]
+pub enum Error {
+    /// The file isn't an image in one of the supported formats
+    #[error("Failed to create a thumbnail\ncaused by: {0}")]
+    Image(#[from] image::ImageError),
+
+    /// Reading the image or accessing the cache failed
+    #[error(transparent)]
+    Io(#[from] io::Error),
+}
+
+/// A thumbnail of the image at `file`, encoded as JPEG or as PNG when the
+/// image is transparent.
+///
+/// Thumbnails are cached in `cache_dir` by the `relative` path of the image
+/// and its modification time, so an image changed since gets a new one.
+///
+/// # Errors
+///
+/// Returns an error if the file isn't a supported image or the cache can't
+/// be written.
+pub async fn thumbnail(
+    cache_dir: &Path,
+    relative: &Path,
+    file: &Path,
+    modified: SystemTime,
+) -> Result<Vec<u8>, Error> {
+    let cached = cache_dir.join(cache_key(relative, modified));
+
+    match fs::read(&cached).await {
+        Ok(bytes) => return Ok(bytes),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {},
+        Err(e) => return Err(e.into()),
+    }
+
+    let file = file.to_owned();
+    let bytes = tokio::task::spawn_blocking(move || render(&file))
+        .await
+        .map_err(io::Error::other)??;
+
+    fs::create_dir_all(cache_dir).await?;
+
+    // renamed into place, so that a concurrent request never reads half of it
+    let partial = cached.with_extension(format!("{:016x}.partial", OsRng.next_u64()));
+    fs::write(&partial, &bytes).await?;
+    fs::rename(&partial, &cached).await?;
+
+    Ok(bytes)
+}
+
+/// MIME type of a thumbnail created by [`thumbnail`].
+#[must_use]
+pub fn mimetype(thumbnail: &[u8]) -> &'static str {
+    image::guess_format(thumbnail)
+        .map_or("application/octet-stream", |format| format.to_mime_type())
+}
+
+fn cache_key(relative: &Path, modified: SystemTime) -> PathBuf {
+    let modified = modified
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos();
+
+    let mut hasher = Sha256::new();
+    hasher.update(relative.as_os_str().as_encoded_bytes());
+    hasher.update(modified.to_le_bytes());
+    hasher.update(SIZE.to_le_bytes());
+
+    let key = hasher
+        .finalize()
+        .iter()
+        .fold(String::new(), |mut key, byte| {
+            let _ = write!(key, "{byte:02x}");
+            key
+        });
+
+    PathBuf::from(key)
+}
+
+/// Delete the thumbnails in `cache_dir` created more than [`CACHE_TTL`] ago,
+/// along with files left over by failed writes.
+pub async fn cleanup(cache_dir: &Path) {
+    let now = SystemTime::now();
+
+    let Ok(mut entries) = fs::read_dir(cache_dir).await else {
+        return;
+    };
+
+    while let Ok(Some(entry)) = entries.next_entry().await {
+        let modified = entry.metadata().await.and_then(|meta| meta.modified());
+
+        let expired = modified
+            .ok()
+            .and_then(|modified| now.duration_since(modified).ok())
+            .is_some_and(|age| age > CACHE_TTL);
+
+        if !expired {
+            continue;
+        }
+
+        if let Err(e) = fs::remove_file(entry.path()).await {
+            logging::warn!("Failed to remove the thumbnail {:?}: {e}", entry.path());
+        }
+    }
+}
+
+fn render(file: &Path) -> Result<Vec<u8>, Error> {
+    let mut limits = Limits::default();
+    limits.max_image_width = Some(MAX_DIMENSION);
+    limits.max_image_height = Some(MAX_DIMENSION);
+    limits.max_alloc = Some(MAX_ALLOC);
+
+    let mut reader = ImageReader::open(file)?.with_guessed_format()?;
+    reader.limits(limits);
+
+    let image = reader.decode()?;
+    let thumbnail = image.thumbnail(SIZE, SIZE);
+
+    // JPEG has no transparency, but is a lot smaller for photos
+    let (thumbnail, format) = if thumbnail.color().has_alpha() {
+        (DynamicImage::from(thumbnail.to_rgba8()), ImageFormat::Png)
+    } else {
+        (DynamicImage::from(thumbnail.to_rgb8()), ImageFormat::Jpeg)
+    };
+
+    let mut bytes = Cursor::new(Vec::new());
+    thumbnail.write_to(&mut bytes, format)?;
+
+    Ok(bytes.into_inner())
+}
@@ -0,0 +1,207 @@
+use std::{
+    fmt,
+    net::{IpAddr, SocketAddr},
+    str::FromStr,
+};
+
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    http::Method,
+    middleware::Next,
+    response::Response,
+};
+use file_share_app::error_template::AppError;
+use ipnet::IpNet;
+
+use crate::errors::{error_response, wants_html};
+
+/// An address or a CIDR range, e.g. `::1` or `192.168.1.0/24`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IpRange(IpNet);
+
+impl IpRange {
+    fn contains(&self, ip: IpAddr) -> bool {
+        self.0.contains(&ip)
+    }
+}
+
+impl FromStr for IpRange {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        value
+            .parse::<IpNet>()
+            .or_else(|_| value.parse::<IpAddr>().map(IpNet::from))
+            .map(IpRange)
+            .map_err(|_| format!("Invalid address or CIDR range '{value}'"))
+    }
+}
+
+impl fmt::Display for IpRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // single addresses are shown the way they are written
+        match self.0 {
+            net if net.prefix_len() == net.max_prefix_len() => write!(f, "{}", net.addr()),
+            net => write!(f, "{net}"),
+        }
+    }
+}
+
+/// Clients that are let in. Deny rules win over allow rules, and when there
+/// are allow rules, only the clients they match are let in.
+#[derive(Debug, Clone, Default)]
+pub struct IpRules {
+    pub allow: Vec<IpRange>,
+    pub deny: Vec<IpRange>,
+}
+
+impl IpRules {
+    fn is_empty(&self) -> bool {
+        self.allow.is_empty() && self.deny.is_empty()
+    }
+
+    fn permits(&self, ip: IpAddr) -> bool {
+        !self.deny.iter().any(|range| range.contains(ip))
+            && (self.allow.is_empty() || self.allow.iter().any(|range| range.contains(ip)))
+    }
+}
+
+/// The rules for all requests and the additional ones for requests that
+/// change something.
+#[derive(Debug, Clone, Default)]
+pub struct IpFilter {
+    pub read: IpRules,
+    pub write: IpRules,
+}
+
+impl IpFilter {
+    pub fn is_open(&self) -> bool {
+        self.read.is_empty() && self.write.is_empty()
+    }
+}
+
+/// Server functions that only read, every other one changes something.
+const READ_ENDPOINTS: [&str; 10] = [
+    "list_dir",
+    "search",
+    "preview",
+    "watch",
+    "list_trash",
+    "list_shares",
+    "share_info",
+    "upload_progress",
+    "login",
+    "logout",
+];
+
+/// Whether a request to `path` changes files, the trash or share links.
+fn is_write(method: &Method, path: &str) -> bool {
+    if let Some(endpoint) = path.strip_prefix("/api/") {
+        !READ_ENDPOINTS.contains(&endpoint)
+    } else if path.starts_with("/dav") {
+        !matches!(method.as_str(), "GET" | "HEAD" | "OPTIONS" | "PROPFIND")
+    } else {
+        path.starts_with("/upload/")
+    }
+}
+
+/// Refuses clients the rules don't let in with `403 Forbidden`, as an error
+/// page for browsers and as JSON for other clients.
+pub async fn enforce(State(filter): State<IpFilter>, request: Request, next: Next) -> Response {
+    // IPv4 clients of dual-stack sockets show up as IPv4-mapped IPv6
+    let client = request
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| addr.ip().to_canonical());
+
+    let write = is_write(request.method(), request.uri().path());
+
+    let message = match client {
+        Some(client) if !filter.read.permits(client) => {
+            format!("Your address {client} isn't allowed to access this server")
+        },
+        Some(client) if write && !filter.write.permits(client) => {
+            format!("Your address {client} isn't allowed to make changes")
+        },
+        Some(_) => return next.run(request).await,
+        None => "Your address is unknown".to_owned(),
+    };
+
+    error_response(AppError::Forbidden(message), wants_html(request.headers()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rules(allow: &[&str], deny: &[&str]) -> IpRules {
+        let parse = |ranges: &[&str]| {
+            ranges
+                .iter()
+                .map(|range| range.parse().unwrap())
+                .collect::<Vec<_>>()
+        };
+
+        IpRules {
+            allow: parse(allow),
+            deny: parse(deny),
+        }
+    }
+
+    fn ip(value: &str) -> IpAddr {
+        value.parse().unwrap()
+    }
+
+    #[test]
+    pub fn test_ip_range() {
+        assert_eq!("::1".parse::<IpRange>().unwrap().to_string(), "::1");
+        assert_eq!(
+            "192.168.1.0/24".parse::<IpRange>().unwrap().to_string(),
+            "192.168.1.0/24"
+        );
+        assert!("192.168.1.0/33".parse::<IpRange>().is_err());
+        assert!("localhost".parse::<IpRange>().is_err());
+    }
+
+    #[test]
+    pub fn test_permits() {
+        let lan = rules(&["192.168.1.0/24"], &[]);
+        assert!(lan.permits(ip("192.168.1.20")));
+        assert!(!lan.permits(ip("192.168.2.1")));
+
+        let local = rules(&["::1"], &[]);
+        assert!(local.permits(ip("::1")));
+        assert!(!local.permits(ip("::2")));
+
+        // clients of dual-stack sockets are canonicalized before the check
+        let private = rules(&["10.0.0.0/8"], &[]);
+        assert!(private.permits(ip("::ffff:10.0.0.1").to_canonical()));
+        assert!(!private.permits(ip("::ffff:11.0.0.1").to_canonical()));
+
+        let mixed = rules(&["10.0.0.0/8"], &["10.0.0.13"]);
+        assert!(mixed.permits(ip("10.0.0.12")));
+        assert!(!mixed.permits(ip("10.0.0.13")));
+
+        let open = rules(&[], &["10.0.0.13"]);
+        assert!(open.permits(ip("192.168.1.20")));
+        assert!(!open.permits(ip("10.0.0.13")));
+    }
+
+    #[test]
+    pub fn test_is_write() {
+        assert!(is_write(&Method::POST, "/api/delete"));
+        assert!(is_write(&Method::POST, "/api/rename"));
+        assert!(is_write(&Method::POST, "/api/upload_chunk"));
+        assert!(!is_write(&Method::POST, "/api/list_dir"));
+        assert!(!is_write(&Method::POST, "/api/search"));
+        assert!(!is_write(&Method::GET, "/api/watch"));
+        assert!(is_write(&Method::POST, "/upload/photos"));
+        assert!(!is_write(&Method::GET, "/files/photos/cat.jpg"));
+        assert!(!is_write(
+            &Method::from_bytes(b"PROPFIND").unwrap(),
+            "/dav/photos"
+        ));
+        assert!(is_write(&Method::PUT, "/dav/photos/cat.jpg"));
+        assert!(is_write(&Method::DELETE, "/dav/photos"));
+    }
+}
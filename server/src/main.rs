@@ -1,55 +1,120 @@
 #![warn(clippy::pedantic)]
 #![recursion_limit = "256"]
 
+pub mod access_log;
+pub mod auth;
 pub mod config;
+pub mod counted_body;
+pub mod dav;
+pub mod errors;
 pub mod fileserv;
+pub mod ip_filter;
+pub mod metrics;
+pub mod throttle;
+pub mod tls;
 
 use std::{
     fs::create_dir_all,
     io,
     net::{IpAddr, SocketAddr},
     process,
+    time::Duration,
 };
 
 use axum::{
     Router,
     extract::DefaultBodyLimit,
+    middleware,
     response::Redirect,
     routing::{get, post},
 };
 use colored::Colorize;
-use file_share_app::{App, AppConfig, AppState, shell};
+use file_share_app::{
+    App, AppConfig, AppState, auth::Auth, shares, shell, trash, uploads, watcher,
+};
 use futures::future::try_join_all;
 use if_addrs::Interface;
 use leptos::{
     logging::{error, warn},
-    prelude::{get_configuration, provide_context},
+    prelude::get_configuration,
 };
 use leptos_axum::{LeptosRoutes, generate_route_list};
-use tower_http::services::ServeDir;
 
 use crate::{
+    access_log::AccessLog,
+    auth::require_login,
     config::{Config, get_config},
     fileserv::{
         file_and_error_handler, file_upload_with_path, file_upload_without_path,
-        handle_archive_with_path, handle_archive_without_path,
+        handle_archive_with_path, handle_archive_without_path, serve_file, serve_share,
+        serve_thumbnail,
     },
 };
 
-const API_HELP_TEXT: &str = r"
+const API_HELP_TEXT: &str = r#"
 File Share
 ===========
 Endpoints:
 - /help                         -- show this help text
 - /api/list_dir path=           -- list the contents of a directory
+- /api/search path=&query=&min_size=&max_size=&modified_after=&modified_before=
+                                -- search a directory recursively, streams
+                                   one JSON object per hit and line
 - /api/new_folder name=&target= -- create a new folder with name in path
+- /api/watch path=              -- stream a line every time the entries of
+                                   a directory change
+- /api/preview path=            -- the start of a text file as HTML, Markdown
+                                   rendered and other text highlighted
+- /api/rename path=&name=&new_name=
+                                -- rename an entry in path
+- /api/move path=&name=&destination=
+                                -- move an entry into another folder
+- /api/copy path=&name=&destination=
+                                -- copy an entry (folders recursively)
+- /api/delete path=&name=       -- move an entry to the trash
+- /api/list_trash               -- list the entries in the trash
+- /api/restore_trash id=        -- restore an entry from the trash
+- /api/purge_trash id=          -- delete an entry in the trash permanently
+- /api/create_share path=&name=&expires_hours=&max_downloads=&password=
+                                -- create a link to an entry, usable without
+                                   an account, optionally limited
+- /api/list_shares              -- list the share links
+- /api/revoke_share id=         -- delete a share link
+- /share/:id/download?method=   -- download the entry of a share link, method
+                                   for folders, password= only in a POSTed
+                                   form
 - /archive/*path?method=        -- create an archive from a path
 - /archive?method=              -- create an archive from root directory
-- /upload/*path                 -- upload a file to a path
-- /upload                       -- upload a file to root directory
+- /archive/*path?method=&selection&name=&name=...
+                                -- create an archive of some entries of a path
+- /thumbnail/*path              -- a preview of an image (JPEG, PNG, WebP
+                                   or GIF) fitting into 256x256 pixels
+- /upload/*path?progress=&conflict=
+                                -- upload a file to a path
+- /upload?progress=&conflict=   -- upload a file to root directory
+- /dav/*path                    -- the shared directory over WebDAV, for
+                                   mounting it in file managers
+- /metrics                      -- counters and gauges in the Prometheus text
+                                   format
+- /api/upload_progress id=      -- stream the progress of uploads with an id
+- /api/begin_upload path=&name=&size=&key=&conflict=
+                                -- start or resume a chunked upload, key= is
+                                   any string the client identifies the
+                                   file with
+- /api/upload_chunk             -- append a chunk (multipart: id, offset,
+                                   progress, chunk) to a chunked upload
+- /api/resume_upload id=        -- the offset to continue a chunked upload from
+- /api/login                    -- log in with username=&password=
 
 Available methods are tar, tar.gz, tar.zst, zip.
-";
+
+Available conflict policies are reject, overwrite, rename, timestamp.
+
+Errors are JSON objects like {"error":"not-found","message":"Not Found"}
+with a matching status, unless the client accepts text/html.
+
+When accounts are enabled, API clients can authenticate using HTTP Basic auth.
+"#;
 
 #[tokio::main]
 async fn main() {
@@ -68,16 +133,35 @@ async fn main() {
         qr,
         interfaces,
         allow_upload,
+        on_conflict,
+        trash_retention,
+        users,
+        rules,
+        tls,
+        limits,
+        ip_filter,
+        access_log,
     } = cli_config;
 
+    let auth = users.map(|path| {
+        Auth::load(&path, tls.is_some()).unwrap_or_else(|e| {
+            eprintln!("Failed to load users from {}: {e}", path.display());
+            process::exit(1);
+        })
+    });
+
     let app_config = AppConfig {
         target_dir: target_dir.clone(),
         allow_upload,
+        rules,
+        on_conflict,
+        trash_retention,
     };
 
     let app_state = AppState {
-        app_config: app_config.clone(),
+        app_config,
         leptos_options: leptos_options.clone(),
+        auth,
     };
 
     if let Err(e) = create_dir_all(&target_dir) {
@@ -90,25 +174,127 @@ async fn main() {
         target_dir.to_string_lossy().yellow().bold()
     );
 
+    if let Some(auth) = &app_state.auth {
+        println!(
+            "Login required, {} user(s) loaded",
+            auth.user_count().to_string().yellow().bold()
+        );
+    }
+
+    // dropping the watcher stops it, so it's kept until the server exits
+    let _watcher = watcher::start(&app_state.app_config)
+        .inspect_err(|e| warn!("Listings won't update live: {e}"))
+        .ok();
+
+    let cleanup_config = app_state.app_config.clone();
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(60 * 60));
+        loop {
+            interval.tick().await;
+            uploads::cleanup(&cleanup_config).await;
+            trash::cleanup(&cleanup_config).await;
+            shares::cleanup(&cleanup_config).await;
+            fileserv::cleanup_thumbnails(&cleanup_config).await;
+        }
+    });
+
+    let access_log = access_log.map(|config| {
+        AccessLog::open(
+            config.path.clone(),
+            config.format,
+            config.max_size,
+            config.keep,
+        )
+        .unwrap_or_else(|e| {
+            eprintln!(
+                "Failed to open the access log {}: {e}",
+                config.path.display()
+            );
+            process::exit(1);
+        })
+    });
+
+    let context_state = app_state.clone();
+
     let app = Router::new()
         .route("/", get(|| async { Redirect::to("/index") }))
         .route("/help", get(|| async { API_HELP_TEXT }))
+        .route("/metrics", get(metrics::serve_metrics))
         .route("/archive/{*path}", get(handle_archive_with_path))
         .route("/archive/", get(handle_archive_without_path))
         .route("/upload/{*path}", post(file_upload_with_path))
         .route("/upload/", post(file_upload_without_path))
-        .nest_service("/files", ServeDir::new(&target_dir))
+        .route("/files/{*path}", get(serve_file))
+        .route("/thumbnail/{*path}", get(serve_thumbnail))
+        .route("/share/{id}/download", get(serve_share).post(serve_share))
+        .merge(dav::router(&app_state.app_config))
         .leptos_routes_with_context(
             &app_state,
             routes,
-            move || provide_context(app_config.clone()),
+            move || context_state.provide_contexts(),
             move || shell(leptos_options.clone()),
         )
         .fallback(file_and_error_handler)
+        .layer(middleware::from_fn_with_state(
+            app_state.clone(),
+            require_login,
+        ))
+        .layer(middleware::from_fn(errors::render))
         .layer(DefaultBodyLimit::disable())
+        .layer(middleware::from_fn(metrics::track))
         .with_state(app_state);
 
-    let display_urls = get_display_urls(&interfaces, port);
+    let app = if limits.is_unlimited() {
+        app
+    } else {
+        app.layer(middleware::from_fn_with_state(
+            throttle::Throttle::new(limits),
+            throttle::limit,
+        ))
+    };
+
+    // before any route, so that refused clients don't get to see anything
+    let app = if ip_filter.is_open() {
+        app
+    } else {
+        app.layer(middleware::from_fn_with_state(
+            ip_filter,
+            ip_filter::enforce,
+        ))
+    };
+
+    // outermost, so that requests refused by the other layers are logged too
+    let app = match access_log {
+        Some(log) => app.layer(middleware::from_fn_with_state(log, access_log::record)),
+        None => app,
+    };
+
+    let display_hosts = get_display_hosts(&interfaces);
+
+    let tls = match tls {
+        Some(source) => {
+            let mut hosts = vec!["localhost".to_string()];
+            hosts.extend(display_hosts.iter().map(ToString::to_string));
+
+            match source.load(hosts).await {
+                Ok(tls) => Some(tls),
+                Err(e) => {
+                    error!("Failed to set up TLS: {e}");
+                    process::exit(1);
+                },
+            }
+        },
+        None => None,
+    };
+
+    let scheme = if tls.is_some() { "https" } else { "http" };
+    let display_urls = display_hosts
+        .iter()
+        .map(|addr| match addr {
+            IpAddr::V4(_) => format!("{scheme}://{addr}:{port}"),
+            IpAddr::V6(_) => format!("{scheme}://[{addr}]:{port}"),
+        })
+        .collect::<Vec<_>>();
 
     let socket_addresses = interfaces
         .iter()
@@ -131,6 +317,13 @@ async fn main() {
             .join("\n")
     );
 
+    if let Some(tls) = &tls {
+        println!(
+            "Certificate fingerprint (SHA-256):\n   {}",
+            tls.fingerprint.yellow().bold()
+        );
+    }
+
     let is_terminal = io::IsTerminal::is_terminal(&io::stdout());
 
     if qr && is_terminal {
@@ -141,11 +334,26 @@ async fn main() {
         println!("Quit by pressing CTRL-C");
     }
 
-    let start_server = |app: Router, addr: SocketAddr| async move {
-        axum_server::bind(addr)
-            .serve(app.into_make_service())
-            .await
-            .map_err(|e| format!("Failed to start server at {addr}: {e}"))
+    let rustls_config = tls.map(|tls| tls.config);
+
+    let start_server = |app: Router, addr: SocketAddr| {
+        let rustls_config = rustls_config.clone();
+        async move {
+            let result = match rustls_config {
+                Some(config) => {
+                    axum_server::bind_rustls(addr, config)
+                        .serve(app.into_make_service_with_connect_info::<SocketAddr>())
+                        .await
+                },
+                None => {
+                    axum_server::bind(addr)
+                        .serve(app.into_make_service_with_connect_info::<SocketAddr>())
+                        .await
+                },
+            };
+
+            result.map_err(|e| format!("Failed to start server at {addr}: {e}"))
+        }
     };
 
     let servers = socket_addresses
@@ -178,7 +386,7 @@ fn print_qr_codes(display_urls: &[String]) {
     }
 }
 
-fn get_display_urls(interfaces: &[IpAddr], port: u16) -> Vec<String> {
+fn get_display_hosts(interfaces: &[IpAddr]) -> Vec<IpAddr> {
     let (wildcard, mut ifaces): (Vec<IpAddr>, Vec<IpAddr>) =
         interfaces.iter().copied().partition(IpAddr::is_unspecified);
 
@@ -199,11 +407,4 @@ fn get_display_urls(interfaces: &[IpAddr], port: u16) -> Vec<String> {
     }
 
     ifaces
-        .into_iter()
-        .map(|addr| match addr {
-            IpAddr::V4(_) => format!("{addr}"),
-            IpAddr::V6(_) => format!("[{addr}]"),
-        })
-        .map(|url| format!("http://{url}:{port}"))
-        .collect::<Vec<_>>()
 }
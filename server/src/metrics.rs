@@ -0,0 +1,43 @@
+use axum::{
+    extract::Request,
+    http::header,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use file_share_app::metrics::{self, FILE_BYTES, InFlight};
+
+use crate::counted_body::counted;
+
+/// Counts error responses and the transfers in flight, and the bytes sent by
+/// `/files`.
+pub async fn track(request: Request, next: Next) -> Response {
+    let in_flight = InFlight::start();
+    let serves_file = request.uri().path().starts_with("/files/");
+
+    let response = next.run(request).await;
+    let status = response.status();
+
+    if status.is_client_error() || status.is_server_error() {
+        metrics::count_error(status.as_u16());
+    }
+
+    let serves_file = serves_file && status.is_success();
+
+    response.map(|body| {
+        counted(body, move |bytes| {
+            drop(in_flight);
+
+            if serves_file {
+                metrics::add(&FILE_BYTES, bytes);
+            }
+        })
+    })
+}
+
+/// Serves the metrics in the Prometheus text format.
+pub async fn serve_metrics() -> impl IntoResponse {
+    (
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        metrics::render().await,
+    )
+}
@@ -0,0 +1,225 @@
+use std::{
+    collections::HashMap,
+    future::Future,
+    net::{IpAddr, SocketAddr},
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use axum::{
+    body::{Body, Bytes, HttpBody},
+    extract::{ConnectInfo, Request, State},
+    middleware::Next,
+    response::Response,
+};
+use http_body::{Frame, SizeHint};
+use tokio::time::{Instant, Sleep, sleep};
+
+/// Bandwidth caps in bytes per second, `None` is unlimited.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Limits {
+    /// Shared by all downloads
+    pub download: Option<u64>,
+    /// Shared by all uploads
+    pub upload: Option<u64>,
+    /// For the downloads of each client
+    pub client_download: Option<u64>,
+    /// For the uploads of each client
+    pub client_upload: Option<u64>,
+}
+
+impl Limits {
+    pub fn is_unlimited(&self) -> bool {
+        self.download.is_none()
+            && self.upload.is_none()
+            && self.client_download.is_none()
+            && self.client_upload.is_none()
+    }
+}
+
+/// Hands out the bandwidth of `rate` bytes per second to the transfers that
+/// share it, in the order they ask for it.
+struct RateLimiter {
+    rate: u64,
+    /// When everything sent so far would have been sent at `rate`
+    next: Mutex<Instant>,
+}
+
+impl RateLimiter {
+    fn new(rate: u64) -> Self {
+        Self {
+            rate: rate.max(1),
+            next: Mutex::new(Instant::now()),
+        }
+    }
+
+    /// Account for `bytes` that are being transferred, returning how long to
+    /// wait before the next ones.
+    #[allow(clippy::cast_precision_loss)] // a rounded rate is good enough
+    fn reserve(&self, bytes: usize) -> Duration {
+        let now = Instant::now();
+        let Ok(mut next) = self.next.lock() else {
+            return Duration::ZERO;
+        };
+
+        // unused bandwidth of idle periods isn't saved up
+        let start = (*next).max(now);
+        *next = start + Duration::from_secs_f64(bytes as f64 / self.rate as f64);
+
+        next.saturating_duration_since(now)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Direction {
+    Download,
+    Upload,
+}
+
+/// The limiters of the whole server and of each client.
+#[derive(Clone)]
+pub struct Throttle {
+    limits: Limits,
+    download: Option<Arc<RateLimiter>>,
+    upload: Option<Arc<RateLimiter>>,
+    clients: Arc<Mutex<HashMap<(IpAddr, Direction), Arc<RateLimiter>>>>,
+}
+
+impl Throttle {
+    pub fn new(limits: Limits) -> Self {
+        Self {
+            limits,
+            download: limits.download.map(|rate| Arc::new(RateLimiter::new(rate))),
+            upload: limits.upload.map(|rate| Arc::new(RateLimiter::new(rate))),
+            clients: Arc::default(),
+        }
+    }
+
+    /// The limiters a transfer in `direction` by `client` has to respect.
+    fn limiters(&self, direction: Direction, client: Option<IpAddr>) -> Vec<Arc<RateLimiter>> {
+        let (shared, client_rate) = match direction {
+            Direction::Download => (&self.download, self.limits.client_download),
+            Direction::Upload => (&self.upload, self.limits.client_upload),
+        };
+
+        let mut limiters = shared.iter().cloned().collect::<Vec<_>>();
+
+        if let (Some(rate), Some(client)) = (client_rate, client) {
+            if let Ok(mut clients) = self.clients.lock() {
+                // forget the clients without transfers in progress
+                clients.retain(|_, limiter| Arc::strong_count(limiter) > 1);
+
+                let limiter = clients
+                    .entry((client, direction))
+                    .or_insert_with(|| Arc::new(RateLimiter::new(rate)));
+                limiters.push(limiter.clone());
+            }
+        }
+
+        limiters
+    }
+
+    fn wrap(&self, body: Body, direction: Direction, client: Option<IpAddr>) -> Body {
+        let limiters = self.limiters(direction, client);
+
+        if limiters.is_empty() {
+            body
+        } else {
+            Body::new(ThrottledBody {
+                inner: body,
+                limiters,
+                delay: None,
+            })
+        }
+    }
+}
+
+/// A body that is polled only as fast as its limiters allow, which slows
+/// down the client through TCP flow control.
+struct ThrottledBody {
+    inner: Body,
+    limiters: Vec<Arc<RateLimiter>>,
+    delay: Option<Pin<Box<Sleep>>>,
+}
+
+impl HttpBody for ThrottledBody {
+    type Data = Bytes;
+    type Error = axum::Error;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        if let Some(delay) = &mut self.delay {
+            if delay.as_mut().poll(cx).is_pending() {
+                return Poll::Pending;
+            }
+            self.delay = None;
+        }
+
+        let poll = Pin::new(&mut self.inner).poll_frame(cx);
+
+        if let Poll::Ready(Some(Ok(frame))) = &poll {
+            if let Some(data) = frame.data_ref() {
+                let wait = self
+                    .limiters
+                    .iter()
+                    .map(|limiter| limiter.reserve(data.len()))
+                    .max()
+                    .unwrap_or_default();
+
+                if !wait.is_zero() {
+                    self.delay = Some(Box::pin(sleep(wait)));
+                }
+            }
+        }
+
+        poll
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+/// The direction of the transfers of `path` that are limited.
+fn limited(path: &str) -> (bool, bool) {
+    let download = ["/files/", "/archive/", "/share/", "/dav"]
+        .iter()
+        .any(|prefix| path.starts_with(prefix));
+    let upload =
+        path.starts_with("/upload/") || path.starts_with("/dav") || path == "/api/upload_chunk";
+
+    (download, upload)
+}
+
+/// Limits the bandwidth of downloads and uploads, by wrapping the bodies of
+/// their responses and requests.
+pub async fn limit(State(throttle): State<Throttle>, request: Request, next: Next) -> Response {
+    let client = request
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| addr.ip());
+
+    let (download, upload) = limited(request.uri().path());
+
+    let request = if upload {
+        request.map(|body| throttle.wrap(body, Direction::Upload, client))
+    } else {
+        request
+    };
+
+    let response = next.run(request).await;
+
+    if download {
+        response.map(|body| throttle.wrap(body, Direction::Download, client))
+    } else {
+        response
+    }
+}
@@ -0,0 +1,156 @@
+use std::{
+    fmt::Write as _,
+    io,
+    path::{Path, PathBuf},
+};
+
+use axum_server::tls_rustls::RustlsConfig;
+use rustls_pki_types::{CertificateDer, pem::PemObject as _};
+use sha2::{Digest as _, Sha256};
+use thiserror::Error as ThisError;
+use tokio::{
+    fs::{self, OpenOptions},
+    io::AsyncWriteExt as _,
+};
+
+#[derive(Debug, ThisError)]
+pub enum Error {
+    /// Reading or writing the certificate files failed
+    #[error("{0}\ncaused by: {1}")]
+    Io(String, io::Error),
+
+    /// The certificate file doesn't contain a PEM encoded certificate
+    #[error("Invalid certificate in {0}\ncaused by: {1}")]
+    InvalidCertificate(String, rustls_pki_types::pem::Error),
+
+    /// Generating the self-signed certificate failed
+    #[error("Failed to generate a self-signed certificate\ncaused by: {0}")]
+    Generate(#[from] rcgen::Error),
+
+    /// There is no directory to cache the self-signed certificate in
+    #[error("Couldn't find a directory to store the self-signed certificate in")]
+    NoCacheDir,
+}
+
+/// Where the certificate comes from.
+#[derive(Debug, Clone)]
+pub enum TlsSource {
+    /// PEM encoded certificate chain and private key supplied by the user
+    Files { cert: PathBuf, key: PathBuf },
+    /// Certificate generated on the first run and reused afterwards
+    SelfSigned,
+}
+
+/// Loaded TLS configuration for the listeners.
+pub struct Tls {
+    pub config: RustlsConfig,
+    /// SHA-256 fingerprint of the certificate, so clients can verify it
+    pub fingerprint: String,
+}
+
+impl TlsSource {
+    /// Load the certificate, generating it first if needed.
+    ///
+    /// `hosts` are the names and addresses included in a newly generated
+    /// certificate.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the files can't be read or written or don't
+    /// contain a valid certificate and key.
+    pub async fn load(&self, hosts: Vec<String>) -> Result<Tls, Error> {
+        match self {
+            TlsSource::Files { cert, key } => load_pem_files(cert, key).await,
+            TlsSource::SelfSigned => {
+                let dir = dirs::config_dir()
+                    .ok_or(Error::NoCacheDir)?
+                    .join("file-share");
+
+                let cert = dir.join("cert.pem");
+                let key = dir.join("key.pem");
+
+                if !fs::try_exists(&cert).await.unwrap_or(false) {
+                    generate_self_signed(&dir, &cert, &key, hosts).await?;
+                }
+
+                load_pem_files(&cert, &key).await
+            },
+        }
+    }
+}
+
+async fn load_pem_files(cert: &Path, key: &Path) -> Result<Tls, Error> {
+    let der = CertificateDer::from_pem_file(cert)
+        .map_err(|e| Error::InvalidCertificate(cert.display().to_string(), e))?;
+
+    let config = RustlsConfig::from_pem_file(cert, key).await.map_err(|e| {
+        Error::Io(
+            format!(
+                "Failed to load the certificate {} with key {}",
+                cert.display(),
+                key.display()
+            ),
+            e,
+        )
+    })?;
+
+    Ok(Tls {
+        config,
+        fingerprint: fingerprint(&der),
+    })
+}
+
+async fn generate_self_signed(
+    dir: &Path,
+    cert: &Path,
+    key: &Path,
+    hosts: Vec<String>,
+) -> Result<(), Error> {
+    let certified = rcgen::generate_simple_self_signed(hosts)?;
+
+    fs::create_dir_all(dir)
+        .await
+        .map_err(|e| Error::Io(format!("Failed to create {}", dir.display()), e))?;
+
+    // left over when writing the certificate failed
+    match fs::remove_file(key).await {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => {
+            return Err(Error::Io(format!("Failed to remove {}", key.display()), e));
+        },
+        _ => {},
+    }
+
+    let write_error = |e| Error::Io(format!("Failed to write {}", key.display()), e);
+
+    // only readable by the owner from the start
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(target_family = "unix")]
+    options.mode(0o600);
+
+    let mut file = options.open(key).await.map_err(write_error)?;
+    file.write_all(certified.signing_key.serialize_pem().as_bytes())
+        .await
+        .map_err(write_error)?;
+    file.flush().await.map_err(write_error)?;
+
+    // written last, its existence marks a complete certificate
+    fs::write(cert, certified.cert.pem())
+        .await
+        .map_err(|e| Error::Io(format!("Failed to write {}", cert.display()), e))?;
+
+    Ok(())
+}
+
+/// Format the SHA-256 digest of a certificate as colon separated hex pairs.
+fn fingerprint(cert: &CertificateDer) -> String {
+    Sha256::digest(cert)
+        .iter()
+        .fold(String::new(), |mut out, byte| {
+            if !out.is_empty() {
+                out.push(':');
+            }
+            let _ = write!(out, "{byte:02X}");
+            out
+        })
+}